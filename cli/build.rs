@@ -0,0 +1,105 @@
+//! Generates the byte offsets of the Pye accounts' fields from the program's IDL, so the
+//! `getProgramAccounts` filters follow the account layouts.
+
+use std::{env, fs, path::Path};
+
+use serde_json::Value;
+
+const IDL_PATH: &str = "../lib/idls/pye_core.json";
+
+/// Accounts whose layouts are generated.
+const ACCOUNTS: [&str; 1] = ["SoloValidatorBond"];
+
+/// Size of the discriminator Anchor prepends to account data.
+const DISCRIMINATOR_SIZE: usize = 8;
+
+fn main() {
+    println!("cargo:rerun-if-changed={}", IDL_PATH);
+    let idl: Value = serde_json::from_str(
+        &fs::read_to_string(IDL_PATH).unwrap_or_else(|e| panic!("reading {}: {}", IDL_PATH, e)),
+    )
+    .unwrap_or_else(|e| panic!("parsing {}: {}", IDL_PATH, e));
+    let types = idl["types"].as_array().expect("IDL without types");
+
+    let mut out = format!("// Generated by build.rs from {}.\n", IDL_PATH);
+    for account in ACCOUNTS {
+        out.push_str(&account_layout(types, account));
+    }
+    let path = Path::new(&env::var("OUT_DIR").unwrap()).join("layout.rs");
+    fs::write(path, out).unwrap();
+}
+
+/// Declares a module of offset constants for the account's fields, up to its first field with a
+/// variable size (after which offsets are not fixed).
+fn account_layout(types: &[Value], account: &str) -> String {
+    let mut out = format!(
+        "\n/// Offsets of the fixed-offset fields of `{}` in its account data, discriminator \
+         included.\npub mod {} {{\n",
+        account,
+        snake_case(account)
+    );
+    let mut offset = DISCRIMINATOR_SIZE;
+    for field in struct_fields(types, account) {
+        let name = field["name"].as_str().unwrap();
+        if !name.starts_with('_') {
+            out.push_str(&format!(
+                "    pub const {}: usize = {};\n",
+                name.to_uppercase(),
+                offset
+            ));
+        }
+        match type_size(types, &field["type"]) {
+            Some(size) => offset += size,
+            None => break,
+        }
+    }
+    out.push_str("}\n");
+    out
+}
+
+fn struct_fields<'a>(types: &'a [Value], name: &str) -> &'a [Value] {
+    let ty = types
+        .iter()
+        .find(|ty| ty["name"] == name)
+        .unwrap_or_else(|| panic!("type {} not found in the IDL", name));
+    assert_eq!(ty["type"]["kind"], "struct", "{} is not a struct", name);
+    ty["type"]["fields"].as_array().unwrap()
+}
+
+/// Borsh size of an IDL type, or `None` when it varies (options, vectors, strings).
+fn type_size(types: &[Value], ty: &Value) -> Option<usize> {
+    if let Some(name) = ty.as_str() {
+        return match name {
+            "bool" | "u8" | "i8" => Some(1),
+            "u16" | "i16" => Some(2),
+            "u32" | "i32" | "f32" => Some(4),
+            "u64" | "i64" | "f64" => Some(8),
+            "u128" | "i128" => Some(16),
+            "pubkey" => Some(32),
+            "string" | "bytes" => None,
+            _ => panic!("unknown IDL type {}", name),
+        };
+    }
+    if let Some(array) = ty["array"].as_array() {
+        let len = array[1].as_u64().expect("array without a fixed length") as usize;
+        return type_size(types, &array[0]).map(|size| size * len);
+    }
+    if let Some(name) = ty["defined"]["name"].as_str() {
+        return struct_fields(types, name)
+            .iter()
+            .map(|field| type_size(types, &field["type"]))
+            .sum();
+    }
+    None
+}
+
+fn snake_case(name: &str) -> String {
+    let mut out = String::new();
+    for (i, c) in name.chars().enumerate() {
+        if c.is_uppercase() && i > 0 {
+            out.push('_');
+        }
+        out.push(c.to_ascii_lowercase());
+    }
+    out
+}
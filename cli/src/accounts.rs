@@ -1,45 +1,52 @@
-use anchor_lang::{AccountDeserialize, Discriminator};
+use anchor_lang::AccountDeserialize;
 use anyhow::{anyhow, Error};
-use log::info;
+use log::{info, warn};
 use pye_core_cpi::pye_core::accounts::SoloValidatorBond;
 use solana_account_decoder_client_types::UiAccountEncoding;
 use solana_client::nonblocking::rpc_client::RpcClient;
 use solana_client::rpc_config::{RpcAccountInfoConfig, RpcProgramAccountsConfig};
-use solana_client::rpc_filter::{Memcmp, MemcmpEncodedBytes, RpcFilterType};
 use solana_sdk::account::from_account;
 use solana_sdk::pubkey::Pubkey;
 use solana_sdk::stake_history::StakeHistory;
 use solana_sdk::sysvar::{slot_history, stake_history};
 
-pub async fn fetch_stake_history(client: &RpcClient) -> Result<StakeHistory, Error> {
+use crate::layout::BondFilters;
+use crate::providers::ChainDataProvider;
+
+pub async fn fetch_stake_history(client: &dyn ChainDataProvider) -> Result<StakeHistory, Error> {
     let account_data = client
         .get_account(&stake_history::ID)
         .await
-        .map_err(|e| anyhow!("Failed to fetch StakeHistory: {}", e))?;
+        .map_err(|e| anyhow!("Failed to fetch StakeHistory: {}", e))?
+        .ok_or_else(|| anyhow!("StakeHistory sysvar not found"))?;
     let stake_history: StakeHistory = from_account::<StakeHistory, _>(&account_data)
         .ok_or_else(|| anyhow!("Failed to deserialize StakeHistory"))?;
     Ok(stake_history)
 }
 
-pub async fn fetch_slot_history(client: &RpcClient) -> Result<slot_history::SlotHistory, Error> {
+pub async fn fetch_slot_history(
+    client: &dyn ChainDataProvider,
+) -> Result<slot_history::SlotHistory, Error> {
     let account_data = client
         .get_account(&slot_history::ID)
         .await
-        .map_err(|e| anyhow!("Failed to fetch SlotHistory: {}", e))?;
+        .map_err(|e| anyhow!("Failed to fetch SlotHistory: {}", e))?
+        .ok_or_else(|| anyhow!("SlotHistory sysvar not found"))?;
     let slot_history = from_account::<slot_history::SlotHistory, _>(&account_data)
         .ok_or_else(|| anyhow!("Failed to deserialize SlotHistory"))?;
     Ok(slot_history)
 }
 
 pub async fn fetch_solo_validator_bond(
-    client: &RpcClient,
+    client: &dyn ChainDataProvider,
     bond_pubkey: &Pubkey,
 ) -> Result<SoloValidatorBond, Error> {
-    let account_data = client
-        .get_account_data(&bond_pubkey)
+    let account = client
+        .get_account(bond_pubkey)
         .await
-        .map_err(|e| anyhow!("Failed to fetch SoloValidatorBond: {}", e))?;
-    let bond = SoloValidatorBond::try_deserialize(&mut account_data.as_slice())
+        .map_err(|e| anyhow!("Failed to fetch SoloValidatorBond: {}", e))?
+        .ok_or_else(|| anyhow!("SoloValidatorBond {} not found", bond_pubkey))?;
+    let bond = SoloValidatorBond::try_deserialize(&mut account.data.as_slice())
         .map_err(|e| anyhow!("Failed to deserialize SoloValidatorBond: {}", e))?;
     Ok(bond)
 }
@@ -50,26 +57,79 @@ pub async fn fetch_active_solo_validator_bonds_by_vote_key_and_issuer(
     vote_pubkey: &Pubkey,
     issuer_pubkey: &Pubkey,
 ) -> Result<Vec<(Pubkey, SoloValidatorBond)>, Error> {
-    let discriminator_filter = RpcFilterType::Memcmp(Memcmp::new_base58_encoded(
-        0,
-        SoloValidatorBond::DISCRIMINATOR,
-    ));
-    let vote_pubkey_filter = RpcFilterType::Memcmp(Memcmp::new(
-        8,
-        MemcmpEncodedBytes::Base58(vote_pubkey.to_string()),
-    ));
-    let issuer_pubkey_filter = RpcFilterType::Memcmp(Memcmp::new(
-        240,
-        MemcmpEncodedBytes::Base58(issuer_pubkey.to_string()),
-    ));
-    let not_matured_filter = RpcFilterType::Memcmp(Memcmp::new_base58_encoded(185, &[0]));
+    let filters = BondFilters::new()
+        .vote_account(vote_pubkey)
+        .active()
+        .issuer(issuer_pubkey);
+    let accounts = fetch_solo_validator_bonds(client, program_id, filters).await?;
+    info!(
+        "Fetched {} active bonds for issuer {}",
+        accounts.len(),
+        issuer_pubkey
+    );
+    Ok(accounts)
+}
+
+/// Fetches every SoloValidatorBond of the issuer pointed at the vote account, including matured
+/// ones.
+pub async fn fetch_solo_validator_bonds_by_vote_key_and_issuer(
+    client: &RpcClient,
+    program_id: &Pubkey,
+    vote_pubkey: &Pubkey,
+    issuer_pubkey: &Pubkey,
+) -> Result<Vec<(Pubkey, SoloValidatorBond)>, Error> {
+    let filters = BondFilters::new()
+        .vote_account(vote_pubkey)
+        .issuer(issuer_pubkey);
+    let accounts = fetch_solo_validator_bonds(client, program_id, filters).await?;
+    info!(
+        "Fetched {} bonds for issuer {}",
+        accounts.len(),
+        issuer_pubkey
+    );
+    Ok(accounts)
+}
+
+/// Fetches the active SoloValidatorBonds pointed at the vote account, whatever their issuer.
+pub async fn fetch_active_solo_validator_bonds_by_vote_key(
+    client: &RpcClient,
+    program_id: &Pubkey,
+    vote_pubkey: &Pubkey,
+) -> Result<Vec<(Pubkey, SoloValidatorBond)>, Error> {
+    let filters = BondFilters::new().vote_account(vote_pubkey).active();
+    let accounts = fetch_solo_validator_bonds(client, program_id, filters).await?;
+    info!(
+        "Fetched {} active bonds for vote account {}",
+        accounts.len(),
+        vote_pubkey
+    );
+    Ok(accounts)
+}
+
+/// Fetches every SoloValidatorBond pointed at the vote account, including matured ones.
+pub async fn fetch_solo_validator_bonds_by_vote_key(
+    client: &RpcClient,
+    program_id: &Pubkey,
+    vote_pubkey: &Pubkey,
+) -> Result<Vec<(Pubkey, SoloValidatorBond)>, Error> {
+    let filters = BondFilters::new().vote_account(vote_pubkey);
+    let accounts = fetch_solo_validator_bonds(client, program_id, filters).await?;
+    info!(
+        "Fetched {} bonds for vote account {}",
+        accounts.len(),
+        vote_pubkey
+    );
+    Ok(accounts)
+}
+
+/// Fetches the SoloValidatorBonds matching `filters`.
+pub async fn fetch_solo_validator_bonds(
+    client: &RpcClient,
+    program_id: &Pubkey,
+    filters: BondFilters,
+) -> Result<Vec<(Pubkey, SoloValidatorBond)>, Error> {
     let config = RpcProgramAccountsConfig {
-        filters: Some(vec![
-            discriminator_filter,
-            vote_pubkey_filter,
-            not_matured_filter,
-            issuer_pubkey_filter,
-        ]),
+        filters: Some(filters.build()),
         account_config: RpcAccountInfoConfig {
             encoding: Some(UiAccountEncoding::Base64Zstd),
             data_slice: None,
@@ -83,18 +143,20 @@ pub async fn fetch_active_solo_validator_bonds_by_vote_key_and_issuer(
         .get_program_accounts_with_config(program_id, config)
         .await
         .map_err(|e| anyhow!("Failed to fetch SoloValidatorBond: {}", e))?;
-    info!(
-        "Fetched {} active bonds for issuer {}",
-        accounts.len(),
-        issuer_pubkey
-    );
 
+    // Accounts matching the filters with a layout that does not decode, e.g. from another
+    // program version, are skipped rather than failing the whole fetch.
     Ok(accounts
         .into_iter()
-        .map(|(pubkey, account)| {
+        .filter_map(|(pubkey, account)| {
             let mut data: &[u8] = &account.data;
-            let bond = SoloValidatorBond::try_deserialize(&mut data).unwrap();
-            (pubkey, bond)
+            match SoloValidatorBond::try_deserialize(&mut data) {
+                Ok(bond) => Some((pubkey, bond)),
+                Err(e) => {
+                    warn!("Skipping SoloValidatorBond {}: {}", pubkey, e);
+                    None
+                }
+            }
         })
         .collect())
 }
@@ -1,14 +1,22 @@
+use std::collections::HashMap;
+
 use anyhow::{anyhow, Result};
 use log::{info, warn};
-use regex::Regex;
-use solana_client::nonblocking::rpc_client::RpcClient;
-use solana_client::rpc_request::RpcError;
-use solana_client::rpc_response::StakeActivationState;
+use solana_client::rpc_response::{RpcInflationReward, StakeActivationState};
 use solana_sdk::account::{Account, ReadableAccount};
 use solana_sdk::pubkey::Pubkey;
 use solana_sdk::stake::state::StakeStateV2;
 use solana_stake_program::stake_state::StakeActivationStatus;
 
+use crate::providers::ChainDataProvider;
+
+/// Maximum number of addresses sent in a single getInflationReward request.
+const INFLATION_REWARD_BATCH_SIZE: usize = 100;
+/// Largest shortfall, in basis points, of the inflation rewards paid to the stake accounts found
+/// against the stakers' share of the validator's rewards, before stake accounts are deemed
+/// missing.
+const MAX_STAKERS_REWARD_SHORTFALL_BPS: u128 = 10;
+
 #[derive(Debug)]
 pub struct StakeActivation {
     pub state: StakeActivationState,
@@ -17,7 +25,7 @@ pub struct StakeActivation {
 }
 
 async fn fetch_stake_for_epoch(
-    client: &RpcClient,
+    client: &dyn ChainDataProvider,
     stake_account: &Account,
     stake_state: &StakeStateV2,
     target_epoch: u64,
@@ -56,64 +64,57 @@ async fn fetch_stake_for_epoch(
     })
 }
 
-pub async fn fetch_bond_active_stake(
-    client: &RpcClient,
-    stake_account_key: &Pubkey,
-    transient_stake_account_key: &Pubkey,
+/// Inflation rewards credited to a stake account since `target_epoch`.
+#[derive(Debug, Default)]
+struct RewardsSince {
+    /// Sum of the rewards credited from `target_epoch` on.
+    total: u64,
+    /// The reward for `target_epoch` and the account's balance after it was credited.
+    target_epoch_reward: (u64, u64),
+}
+
+/// Fetches the inflation rewards credited to `addresses` from `target_epoch` to the last
+/// completed epoch, with a single request per epoch for all of them.
+async fn fetch_rewards_since(
+    client: &dyn ChainDataProvider,
+    addresses: &[Pubkey],
     target_epoch: u64,
     current_epoch: u64,
-) -> Result<u64> {
-    if target_epoch != current_epoch - 1 {
-        return Err(anyhow!("Unsupported target epoch delta"));
-    }
-    let maybe_stake_account = &client.get_account(stake_account_key).await;
-    let stake_account = match maybe_stake_account {
-        Ok(account) => account,
-        Err(err) => match &err.kind {
-            solana_client::client_error::ClientErrorKind::RpcError(rpc_error) => match rpc_error {
-                RpcError::RpcResponseError {
-                    code: _,
-                    message,
-                    data: _,
-                } => {
-                    // SoloValidatorBond's initialize stake accounts on the first deposit. So in
-                    // the case where a bond was created, but no deposits were made, the RPC will
-                    // error with account not found. In this case, we short circuit and return 0
-                    // as the active stake.
-                    let account_not_found_match = Regex::new(r"^AccountNotFound").unwrap();
-                    if account_not_found_match.is_match(&message) {
-                        warn!("Fetch stake account {} error {:?}", stake_account_key, err);
-                        return Ok(0);
-                    } else {
-                        return Err(anyhow!("Failed to fetch StakeAccount: {}", err));
-                    }
+) -> Result<Vec<RewardsSince>> {
+    let mut rewards_since: Vec<RewardsSince> =
+        addresses.iter().map(|_| RewardsSince::default()).collect();
+    for epoch in target_epoch..current_epoch {
+        let rewards = client.get_inflation_reward(addresses, epoch).await?;
+        for (since, reward) in rewards_since.iter_mut().zip(rewards) {
+            if let Some(reward) = reward {
+                since.total += reward.amount;
+                if epoch == target_epoch {
+                    since.target_epoch_reward = (reward.amount, reward.post_balance);
                 }
-                _ => {
-                    return Err(anyhow!("Failed to fetch StakeAccount: {}", err));
-                }
-            },
-            _ => {
-                return Err(anyhow!("Failed to fetch StakeAccount: {}", err));
             }
-        },
-    };
+        }
+    }
+    Ok(rewards_since)
+}
+
+/// Reconstructs the active stake of a single stake account during `target_epoch`.
+///
+/// The delegation's current stake includes every inflation reward credited since
+/// `target_epoch`, so those rewards are subtracted from the effective stake at `target_epoch`.
+async fn fetch_active_stake_at_epoch(
+    client: &dyn ChainDataProvider,
+    stake_account_key: &Pubkey,
+    stake_account: &Account,
+    rewards_since: &RewardsSince,
+    target_epoch: u64,
+) -> Result<u64> {
     let stake_state = &stake_account.deserialize_data::<StakeStateV2>()?;
-    // Fetch inflation rewards for the target epoch
-    let maybe_inflation_rewards = &client
-        .get_inflation_reward(&[*stake_account_key], Some(target_epoch))
-        .await?;
-    let (inflation_rewards, post_balance) = maybe_inflation_rewards[0]
-        .as_ref()
-        .map(|x| (x.amount, x.post_balance))
-        .unwrap_or((0, 0));
-    let active_stake_for_current_epoch =
+    let (inflation_rewards, post_balance) = rewards_since.target_epoch_reward;
+    let activation =
         fetch_stake_for_epoch(client, stake_account, stake_state, target_epoch).await?;
-    info!(
-        "Current Stake Account: {:?}",
-        active_stake_for_current_epoch
-    );
-    let mut bond_active_stake = if active_stake_for_current_epoch.active >= inflation_rewards {
-        active_stake_for_current_epoch.active - inflation_rewards
+    info!("Stake Account {}: {:?}", stake_account_key, activation);
+    let active_stake = if activation.active >= rewards_since.total {
+        activation.active - rewards_since.total
     } else {
         //  If the account was decativated or merged in, then the current active amount can be 0.
         //  This is used to determine the base MEV earned by the bond (since we only have total
@@ -121,43 +122,85 @@ pub async fn fetch_bond_active_stake(
         //  because it includes additional lamports than it makes
         //.    A) makes base and expected MEV look higher, which should be proportional and net out.
         //.    B) makes expected block rewards highe, which is in favor of the stakers
-        post_balance - inflation_rewards
+        post_balance.saturating_sub(inflation_rewards)
+    };
+    Ok(active_stake)
+}
+
+/// Fetches the SoloValidatorBond's active stake during `target_epoch`, which can be any
+/// completed epoch still covered by the StakeHistory sysvar.
+pub async fn fetch_bond_active_stake(
+    client: &dyn ChainDataProvider,
+    stake_account_key: &Pubkey,
+    transient_stake_account_key: &Pubkey,
+    target_epoch: u64,
+    current_epoch: u64,
+) -> Result<u64> {
+    if target_epoch >= current_epoch {
+        return Err(anyhow!(
+            "Target epoch {} has not completed yet",
+            target_epoch
+        ));
+    }
+    let stake_account = match client
+        .get_account(stake_account_key)
+        .await
+        .map_err(|e| anyhow!("Failed to fetch StakeAccount: {}", e))?
+    {
+        Some(account) => account,
+        None => {
+            // SoloValidatorBond's initialize stake accounts on the first deposit. So in the case
+            // where a bond was created, but no deposits were made, the account does not exist.
+            // In this case, we short circuit and return 0 as the active stake.
+            warn!("Stake account {} not found", stake_account_key);
+            return Ok(0);
+        }
     };
+    let transient_account = if !transient_stake_account_key.eq(&Pubkey::default()) {
+        Some(
+            client
+                .get_account(transient_stake_account_key)
+                .await
+                .map_err(|e| anyhow!("Failed to fetch Transient StakeAccount: {}", e))?
+                .ok_or_else(|| {
+                    anyhow!(
+                        "Transient StakeAccount {} not found",
+                        transient_stake_account_key
+                    )
+                })?,
+        )
+    } else {
+        None
+    };
+    let addresses: Vec<Pubkey> = match transient_account {
+        Some(_) => vec![*stake_account_key, *transient_stake_account_key],
+        None => vec![*stake_account_key],
+    };
+    let rewards_since =
+        fetch_rewards_since(client, &addresses, target_epoch, current_epoch).await?;
+
+    let mut bond_active_stake = fetch_active_stake_at_epoch(
+        client,
+        stake_account_key,
+        &stake_account,
+        &rewards_since[0],
+        target_epoch,
+    )
+    .await?;
     info!(
         "Active stake for epoch {}: {}",
         target_epoch, bond_active_stake
     );
 
-    if !transient_stake_account_key.eq(&Pubkey::default()) {
-        let transient_account = &client
-            .get_account(&transient_stake_account_key)
-            .await
-            .map_err(|e| anyhow!("Failed to fetch Transient StakeAccount: {}", e))?;
-        let transient_state = &transient_account.deserialize_data::<StakeStateV2>()?;
-        let transient_amount =
-            fetch_stake_for_epoch(client, transient_account, transient_state, target_epoch).await?;
-        let maybe_inflation_rewards = &client
-            .get_inflation_reward(&[*stake_account_key], Some(target_epoch))
-            .await?;
-        let (inflation_rewards, post_balance) = maybe_inflation_rewards[0]
-            .as_ref()
-            .map(|x| (x.amount, x.post_balance))
-            .unwrap_or((0, 0));
-        let transient_stake_at_target_epoch = if transient_amount.active >= inflation_rewards {
-            transient_amount.active - inflation_rewards
-        } else {
-            //  If the account was decativated or merged in, then the current active amount can be 0.
-            //  This is used to determine the base MEV earned by the bond (since we only have total
-            //  for the validator), the max MEV, and the max block rewards. If this number is higher
-            //  because it includes additional lamports than it makes
-            //.    A) makes base and expected MEV look higher, which should be proportional and net out.
-            //.    B) makes expected block rewards highe, which is in favor of the stakers
-            post_balance - inflation_rewards
-        };
-        info!(
-            "Transient Stake Account: {:?}",
-            transient_stake_at_target_epoch
-        );
+    if let Some(transient_account) = &transient_account {
+        let transient_stake_at_target_epoch = fetch_active_stake_at_epoch(
+            client,
+            transient_stake_account_key,
+            transient_account,
+            &rewards_since[1],
+            target_epoch,
+        )
+        .await?;
         info!(
             "Transient active stake for epoch {}: {}",
             target_epoch, transient_stake_at_target_epoch
@@ -168,3 +211,113 @@ pub async fn fetch_bond_active_stake(
     info!("Total Bond Active Stake: {}\n", bond_active_stake);
     Ok(bond_active_stake)
 }
+
+/// Reconstructs the validator's active stake during `target_epoch` from the stake accounts
+/// currently delegated to it and the StakeHistory sysvar, netting out inflation rewards credited
+/// since. Stake accounts closed or redelegated since `target_epoch` are no longer visible, so
+/// the inflation rewards paid to the accounts found for `target_epoch` are checked against the
+/// stakers' share of the validator's rewards, and the reconstruction fails if they fall short.
+pub async fn fetch_validator_active_stake(
+    client: &dyn ChainDataProvider,
+    vote_pubkey: &Pubkey,
+    target_epoch: u64,
+    current_epoch: u64,
+) -> Result<u64> {
+    if target_epoch >= current_epoch {
+        return Err(anyhow!(
+            "Target epoch {} has not completed yet",
+            target_epoch
+        ));
+    }
+    let stake_history = crate::accounts::fetch_stake_history(client).await?;
+    let mut effective_stakes = HashMap::new();
+    for (pubkey, account) in client.get_stake_accounts(vote_pubkey).await? {
+        let Ok(stake_state) = account.deserialize_data::<StakeStateV2>() else {
+            continue;
+        };
+        let Some(delegation) = stake_state.delegation() else {
+            continue;
+        };
+        let effective = delegation
+            .stake_activating_and_deactivating(target_epoch, &stake_history, None)
+            .effective;
+        if effective > 0 {
+            effective_stakes.insert(pubkey, effective);
+        }
+    }
+
+    let stake_accounts: Vec<Pubkey> = effective_stakes.keys().copied().collect();
+    let mut stakers_reward = 0u64;
+    for epoch in target_epoch..current_epoch {
+        for batch in stake_accounts.chunks(INFLATION_REWARD_BATCH_SIZE) {
+            let rewards = client.get_inflation_reward(batch, epoch).await?;
+            for (pubkey, reward) in batch.iter().zip(rewards) {
+                if let (Some(reward), Some(effective)) = (reward, effective_stakes.get_mut(pubkey))
+                {
+                    *effective = effective.saturating_sub(reward.amount);
+                    if epoch == target_epoch {
+                        stakers_reward += reward.amount;
+                    }
+                }
+            }
+        }
+    }
+    let vote_reward = client
+        .get_inflation_reward(&[*vote_pubkey], target_epoch)
+        .await?
+        .pop()
+        .flatten();
+    check_stakers_reward(
+        vote_pubkey,
+        target_epoch,
+        stakers_reward,
+        vote_reward.as_ref(),
+    )?;
+
+    let validator_active_stake = effective_stakes.values().sum();
+    info!(
+        "Validator {} active stake for epoch {} from {} stake accounts: {}",
+        vote_pubkey,
+        target_epoch,
+        effective_stakes.len(),
+        validator_active_stake
+    );
+    Ok(validator_active_stake)
+}
+
+/// Fails if `stakers_reward`, the inflation rewards paid to the stake accounts found for
+/// `target_epoch`, falls short of the stakers' share of the validator's rewards implied by its
+/// commission's reward, `vote_reward`: stake accounts delegated then were closed or redelegated
+/// since. This cannot be told with a commission of 0 or 100%.
+fn check_stakers_reward(
+    vote_pubkey: &Pubkey,
+    target_epoch: u64,
+    stakers_reward: u64,
+    vote_reward: Option<&RpcInflationReward>,
+) -> Result<()> {
+    let Some(vote_reward) = vote_reward else {
+        // The validator earned no inflation rewards, so neither did its stakers.
+        return Ok(());
+    };
+    let commission = u128::from(vote_reward.commission.unwrap_or(0));
+    if commission == 0 || commission >= 100 {
+        warn!(
+            "Cannot check that every stake account delegated to {} in epoch {} was found, with a {}% commission",
+            vote_pubkey, target_epoch, commission
+        );
+        return Ok(());
+    }
+    let expected = u128::from(vote_reward.amount) * (100 - commission) / commission;
+    let shortfall = expected.saturating_sub(u128::from(stakers_reward));
+    if shortfall * 10_000 > expected * MAX_STAKERS_REWARD_SHORTFALL_BPS {
+        return Err(anyhow!(
+            "The stake accounts delegated to {} were paid {} lamports of inflation rewards for epoch {}, short of the {} its {}% commission implies: stake accounts were closed or redelegated since",
+            vote_pubkey,
+            stakers_reward,
+            target_epoch,
+            expected,
+            commission
+        ));
+    }
+    Ok(())
+}
@@ -0,0 +1,281 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::str::FromStr;
+
+use anyhow::{anyhow, Result};
+use clap::Parser;
+use dialoguer::Confirm;
+use serde::{Deserialize, Serialize};
+use solana_sdk::pubkey::Pubkey;
+
+use crate::ledger::PayoutLedger;
+
+/// How payouts are approved before they are sent.
+#[derive(Parser, Debug, Clone)]
+pub struct ApprovalArgs {
+    /// Approve payouts without asking, e.g. when run from cron. Payouts above the
+    /// `--approval-policy` thresholds are held instead of sent.
+    #[arg(short = 'y', long, env)]
+    pub yes: bool,
+    /// Path to a JSON approval policy with per-bond and per-epoch maximum payouts. Payouts under
+    /// the thresholds are approved without asking, payouts above them need a confirmation (or
+    /// are held with `--yes`).
+    #[arg(long, env)]
+    pub approval_policy: Option<String>,
+}
+
+/// Maximum payouts, in lamports, that are approved without asking.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ApprovalPolicy {
+    /// Maximum payout to a bond for one epoch, unless the bond is listed in `bonds`.
+    #[serde(default)]
+    pub max_bond_payout: Option<u64>,
+    /// Maximum payout for one epoch of the listed bonds, keyed by bond pubkey.
+    #[serde(default)]
+    pub bonds: BTreeMap<String, u64>,
+    /// Maximum total paid to all bonds for one epoch.
+    #[serde(default)]
+    pub max_epoch_payout: Option<u64>,
+}
+
+impl ApprovalPolicy {
+    pub fn read(path: &str) -> Result<Self> {
+        let data = fs::read_to_string(path)
+            .map_err(|e| anyhow!("Failed to read approval policy {}: {}", path, e))?;
+        let policy: Self = serde_json::from_str(&data)
+            .map_err(|e| anyhow!("Invalid approval policy {}: {}", path, e))?;
+        for bond in policy.bonds.keys() {
+            Pubkey::from_str(bond)
+                .map_err(|e| anyhow!("Invalid bond {} in approval policy: {}", bond, e))?;
+        }
+        Ok(policy)
+    }
+
+    /// Returns why paying `amount` to `bond_pubkey` exceeds the policy, given the lamports
+    /// already approved for the epoch.
+    pub fn exceeded(
+        &self,
+        bond_pubkey: &Pubkey,
+        amount: u64,
+        epoch_approved: u64,
+    ) -> Option<String> {
+        let max_bond_payout = self
+            .bonds
+            .get(&bond_pubkey.to_string())
+            .copied()
+            .or(self.max_bond_payout);
+        if let Some(max) = max_bond_payout {
+            if amount > max {
+                return Some(format!(
+                    "{} lamports is above the bond's maximum payout of {}",
+                    amount, max
+                ));
+            }
+        }
+        if let Some(max) = self.max_epoch_payout {
+            if epoch_approved.saturating_add(amount) > max {
+                return Some(format!(
+                    "{} lamports on top of the {} already approved for the epoch is above the epoch's maximum payout of {}",
+                    amount, epoch_approved, max
+                ));
+            }
+        }
+        None
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Decision {
+    Approve,
+    Ask,
+    /// Above the policy's thresholds while running unattended, with the reason.
+    Hold(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Approval {
+    Approved,
+    Declined,
+    Held(String),
+}
+
+/// Approves payouts following `--yes` and the approval policy, keeping count of the lamports
+/// approved for each epoch, starting from those the ledger already has paid or on their way.
+pub struct Approver {
+    yes: bool,
+    policy: Option<ApprovalPolicy>,
+    approved: BTreeMap<u64, u64>,
+}
+
+impl Approver {
+    pub fn new(args: &ApprovalArgs, ledger: &PayoutLedger) -> Result<Self> {
+        let policy = args
+            .approval_policy
+            .as_deref()
+            .map(ApprovalPolicy::read)
+            .transpose()?;
+        Ok(Self {
+            yes: args.yes,
+            policy,
+            // Payouts of other bonds, made by earlier runs or other processes, count towards the
+            // epoch's maximum too.
+            approved: ledger.paid_by_epoch(),
+        })
+    }
+
+    pub fn decide(&self, bond_pubkey: &Pubkey, epoch: u64, amount: u64) -> Decision {
+        let exceeded = self.policy.as_ref().and_then(|policy| {
+            let epoch_approved = self.approved.get(&epoch).copied().unwrap_or(0);
+            policy.exceeded(bond_pubkey, amount, epoch_approved)
+        });
+        match exceeded {
+            Some(reason) if self.yes => Decision::Hold(reason),
+            Some(_) => Decision::Ask,
+            None if self.yes || self.policy.is_some() => Decision::Approve,
+            None => Decision::Ask,
+        }
+    }
+
+    /// Approves paying `amount` to `bond_pubkey` for `epoch`, asking `prompt` when needed.
+    pub fn approve(
+        &mut self,
+        bond_pubkey: &Pubkey,
+        epoch: u64,
+        amount: u64,
+        prompt: &str,
+    ) -> Result<Approval> {
+        let approval = match self.decide(bond_pubkey, epoch, amount) {
+            Decision::Approve => Approval::Approved,
+            Decision::Ask => {
+                if Confirm::new().with_prompt(prompt).interact()? {
+                    Approval::Approved
+                } else {
+                    Approval::Declined
+                }
+            }
+            Decision::Hold(reason) => Approval::Held(reason),
+        };
+        if approval == Approval::Approved {
+            *self.approved.entry(epoch).or_default() += amount;
+        }
+        Ok(approval)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rewards::ExcessRewards;
+    use crate::test_utils::temp_ledger_path;
+
+    fn approver(yes: bool, policy: Option<ApprovalPolicy>) -> Approver {
+        Approver {
+            yes,
+            policy,
+            approved: BTreeMap::new(),
+        }
+    }
+
+    #[test]
+    fn test_approval_decisions() {
+        let bond = Pubkey::new_unique();
+        let whale = Pubkey::new_unique();
+        let policy = ApprovalPolicy {
+            max_bond_payout: Some(1_000),
+            bonds: BTreeMap::from([(whale.to_string(), 5_000)]),
+            max_epoch_payout: Some(5_500),
+        };
+
+        assert_eq!(approver(false, None).decide(&bond, 700, 1), Decision::Ask);
+        assert_eq!(
+            approver(true, None).decide(&bond, 700, u64::MAX),
+            Decision::Approve
+        );
+
+        let mut interactive = approver(false, Some(policy.clone()));
+        assert_eq!(interactive.decide(&bond, 700, 1_000), Decision::Approve);
+        assert_eq!(interactive.decide(&bond, 700, 1_001), Decision::Ask);
+        assert_eq!(interactive.decide(&whale, 700, 5_000), Decision::Approve);
+
+        let mut unattended = approver(true, Some(policy));
+        assert!(matches!(
+            unattended.decide(&bond, 700, 1_001),
+            Decision::Hold(_)
+        ));
+        for approver in [&mut interactive, &mut unattended] {
+            assert_eq!(
+                approver.approve(&whale, 700, 5_000, "").unwrap(),
+                Approval::Approved
+            );
+            // The epoch's maximum counts what was already approved for it.
+            assert_ne!(approver.decide(&bond, 700, 501), Decision::Approve);
+            assert_eq!(approver.decide(&bond, 700, 500), Decision::Approve);
+            assert_eq!(approver.decide(&bond, 701, 1_000), Decision::Approve);
+        }
+    }
+
+    #[test]
+    fn test_epoch_maximum_counts_payouts_on_record() {
+        let dir = std::env::temp_dir();
+        let policy_path = dir.join(format!("pye-epoch-policy-{}.json", std::process::id()));
+        fs::write(&policy_path, r#"{"max_epoch_payout": 1500}"#).unwrap();
+        let args = ApprovalArgs {
+            yes: true,
+            approval_policy: Some(policy_path.to_str().unwrap().to_string()),
+        };
+        let ledger_path = temp_ledger_path("approval");
+        let (first, second, vote) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+
+        // Each bond is paid by a separate run.
+        let mut ledger = PayoutLedger::open(&ledger_path).unwrap();
+        let mut approver = Approver::new(&args, &ledger).unwrap();
+        assert_eq!(
+            approver.approve(&first, 700, 1_000, "").unwrap(),
+            Approval::Approved
+        );
+        ledger
+            .record_pending(&first, &vote, 700, ExcessRewards::default(), 0, 1_000)
+            .unwrap();
+
+        let ledger = PayoutLedger::open(&ledger_path).unwrap();
+        let approver = Approver::new(&args, &ledger).unwrap();
+        assert!(matches!(
+            approver.decide(&second, 700, 1_000),
+            Decision::Hold(_)
+        ));
+        assert_eq!(approver.decide(&second, 700, 500), Decision::Approve);
+        assert_eq!(approver.decide(&second, 701, 1_000), Decision::Approve);
+        fs::remove_file(&policy_path).unwrap();
+        fs::remove_file(&ledger_path).unwrap();
+    }
+
+    #[test]
+    fn test_read_approval_policy() {
+        let path =
+            std::env::temp_dir().join(format!("pye-approval-policy-{}.json", std::process::id()));
+        let bond = Pubkey::new_unique();
+        fs::write(
+            &path,
+            format!(
+                r#"{{"max_bond_payout": 1000, "bonds": {{"{}": 5000}}}}"#,
+                bond
+            ),
+        )
+        .unwrap();
+        let policy = ApprovalPolicy::read(path.to_str().unwrap()).unwrap();
+        assert_eq!(policy.max_bond_payout, Some(1_000));
+        assert_eq!(policy.bonds[&bond.to_string()], 5_000);
+        assert_eq!(policy.max_epoch_payout, None);
+
+        fs::write(&path, r#"{"bonds": {"not-a-bond": 5000}}"#).unwrap();
+        assert!(ApprovalPolicy::read(path.to_str().unwrap()).is_err());
+        fs::write(&path, r#"{"max_payout": 5000}"#).unwrap();
+        assert!(ApprovalPolicy::read(path.to_str().unwrap()).is_err());
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,2 +1,203 @@
+use std::ops::RangeInclusive;
+
+use anyhow::{anyhow, Result};
+use pye_core_cpi::pye_core::accounts::SoloValidatorBond;
+use solana_sdk::epoch_schedule::EpochSchedule;
+
+use crate::providers::ChainDataProvider;
+
+pub mod offline;
+pub mod reconcile;
 pub mod transfer_excess_rewards;
 pub mod validator_bond_manager;
+
+/// Resolves the target epochs requested on the command line. Without any epoch arguments only
+/// the last completed epoch is targeted; `--to-epoch` defaults to the last completed epoch.
+pub fn resolve_target_epochs(
+    epoch: Option<u64>,
+    from_epoch: Option<u64>,
+    to_epoch: Option<u64>,
+    current_epoch: u64,
+) -> Result<RangeInclusive<u64>> {
+    let last_completed_epoch = current_epoch
+        .checked_sub(1)
+        .ok_or_else(|| anyhow!("No completed epoch yet"))?;
+    let (from_epoch, to_epoch) = match (epoch, from_epoch, to_epoch) {
+        (Some(epoch), None, None) => (epoch, epoch),
+        (None, from_epoch, to_epoch) => (
+            from_epoch.unwrap_or(last_completed_epoch),
+            to_epoch.unwrap_or(last_completed_epoch),
+        ),
+        _ => {
+            return Err(anyhow!(
+                "--epoch cannot be combined with --from-epoch/--to-epoch"
+            ))
+        }
+    };
+    if to_epoch > last_completed_epoch {
+        return Err(anyhow!(
+            "Epoch {} has not completed yet (current epoch: {})",
+            to_epoch,
+            current_epoch
+        ));
+    }
+    if from_epoch > to_epoch {
+        return Err(anyhow!(
+            "--from-epoch {} is after --to-epoch {}",
+            from_epoch,
+            to_epoch
+        ));
+    }
+    Ok(from_epoch..=to_epoch)
+}
+
+/// Resolves the first epoch the manager processes on startup: the one after its checkpoint, the
+/// last epoch it fully processed, going back at most `max_catch_up_epochs` completed epochs.
+/// Without a checkpoint, only the epochs completing from now on are processed.
+pub fn resolve_resume_epoch(
+    checkpoint: Option<u64>,
+    current_epoch: u64,
+    max_catch_up_epochs: u64,
+) -> u64 {
+    match checkpoint {
+        Some(checkpoint) => (checkpoint + 1)
+            .max(current_epoch.saturating_sub(max_catch_up_epochs))
+            .min(current_epoch),
+        None => current_epoch,
+    }
+}
+
+/// How many slots before the end of an epoch are searched for its last produced block.
+const MAX_SKIPPED_END_SLOTS: u64 = 100;
+
+/// Returns the block time of the last slot produced in `epoch`, walking back over skipped slots
+/// at its end.
+pub async fn fetch_epoch_end_time(
+    chain_data: &dyn ChainDataProvider,
+    epoch_schedule: &EpochSchedule,
+    epoch: u64,
+) -> Result<i64> {
+    let first_slot = epoch_schedule.get_first_slot_in_epoch(epoch);
+    let last_slot = epoch_schedule.get_last_slot_in_epoch(epoch);
+    let lowest_slot = last_slot
+        .saturating_sub(MAX_SKIPPED_END_SLOTS)
+        .max(first_slot);
+    let mut last_err = None;
+    for slot in (lowest_slot..=last_slot).rev() {
+        match chain_data.get_block_time(slot).await {
+            Ok(block_time) => return Ok(block_time),
+            Err(e) => last_err = Some(e),
+        }
+    }
+    Err(anyhow!(
+        "Failed to fetch the end time of epoch {}: {}",
+        epoch,
+        last_err.map(|e| e.to_string()).unwrap_or_default()
+    ))
+}
+
+/// Whether the bond was outstanding when its validator's epoch ended at `epoch_end_ts`, i.e.
+/// issued before then and not matured yet. Only those epochs are owed excess rewards.
+pub fn bond_outstanding_at(bond: &SoloValidatorBond, epoch_end_ts: i64) -> bool {
+    bond.issuance_ts < epoch_end_ts && bond.maturity_ts > epoch_end_ts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_defaults_to_last_completed_epoch() {
+        assert_eq!(
+            resolve_target_epochs(None, None, None, 800).unwrap(),
+            799..=799
+        );
+    }
+
+    #[test]
+    fn test_single_epoch() {
+        assert_eq!(
+            resolve_target_epochs(Some(795), None, None, 800).unwrap(),
+            795..=795
+        );
+    }
+
+    #[test]
+    fn test_epoch_range() {
+        assert_eq!(
+            resolve_target_epochs(None, Some(796), None, 800).unwrap(),
+            796..=799
+        );
+        assert_eq!(
+            resolve_target_epochs(None, Some(796), Some(797), 800).unwrap(),
+            796..=797
+        );
+    }
+
+    #[test]
+    fn test_rejects_incomplete_epoch() {
+        assert!(resolve_target_epochs(Some(800), None, None, 800).is_err());
+        assert!(resolve_target_epochs(None, None, Some(801), 800).is_err());
+    }
+
+    #[test]
+    fn test_rejects_invalid_range() {
+        assert!(resolve_target_epochs(None, Some(799), Some(797), 800).is_err());
+        assert!(resolve_target_epochs(Some(797), Some(796), None, 800).is_err());
+    }
+
+    #[test]
+    fn test_bond_outstanding_at() {
+        let mut bond = crate::providers::fake::solo_validator_bond(
+            solana_sdk::pubkey::Pubkey::new_unique(),
+            solana_sdk::pubkey::Pubkey::new_unique(),
+            0,
+            0,
+            0,
+        );
+        bond.issuance_ts = 1_000;
+        bond.maturity_ts = 2_000;
+        assert!(!bond_outstanding_at(&bond, 900));
+        assert!(!bond_outstanding_at(&bond, 1_000));
+        assert!(bond_outstanding_at(&bond, 1_500));
+        assert!(!bond_outstanding_at(&bond, 2_000));
+    }
+
+    #[test]
+    fn test_resume_from_checkpoint() {
+        assert_eq!(resolve_resume_epoch(None, 800, 3), 800);
+        assert_eq!(resolve_resume_epoch(Some(799), 800, 3), 800);
+        assert_eq!(resolve_resume_epoch(Some(798), 800, 3), 799);
+        // Older epochs are past the RPC's history.
+        assert_eq!(resolve_resume_epoch(Some(790), 800, 3), 797);
+        assert_eq!(resolve_resume_epoch(Some(1), 2, 3), 2);
+    }
+
+    #[tokio::test]
+    async fn test_epoch_end_time() {
+        let chain = crate::providers::fake::FakeChainData::new(800);
+        let last_slot = chain.epoch_schedule().get_last_slot_in_epoch(798);
+        let chain = chain.with_block_time(last_slot, 1_700_000_000);
+        let epoch_schedule = *chain.epoch_schedule();
+        assert_eq!(
+            fetch_epoch_end_time(&chain, &epoch_schedule, 798)
+                .await
+                .unwrap(),
+            1_700_000_000
+        );
+        // A past epoch's end cannot be told without its block time.
+        assert!(fetch_epoch_end_time(&chain, &epoch_schedule, 799)
+            .await
+            .is_err());
+
+        // Skipped slots at the end of the epoch are walked back over.
+        let last_slot = epoch_schedule.get_last_slot_in_epoch(799);
+        let chain = chain.with_block_time(last_slot - 3, 1_700_400_000);
+        assert_eq!(
+            fetch_epoch_end_time(&chain, &epoch_schedule, 799)
+                .await
+                .unwrap(),
+            1_700_400_000
+        );
+    }
+}
@@ -0,0 +1,685 @@
+use std::fs;
+use std::str::FromStr;
+
+use anyhow::{anyhow, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use clap::Parser;
+use dialoguer::Confirm;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use solana_metrics::{datapoint_error, flush};
+use solana_sdk::{pubkey::Pubkey, signer::Signer, transaction::Transaction};
+
+use crate::{
+    ledger::PayoutLedger,
+    rpc_utils::new_rpc_client,
+    sender::{send_with_rebroadcast, SendOutcome, SenderArgs, TransactionRpc},
+    signer::{signer_from_uri, RemoteSignerArgs},
+    transactions::{decode_payout_message, DecodedPayout, PayoutMethod, PayoutTransaction},
+};
+
+/// Payout transactions exported by `transfer-excess-rewards --export`, to be signed with `sign`
+/// and broadcast with `submit`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportedPayouts {
+    pub transactions: Vec<ExportedTransaction>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportedTransaction {
+    pub transfers: Vec<ExportedTransfer>,
+    /// Durable nonce account whose nonce the transaction uses as its blockhash.
+    pub nonce_account: String,
+    /// Base64-encoded wire transaction, partially signed or not signed at all.
+    pub transaction: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExportedTransfer {
+    pub bond: String,
+    pub vote_pubkey: String,
+    pub epoch: u64,
+    /// Account the lamports are transferred to, which depends on the payout method.
+    pub destination: String,
+    pub lamports: u64,
+}
+
+impl ExportedTransaction {
+    /// Exports the unsigned `payout`, paid with `method`, which must use a durable nonce.
+    /// `epochs[i]` is the epoch paid by the `i`-th transfer of the payout.
+    pub fn new(payout: &PayoutTransaction, epochs: &[u64], method: PayoutMethod) -> Result<Self> {
+        let nonce = payout
+            .nonce
+            .as_ref()
+            .ok_or_else(|| anyhow!("Exported transactions must use a durable nonce"))?;
+        let transfers = payout
+            .transfers
+            .iter()
+            .zip(epochs)
+            .map(|(transfer, epoch)| ExportedTransfer {
+                bond: transfer.bond_pubkey.to_string(),
+                vote_pubkey: transfer.validator_vote_account.to_string(),
+                epoch: *epoch,
+                destination: method
+                    .destination(&transfer.bond_pubkey, &transfer.stake_account)
+                    .to_string(),
+                lamports: transfer.lamports,
+            })
+            .collect();
+        let mut exported = Self {
+            transfers,
+            nonce_account: nonce.account.to_string(),
+            transaction: String::new(),
+        };
+        exported.set_transaction(&Transaction::new_unsigned(payout.message.clone()))?;
+        Ok(exported)
+    }
+
+    pub fn transaction(&self) -> Result<Transaction> {
+        let data = STANDARD
+            .decode(&self.transaction)
+            .map_err(|e| anyhow!("Invalid transaction encoding: {}", e))?;
+        bincode::deserialize(&data).map_err(|e| anyhow!("Invalid transaction: {}", e))
+    }
+
+    pub fn set_transaction(&mut self, transaction: &Transaction) -> Result<()> {
+        self.transaction = STANDARD.encode(bincode::serialize(transaction)?);
+        Ok(())
+    }
+
+    /// Decodes what `transaction` does and checks that it is exactly what the file lists: the
+    /// advance of the listed nonce account and the listed transfers, in order, and nothing else.
+    /// Only the bonds and epochs the transfers are labelled with are taken from the file.
+    fn verify(&self, transaction: &Transaction) -> Result<DecodedPayout> {
+        let decoded = decode_payout_message(&transaction.message)?;
+        let nonce_account = Pubkey::from_str(&self.nonce_account)?;
+        if decoded.nonce_account != Some(nonce_account) {
+            return Err(anyhow!(
+                "Transaction does not use the listed nonce account {}",
+                nonce_account
+            ));
+        }
+        let listed = self
+            .transfers
+            .iter()
+            .map(|transfer| Ok((Pubkey::from_str(&transfer.destination)?, transfer.lamports)))
+            .collect::<Result<Vec<_>>>()?;
+        if decoded.transfers != listed {
+            return Err(anyhow!(
+                "Transaction transfers {:?}, not the listed {:?}",
+                decoded.transfers,
+                listed
+            ));
+        }
+        Ok(decoded)
+    }
+}
+
+impl ExportedPayouts {
+    pub fn read(path: &str) -> Result<Self> {
+        let data =
+            fs::read_to_string(path).map_err(|e| anyhow!("Failed to read {}: {}", path, e))?;
+        serde_json::from_str(&data).map_err(|e| anyhow!("Failed to parse {}: {}", path, e))
+    }
+
+    pub fn write(&self, path: &str) -> Result<()> {
+        fs::write(path, serde_json::to_string_pretty(self)?)
+            .map_err(|e| anyhow!("Failed to write {}: {}", path, e))
+    }
+}
+
+#[derive(Clone, Debug, Parser)]
+pub struct SignArgs {
+    /// File written by `transfer-excess-rewards --export`.
+    #[arg(short, long, env)]
+    input: String,
+    /// Signer of the transactions (payer and/or nonce authority): a keypair path, `prompt://`,
+    /// `stdin` or the URL of a remote signer. Can be given several times.
+    #[arg(short, long = "keypair", required = true)]
+    keypairs: Vec<String>,
+    /// Where to write the signed transactions. Defaults to overwriting the input file.
+    #[arg(short, long, env)]
+    output: Option<String>,
+    #[command(flatten)]
+    remote_signer: RemoteSignerArgs,
+}
+
+/// Signs exported payout transactions with the given keypairs. Does not need network access.
+pub async fn handle_sign(args: SignArgs) -> Result<()> {
+    let mut keypairs = vec![];
+    for uri in &args.keypairs {
+        keypairs.push(signer_from_uri(uri, "signer", &args.remote_signer).await?);
+    }
+    let mut exported = ExportedPayouts::read(&args.input)?;
+
+    // The summary is decoded from the transactions themselves, so a tampered file cannot show
+    // other transfers than the ones signed.
+    for (index, entry) in exported.transactions.iter().enumerate() {
+        let transaction = entry.transaction()?;
+        let decoded = entry
+            .verify(&transaction)
+            .map_err(|e| anyhow!("Transaction {} does not match the file: {}", index, e))?;
+        println!(
+            "Transaction {} (fee payer {}, nonce account {}, up to {} lamports in priority fees):",
+            index,
+            decoded.fee_payer,
+            entry.nonce_account,
+            decoded.compute_budget.priority_fee()
+        );
+        for ((destination, lamports), transfer) in decoded.transfers.iter().zip(&entry.transfers) {
+            println!(
+                "  {} lamports to {} (SoloValidatorBond {} for epoch {})",
+                lamports, destination, transfer.bond, transfer.epoch
+            );
+        }
+    }
+    if !Confirm::new()
+        .with_prompt(format!(
+            "Sign {} transactions?",
+            exported.transactions.len()
+        ))
+        .interact()?
+    {
+        info!("Aborted: user declined to sign the transactions.");
+        return Ok(());
+    }
+
+    for entry in exported.transactions.iter_mut() {
+        let mut transaction = entry.transaction()?;
+        sign_transaction(&mut transaction, &keypairs)?;
+        entry.set_transaction(&transaction)?;
+    }
+    let output = args.output.as_deref().unwrap_or(&args.input);
+    exported.write(output)?;
+    println!("Signed transactions written to {}", output);
+    Ok(())
+}
+
+/// Signs `transaction` with every keypair that is one of its required signers, keeping the
+/// signatures already present. Returns the number of signatures added.
+pub fn sign_transaction<S: Signer>(transaction: &mut Transaction, keypairs: &[S]) -> Result<usize> {
+    let signers = &transaction.message.account_keys
+        [..transaction.message.header.num_required_signatures as usize];
+    let keypairs: Vec<&S> = keypairs
+        .iter()
+        .filter(|keypair| signers.contains(&keypair.pubkey()))
+        .collect();
+    // The recent blockhash of an exported transaction is its durable nonce, so it is kept.
+    let blockhash = transaction.message.recent_blockhash;
+    transaction.try_partial_sign(&keypairs, blockhash)?;
+    Ok(keypairs.len())
+}
+
+#[derive(Clone, Debug, Parser)]
+pub struct SubmitArgs {
+    /// RPC Endpoint
+    #[arg(
+        short,
+        long,
+        env,
+        default_value = "https://api.mainnet-beta.solana.com"
+    )]
+    rpc: String,
+    /// File of transactions signed with `sign`.
+    #[arg(short, long, env)]
+    input: String,
+    /// Path to the payout ledger the transactions were exported from, updated with their
+    /// outcome.
+    #[arg(long, env, default_value = "pye-payout-ledger.json")]
+    ledger: String,
+    #[command(flatten)]
+    sender: SenderArgs,
+}
+
+/// Broadcasts signed payout transactions and records their outcome in the ledger.
+pub async fn handle_submit(args: SubmitArgs) -> Result<()> {
+    let rpc = new_rpc_client(args.rpc.clone());
+    let exported = ExportedPayouts::read(&args.input)?;
+    let mut ledger = PayoutLedger::open(&args.ledger)?;
+    let failures = submit_payouts(&rpc, &args.sender, &exported, &mut ledger).await?;
+    flush();
+
+    if failures > 0 {
+        return Err(anyhow!(
+            "{} of {} transactions were not confirmed",
+            failures,
+            exported.transactions.len()
+        ));
+    }
+    Ok(())
+}
+
+/// Broadcasts every signed transaction of `exported` and records the outcome of each transfer
+/// in `ledger`. Returns the number of transactions that were not confirmed.
+async fn submit_payouts(
+    rpc: &dyn TransactionRpc,
+    sender: &SenderArgs,
+    exported: &ExportedPayouts,
+    ledger: &mut PayoutLedger,
+) -> Result<usize> {
+    // A signed transaction cannot be re-signed: it only lands with the nonce it was signed with.
+    let sender = SenderArgs {
+        max_resigns: 0,
+        ..sender.clone()
+    };
+
+    let mut failures = 0;
+    for entry in &exported.transactions {
+        let transaction = entry.transaction()?;
+        // The ledger is updated from the transfers listed in the file.
+        entry.verify(&transaction)?;
+        if !transaction.is_signed() {
+            return Err(anyhow!(
+                "Transaction paying {:?} is not fully signed",
+                entry.transfers
+            ));
+        }
+        // Recorded before broadcasting, so the payouts can be checked if submitting is interrupted.
+        let recorded = recorded_transfers(entry, ledger)?;
+        for (bond_pubkey, epoch) in &recorded {
+            ledger.record_submitted(bond_pubkey, *epoch, &transaction.signatures[0])?;
+        }
+        let nonce_account = Pubkey::from_str(&entry.nonce_account)?;
+        let sign = |nonce| {
+            if nonce == transaction.message.recent_blockhash {
+                Ok(transaction.clone())
+            } else {
+                Err(anyhow!(
+                    "Nonce account {} was advanced since the transaction was signed",
+                    nonce_account
+                ))
+            }
+        };
+        let outcome = send_with_rebroadcast(rpc, &sender, Some(&nonce_account), &sign).await;
+        match &outcome {
+            SendOutcome::Confirmed { signature } => println!("Transaction Sent: {}\n", signature),
+            outcome => {
+                failures += 1;
+                println!("Transaction not confirmed: {:?}\n", outcome);
+                datapoint_error!(
+                    "excess_reward_transfer_failure",
+                    ("bonds", entry.transfers.len() as i64, i64),
+                    (
+                        "signature",
+                        outcome
+                            .signature()
+                            .map(|signature| signature.to_string())
+                            .unwrap_or_default(),
+                        String
+                    ),
+                    ("outcome", format!("{:?}", outcome), String),
+                );
+            }
+        }
+
+        for (bond_pubkey, epoch) in &recorded {
+            ledger.record_send_outcome(bond_pubkey, *epoch, &outcome, None)?;
+        }
+    }
+    Ok(failures)
+}
+
+/// Bond and epoch of every transfer of `entry` that has a ledger record.
+fn recorded_transfers(
+    entry: &ExportedTransaction,
+    ledger: &PayoutLedger,
+) -> Result<Vec<(Pubkey, u64)>> {
+    let mut recorded = vec![];
+    for transfer in &entry.transfers {
+        let bond_pubkey = Pubkey::from_str(&transfer.bond)?;
+        if ledger.get(&bond_pubkey, transfer.epoch).is_none() {
+            warn!(
+                "No ledger record for SoloValidatorBond {} epoch {}",
+                bond_pubkey, transfer.epoch
+            );
+            continue;
+        }
+        recorded.push((bond_pubkey, transfer.epoch));
+    }
+    Ok(recorded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    use async_trait::async_trait;
+    use solana_sdk::{
+        hash::Hash,
+        message::Message,
+        signature::{Keypair, Signature},
+        system_instruction::transfer as system_transfer,
+    };
+    use solana_transaction_status_client_types::{
+        TransactionConfirmationStatus, TransactionStatus,
+    };
+
+    use crate::ledger::PayoutStatus;
+    use crate::nonce::Nonce;
+    use crate::rewards::ExcessRewards;
+    use crate::sender::TransactionExpiry;
+    use crate::test_utils::temp_ledger_path;
+    use crate::transactions::BondTransfer;
+
+    /// Cluster where every transaction sent lands, and whose nonce accounts all hold `nonce`.
+    struct LandingRpc {
+        nonce: Hash,
+        landed: Mutex<Vec<Signature>>,
+    }
+
+    impl LandingRpc {
+        fn new(nonce: Hash) -> Self {
+            Self {
+                nonce,
+                landed: Mutex::default(),
+            }
+        }
+    }
+
+    #[async_trait]
+    impl TransactionRpc for LandingRpc {
+        async fn get_latest_blockhash(&self) -> Result<(Hash, u64)> {
+            Err(anyhow!(
+                "Durable nonce transactions don't use a recent blockhash"
+            ))
+        }
+
+        async fn send_transaction(&self, transaction: &Transaction) -> Result<Signature> {
+            let signature = transaction.signatures[0];
+            self.landed.lock().unwrap().push(signature);
+            Ok(signature)
+        }
+
+        async fn get_signature_status(
+            &self,
+            signature: &Signature,
+        ) -> Result<Option<TransactionStatus>> {
+            Ok(self
+                .landed
+                .lock()
+                .unwrap()
+                .contains(signature)
+                .then(|| TransactionStatus {
+                    slot: 1,
+                    confirmations: None,
+                    status: Ok(()),
+                    err: None,
+                    confirmation_status: Some(TransactionConfirmationStatus::Finalized),
+                }))
+        }
+
+        async fn get_finalized_block_height(&self) -> Result<u64> {
+            Ok(0)
+        }
+
+        async fn get_nonce(&self, _nonce_account: &Pubkey) -> Result<Hash> {
+            Ok(self.nonce)
+        }
+    }
+
+    #[test]
+    fn test_export_and_sign_round_trip() {
+        let payer = Keypair::new();
+        let nonce_authority = Keypair::new();
+        let nonce = Nonce {
+            account: Pubkey::new_unique(),
+            authority: nonce_authority.pubkey(),
+            blockhash: Hash::new_unique(),
+        };
+        let transfer = BondTransfer {
+            bond_pubkey: Pubkey::new_unique(),
+            validator_vote_account: Pubkey::new_unique(),
+            stake_account: Pubkey::new_unique(),
+            lamports: 1_000,
+        };
+        let mut message = Message::new_with_nonce(
+            vec![system_transfer(
+                &payer.pubkey(),
+                &transfer.bond_pubkey,
+                transfer.lamports,
+            )],
+            Some(&payer.pubkey()),
+            &nonce.account,
+            &nonce.authority,
+        );
+        message.recent_blockhash = nonce.blockhash;
+        let payout = PayoutTransaction {
+            transfers: vec![transfer],
+            message,
+            nonce: Some(nonce),
+        };
+
+        let mut exported =
+            ExportedTransaction::new(&payout, &[800], PayoutMethod::SystemTransfer).unwrap();
+        assert_eq!(
+            exported.transfers,
+            vec![ExportedTransfer {
+                bond: transfer.bond_pubkey.to_string(),
+                vote_pubkey: transfer.validator_vote_account.to_string(),
+                epoch: 800,
+                destination: transfer.bond_pubkey.to_string(),
+                lamports: 1_000,
+            }]
+        );
+        let mut transaction = exported.transaction().unwrap();
+        let decoded = exported.verify(&transaction).unwrap();
+        assert_eq!(decoded.fee_payer, payer.pubkey());
+        assert_eq!(decoded.transfers, vec![(transfer.bond_pubkey, 1_000)]);
+        assert!(!transaction.is_signed());
+
+        // Keys that are not signers of the transaction are ignored.
+        assert_eq!(
+            sign_transaction(&mut transaction, &[payer.insecure_clone(), Keypair::new()]).unwrap(),
+            1
+        );
+        assert!(!transaction.is_signed());
+        assert_eq!(
+            sign_transaction(&mut transaction, &[nonce_authority]).unwrap(),
+            1
+        );
+        assert!(transaction.is_signed());
+        assert_eq!(transaction.message.recent_blockhash, nonce.blockhash);
+        transaction.verify().unwrap();
+
+        exported.set_transaction(&transaction).unwrap();
+        assert_eq!(exported.transaction().unwrap(), transaction);
+
+        exported.verify(&transaction).unwrap();
+    }
+
+    #[test]
+    fn test_verify_rejects_tampered_exports() {
+        let payer = Pubkey::new_unique();
+        let nonce_account = Pubkey::new_unique();
+        let bond = Pubkey::new_unique();
+        let message = |instructions: Vec<_>| {
+            Message::new_with_nonce(instructions, Some(&payer), &nonce_account, &payer)
+        };
+        let exported = |message: Message| {
+            let mut exported = ExportedTransaction {
+                transfers: vec![ExportedTransfer {
+                    bond: bond.to_string(),
+                    vote_pubkey: Pubkey::new_unique().to_string(),
+                    epoch: 800,
+                    destination: bond.to_string(),
+                    lamports: 1_000,
+                }],
+                nonce_account: nonce_account.to_string(),
+                transaction: String::new(),
+            };
+            exported
+                .set_transaction(&Transaction::new_unsigned(message))
+                .unwrap();
+            exported
+        };
+        let honest = exported(message(vec![system_transfer(&payer, &bond, 1_000)]));
+        honest.verify(&honest.transaction().unwrap()).unwrap();
+
+        // The file lists another amount or destination than the transaction pays.
+        let tampers: [fn(&mut ExportedTransfer); 2] = [
+            |transfer| transfer.lamports = 10,
+            |transfer| transfer.destination = Pubkey::new_unique().to_string(),
+        ];
+        for tamper in tampers {
+            let mut tampered = honest.clone();
+            tamper(&mut tampered.transfers[0]);
+            assert!(tampered.verify(&tampered.transaction().unwrap()).is_err());
+        }
+
+        // The transaction pays another amount, or pays someone else on top of the bond.
+        for instructions in [
+            vec![system_transfer(&payer, &bond, 1_000_000)],
+            vec![
+                system_transfer(&payer, &bond, 1_000),
+                system_transfer(&payer, &Pubkey::new_unique(), 1_000),
+            ],
+        ] {
+            let tampered = ExportedTransaction {
+                transaction: exported(message(instructions)).transaction,
+                ..honest.clone()
+            };
+            assert!(tampered.verify(&tampered.transaction().unwrap()).is_err());
+        }
+
+        // The transaction uses another nonce account than the listed one.
+        let tampered = ExportedTransaction {
+            nonce_account: Pubkey::new_unique().to_string(),
+            ..honest.clone()
+        };
+        assert!(tampered.verify(&tampered.transaction().unwrap()).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_submit_records_every_epoch_of_a_transaction() {
+        let path = temp_ledger_path("submit");
+        let mut ledger = PayoutLedger::open(&path).unwrap();
+        let payer = Keypair::new();
+        let nonce = Nonce {
+            account: Pubkey::new_unique(),
+            authority: payer.pubkey(),
+            blockhash: Hash::new_unique(),
+        };
+        let bond = BondTransfer {
+            bond_pubkey: Pubkey::new_unique(),
+            validator_vote_account: Pubkey::new_unique(),
+            stake_account: Pubkey::new_unique(),
+            lamports: 0,
+        };
+        // One transaction paying three epochs of the same bond.
+        let epochs = [700, 701, 702];
+        let transfers: Vec<BondTransfer> = [1_000, 2_000, 3_000]
+            .into_iter()
+            .map(|lamports| BondTransfer { lamports, ..bond })
+            .collect();
+        for (epoch, transfer) in epochs.iter().zip(&transfers) {
+            ledger
+                .record_pending(
+                    &bond.bond_pubkey,
+                    &bond.validator_vote_account,
+                    *epoch,
+                    ExcessRewards::default(),
+                    0,
+                    transfer.lamports,
+                )
+                .unwrap();
+        }
+        let mut message = Message::new_with_nonce(
+            transfers
+                .iter()
+                .map(|t| system_transfer(&payer.pubkey(), &t.bond_pubkey, t.lamports))
+                .collect(),
+            Some(&payer.pubkey()),
+            &nonce.account,
+            &nonce.authority,
+        );
+        message.recent_blockhash = nonce.blockhash;
+        let payout = PayoutTransaction {
+            transfers,
+            message,
+            nonce: Some(nonce),
+        };
+        let mut entry =
+            ExportedTransaction::new(&payout, &epochs, PayoutMethod::SystemTransfer).unwrap();
+        let mut transaction = entry.transaction().unwrap();
+        sign_transaction(&mut transaction, &[payer]).unwrap();
+        entry.set_transaction(&transaction).unwrap();
+
+        let rpc = LandingRpc::new(nonce.blockhash);
+        let sender = SenderArgs {
+            rebroadcast_interval_ms: 0,
+            max_resigns: 3,
+            max_nonce_rebroadcasts: 100,
+        };
+        let exported = ExportedPayouts {
+            transactions: vec![entry],
+        };
+        assert_eq!(
+            submit_payouts(&rpc, &sender, &exported, &mut ledger)
+                .await
+                .unwrap(),
+            0
+        );
+
+        let reopened = PayoutLedger::open(&path).unwrap();
+        let mut paid: Vec<(u64, PayoutStatus)> = reopened
+            .find_by_signature(&transaction.signatures[0].to_string())
+            .into_iter()
+            .map(|record| (record.epoch, record.status))
+            .collect();
+        paid.sort_by_key(|(epoch, _)| *epoch);
+        assert_eq!(
+            paid,
+            epochs
+                .iter()
+                .map(|epoch| (*epoch, PayoutStatus::Confirmed))
+                .collect::<Vec<_>>()
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_unsubmitted_export_fails_once_nonce_advances() {
+        let path = temp_ledger_path("unsubmitted");
+        let mut ledger = PayoutLedger::open(&path).unwrap();
+        let bond = Pubkey::new_unique();
+        let exported_nonce = Hash::new_unique();
+        ledger
+            .record_pending(
+                &bond,
+                &Pubkey::new_unique(),
+                700,
+                ExcessRewards::default(),
+                0,
+                1_000,
+            )
+            .unwrap();
+        ledger
+            .record_exported(
+                &bond,
+                700,
+                TransactionExpiry::Nonce {
+                    account: Pubkey::new_unique().to_string(),
+                    nonce: exported_nonce.to_string(),
+                },
+            )
+            .unwrap();
+
+        // The signed transaction can still be submitted.
+        let failed = ledger
+            .check_pending(&LandingRpc::new(exported_nonce))
+            .await
+            .unwrap();
+        assert!(failed.is_empty());
+        assert!(ledger.blocking_record(&bond, 700).is_some());
+
+        let failed = ledger
+            .check_pending(&LandingRpc::new(Hash::new_unique()))
+            .await
+            .unwrap();
+        assert_eq!(failed, vec![(bond, 700)]);
+        assert_eq!(ledger.get(&bond, 700).unwrap().status, PayoutStatus::Failed);
+        assert!(ledger.blocking_record(&bond, 700).is_none());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
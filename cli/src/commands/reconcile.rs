@@ -0,0 +1,712 @@
+use std::collections::{hash_map::Entry, BTreeMap, HashMap};
+use std::str::FromStr;
+
+use anyhow::{anyhow, Result};
+use clap::Parser;
+use futures::stream::{self, StreamExt};
+use log::{info, warn};
+use pye_core_cpi::pye_core::accounts::SoloValidatorBond;
+use serde_json::Value;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_client::rpc_client::GetConfirmedSignaturesForAddress2Config;
+use solana_client::rpc_config::RpcTransactionConfig;
+use solana_commitment_config::CommitmentConfig;
+use solana_metrics::{datapoint_info, datapoint_warn, flush};
+use solana_sdk::{epoch_schedule::EpochSchedule, pubkey::Pubkey, signature::Signature};
+use solana_transaction_status_client_types::{
+    option_serializer::OptionSerializer, EncodedTransaction, EncodedTransactionWithStatusMeta,
+    UiInstruction, UiMessage, UiParsedInstruction, UiTransactionEncoding,
+};
+
+use crate::{
+    accounts::{fetch_solo_validator_bond, fetch_solo_validator_bonds_by_vote_key},
+    commands::resolve_target_epochs,
+    ledger::{apply_carry_forward, PayoutLedger, PayoutRecord},
+    providers::{MevDataProvider, MevSourceArgs},
+    rewards::{
+        block_rewards::calculate_block_rewards, calculate_bond_excess_rewards,
+        mev_rewards::ValidatorInfo, or_zero_if_permissive, resolve_validator_active_stake,
+        StakeDenominatorArgs,
+    },
+    rpc_utils::new_rpc_client,
+    transactions::PayoutMethod,
+};
+
+/// Maximum number of signatures returned by one getSignaturesForAddress request.
+const SIGNATURES_PAGE_SIZE: usize = 1_000;
+
+#[derive(Clone, Debug, Parser)]
+pub struct ReconcileArgs {
+    /// RPC Endpoint
+    #[arg(
+        short,
+        long,
+        env,
+        default_value = "https://api.mainnet-beta.solana.com"
+    )]
+    rpc: String,
+    /// The Pye program ID
+    #[arg(
+        long,
+        env,
+        default_value = "PYEQZ2qYHPQapnw8Ms8MSPMNzoq59NHHfNwAtuV26wx"
+    )]
+    program_id: Pubkey,
+    /// SoloValidatorBond to reconcile.
+    #[arg(short, long, env, required_unless_present = "vote_pubkey")]
+    bond: Option<Pubkey>,
+    /// Reconcile every SoloValidatorBond pointed at this vote account.
+    #[arg(short, long, env, conflicts_with = "bond")]
+    vote_pubkey: Option<Pubkey>,
+    /// Pubkey of the account that pays the excess rewards.
+    #[arg(long, env)]
+    payer_pubkey: Pubkey,
+    /// Payout method the excess rewards were paid with. Transfers are looked for at its
+    /// destination: the bond's stake account for `stake-account`, the bond account otherwise.
+    #[arg(long, env, value_enum, default_value_t = PayoutMethod::SystemTransfer)]
+    payout_method: PayoutMethod,
+    /// First epoch to reconcile.
+    #[arg(long, env)]
+    from_epoch: u64,
+    /// Last epoch (inclusive) to reconcile. Defaults to the last completed epoch.
+    #[arg(long, env)]
+    to_epoch: Option<u64>,
+    /// Maximum RPC requests to send concurrently.
+    #[arg(long, env, default_value = "50")]
+    concurrency: usize,
+    /// Maximum number of pages of 1,000 signatures scanned in each bond's transaction history.
+    /// Transfers older than the last page scanned are not found, and a warning is logged.
+    #[arg(long, env, default_value = "20")]
+    max_signature_pages: usize,
+    /// The wait time (in secs) between get_block RPC call retries.
+    #[arg(long, env, default_value = "1800")]
+    block_retry_delay: u64,
+    /// Number of getBlock calls packed into one batched JSON-RPC request. 1 disables batching.
+    #[arg(long, env, default_value = "20")]
+    block_batch_size: usize,
+    /// Path to the local payout ledger, used to attribute transfers to the epoch they paid for.
+    #[arg(long, env, default_value = "pye-payout-ledger.json")]
+    ledger: String,
+    /// Net each epoch against the negative balance carried from previous epochs, as the payout
+    /// commands do with `--carry-forward`.
+    #[arg(long, env)]
+    carry_forward: bool,
+    #[command(flatten)]
+    mev: MevSourceArgs,
+    #[command(flatten)]
+    stake: StakeDenominatorArgs,
+    /// Treat reward components that cannot be fetched (block rewards, inflation rewards) as 0
+    /// instead of reporting the bond's epoch as unknown.
+    #[arg(long, env)]
+    permissive: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReconcileStatus {
+    Ok,
+    Underpaid,
+    Overpaid,
+    Missing,
+}
+
+pub fn reconcile_status(expected: u64, paid: u64) -> ReconcileStatus {
+    if paid == expected {
+        ReconcileStatus::Ok
+    } else if paid == 0 {
+        ReconcileStatus::Missing
+    } else if paid < expected {
+        ReconcileStatus::Underpaid
+    } else {
+        ReconcileStatus::Overpaid
+    }
+}
+
+/// The validator's MEV data and total block rewards for an epoch, or why they are unavailable.
+type ValidatorEpochData = std::result::Result<(ValidatorInfo, u64), String>;
+
+/// A system transfer from the payer to a bond's payout destination, found in its transaction
+/// history.
+struct PayerTransfer {
+    signature: String,
+    slot: u64,
+    lamports: u64,
+    /// Accounts of the transaction, among which the proposal it executes if it was paid from a
+    /// multisig.
+    accounts: Vec<String>,
+}
+
+pub async fn handle_reconcile(args: ReconcileArgs) -> Result<()> {
+    args.stake.check(&args.mev)?;
+    let client = new_rpc_client(args.rpc.clone());
+    let mev_provider = args.mev.mev_data_provider(&args.rpc);
+    let ledger = PayoutLedger::open(&args.ledger)?;
+
+    let current_epoch = client.get_epoch_info().await?.epoch;
+    let epoch_schedule = client.get_epoch_schedule().await?;
+    let target_epochs =
+        resolve_target_epochs(None, Some(args.from_epoch), args.to_epoch, current_epoch)?;
+
+    let bonds: Vec<(Pubkey, SoloValidatorBond)> = match (args.bond, args.vote_pubkey) {
+        (Some(bond_pubkey), _) => vec![(
+            bond_pubkey,
+            fetch_solo_validator_bond(&client, &bond_pubkey).await?,
+        )],
+        (None, Some(vote_pubkey)) => {
+            fetch_solo_validator_bonds_by_vote_key(&client, &args.program_id, &vote_pubkey).await?
+        }
+        (None, None) => return Err(anyhow!("Either --bond or --vote-pubkey is required")),
+    };
+
+    // Excess rewards for an epoch are paid once the epoch has ended.
+    let min_slot = epoch_schedule.get_first_slot_in_epoch(*target_epochs.start() + 1);
+    // MEV data and total block rewards per (vote account, epoch), shared by all bonds.
+    let mut validator_epoch_data: HashMap<(Pubkey, u64), ValidatorEpochData> = HashMap::new();
+
+    println!(
+        "{:<44} {:>6} {:>15} {:>15} {:>15} {:>15}  Status",
+        "Bond", "Epoch", "Expected", "Paid", "Difference", "Carry"
+    );
+    for (bond_pubkey, bond) in bonds.iter() {
+        let destination = args
+            .payout_method
+            .destination(bond_pubkey, &bond.stake_account);
+        let transfers = fetch_payer_transfers(&client, &args, &destination, min_slot).await?;
+        let mut paid_by_epoch: BTreeMap<u64, u64> = BTreeMap::new();
+        let mut inferred = vec![];
+        for transfer in transfers {
+            let mut paid = attribute_transfer(&ledger, bond_pubkey, &transfer);
+            if paid.is_empty() {
+                // Transfers the ledger has no record of, e.g. made by hand or before the ledger
+                // was kept, are attributed from the transaction history alone.
+                let epoch = inferred_epoch(&epoch_schedule, &transfer);
+                paid.push((epoch, transfer.lamports));
+                inferred.push(transfer);
+            }
+            for (epoch, lamports) in paid {
+                *paid_by_epoch.entry(epoch).or_default() += lamports;
+            }
+        }
+
+        // Start from the balance the ledger carried into the first epoch, if any.
+        let mut carry = if args.carry_forward {
+            ledger
+                .get(bond_pubkey, *target_epochs.start())
+                .map(|record| record.carry_in)
+                .unwrap_or(0)
+        } else {
+            0
+        };
+        for target_epoch in target_epochs.clone() {
+            let vote_pubkey = bond.validator_vote_account;
+            if let Entry::Vacant(entry) = validator_epoch_data.entry((vote_pubkey, target_epoch)) {
+                let data = fetch_validator_epoch_data(
+                    &client,
+                    mev_provider.as_ref(),
+                    &args,
+                    &epoch_schedule,
+                    &vote_pubkey,
+                    target_epoch,
+                    current_epoch,
+                )
+                .await
+                .map_err(|e| e.to_string());
+                entry.insert(data);
+            }
+            let paid = paid_by_epoch.get(&target_epoch).copied().unwrap_or(0);
+            let is_inferred = inferred
+                .iter()
+                .any(|transfer| inferred_epoch(&epoch_schedule, transfer) == target_epoch);
+            let inferred_note = if is_inferred { ", inferred" } else { "" };
+            let expected = match &validator_epoch_data[&(vote_pubkey, target_epoch)] {
+                Ok((mev_data, validator_block_rewards)) => {
+                    calculate_bond_excess_rewards(
+                        &client,
+                        bond,
+                        mev_data,
+                        *validator_block_rewards,
+                        target_epoch,
+                        current_epoch,
+                        args.permissive,
+                    )
+                    .await
+                }
+                Err(e) => Err(anyhow!("{}", e)),
+            };
+            let expected = match expected {
+                Ok(rewards) => {
+                    let (amount, carry_out) = apply_carry_forward(rewards.total(), carry);
+                    if args.carry_forward {
+                        carry = carry_out;
+                    }
+                    amount
+                }
+                Err(e) => {
+                    warn!(
+                        "Could not compute excess rewards for bond {} epoch {}: {}",
+                        bond_pubkey, target_epoch, e
+                    );
+                    println!(
+                        "{:<44} {:>6} {:>15} {:>15} {:>15} {:>15}  Unknown ({}{})",
+                        bond_pubkey.to_string(),
+                        target_epoch,
+                        "-",
+                        paid,
+                        "-",
+                        carry,
+                        e,
+                        inferred_note
+                    );
+                    continue;
+                }
+            };
+            let status = reconcile_status(expected, paid);
+            let status_text = if is_inferred {
+                format!("{:?} (inferred)", status)
+            } else {
+                format!("{:?}", status)
+            };
+            println!(
+                "{:<44} {:>6} {:>15} {:>15} {:>15} {:>15}  {}",
+                bond_pubkey.to_string(),
+                target_epoch,
+                expected,
+                paid,
+                paid as i64 - expected as i64,
+                carry,
+                status_text
+            );
+            datapoint_info!(
+                "excess_reward_reconciliation",
+                (
+                    "vote_pubkey",
+                    bond.validator_vote_account.to_string(),
+                    String
+                ),
+                ("epoch", target_epoch.to_string(), String),
+                ("bond", bond_pubkey.to_string(), String),
+                ("expected", expected as i64, i64),
+                ("paid", paid as i64, i64),
+                ("carry_out", carry, i64),
+                ("status", format!("{:?}", status), String),
+                ("inferred", is_inferred, bool),
+            );
+        }
+        report_inferred_transfers(bond_pubkey, bond, &epoch_schedule, &inferred);
+    }
+    flush();
+    Ok(())
+}
+
+async fn fetch_validator_epoch_data(
+    client: &RpcClient,
+    mev_provider: &dyn MevDataProvider,
+    args: &ReconcileArgs,
+    epoch_schedule: &EpochSchedule,
+    vote_pubkey: &Pubkey,
+    target_epoch: u64,
+    current_epoch: u64,
+) -> Result<(ValidatorInfo, u64)> {
+    let mev_data = mev_provider
+        .fetch_validator_info(vote_pubkey, target_epoch)
+        .await?;
+    let mev_data = resolve_validator_active_stake(
+        client,
+        vote_pubkey,
+        mev_data,
+        args.stake.stake_denominator,
+        target_epoch,
+        current_epoch,
+    )
+    .await?;
+    let validator_block_rewards = or_zero_if_permissive(
+        calculate_block_rewards(
+            client,
+            vote_pubkey,
+            epoch_schedule,
+            target_epoch,
+            args.concurrency,
+            args.block_batch_size,
+            args.block_retry_delay,
+        )
+        .await,
+        "block rewards",
+        args.permissive,
+    )?;
+    Ok((mev_data, validator_block_rewards))
+}
+
+/// Attributes a transfer to the bond to the epochs its transaction paid for the bond, as
+/// `(epoch, lamports)`. A transaction paying several epochs of the bond is split by the amount
+/// it was recorded to pay for each epoch, the latest epoch getting whatever does not match them.
+/// Payments replaced by a `--force-repay` still count towards their epoch. A multisig
+/// payout whose proposal execution is not recorded yet is found through its proposal. Returns
+/// nothing if the ledger has no record of the transaction paying the bond.
+fn attribute_transfer(
+    ledger: &PayoutLedger,
+    bond_pubkey: &Pubkey,
+    transfer: &PayerTransfer,
+) -> Vec<(u64, u64)> {
+    let bond = bond_pubkey.to_string();
+    let mut records: Vec<&PayoutRecord> = ledger.find_by_signature(&transfer.signature);
+    if records.is_empty() {
+        records = transfer
+            .accounts
+            .iter()
+            .flat_map(|account| ledger.find_by_proposal(account))
+            .collect();
+    }
+    records.retain(|record| record.bond == bond);
+    records.sort_by_key(|record| record.epoch);
+
+    let mut remaining = transfer.lamports;
+    let mut paid: Vec<(u64, u64)> = records
+        .iter()
+        .map(|record| {
+            let amount = record
+                .amount_paid_by(&transfer.signature)
+                .unwrap_or(record.amount);
+            let lamports = amount.min(remaining);
+            remaining -= lamports;
+            (record.epoch, lamports)
+        })
+        .collect();
+    if let Some((_, lamports)) = paid.last_mut() {
+        *lamports += remaining;
+    }
+    paid
+}
+
+/// Epoch a transfer the ledger has no record of is assumed to pay for: the epoch before the
+/// one it landed in, as excess rewards are paid once their epoch has ended.
+fn inferred_epoch(epoch_schedule: &EpochSchedule, transfer: &PayerTransfer) -> u64 {
+    epoch_schedule.get_epoch(transfer.slot).saturating_sub(1)
+}
+
+/// Reports the transfers to the bond that the ledger has no record of, which were counted
+/// towards the epoch before the one they landed in.
+fn report_inferred_transfers(
+    bond_pubkey: &Pubkey,
+    bond: &SoloValidatorBond,
+    epoch_schedule: &EpochSchedule,
+    transfers: &[PayerTransfer],
+) {
+    for transfer in transfers {
+        let landing_epoch = epoch_schedule.get_epoch(transfer.slot);
+        println!(
+            "{:<44} {:>6} {:>15} {:>15} {:>15} {:>15}  Inferred (not on the ledger, landed in epoch {}: {})",
+            bond_pubkey.to_string(),
+            inferred_epoch(epoch_schedule, transfer),
+            "-",
+            transfer.lamports,
+            "-",
+            "-",
+            landing_epoch,
+            transfer.signature
+        );
+        datapoint_warn!(
+            "excess_reward_unattributed_transfer",
+            (
+                "vote_pubkey",
+                bond.validator_vote_account.to_string(),
+                String
+            ),
+            ("bond", bond_pubkey.to_string(), String),
+            ("landing_epoch", landing_epoch.to_string(), String),
+            (
+                "inferred_epoch",
+                inferred_epoch(epoch_schedule, transfer).to_string(),
+                String
+            ),
+            ("lamports", transfer.lamports as i64, i64),
+            ("signature", transfer.signature.clone(), String),
+        );
+    }
+}
+
+/// Scans the transaction history of the payouts' `destination` back to `min_slot`, or for at most
+/// `--max-signature-pages` pages, for transfers from the payer. The transactions are fetched
+/// concurrently.
+async fn fetch_payer_transfers(
+    client: &RpcClient,
+    args: &ReconcileArgs,
+    destination: &Pubkey,
+    min_slot: u64,
+) -> Result<Vec<PayerTransfer>> {
+    let payer_pubkey = &args.payer_pubkey;
+    let mut candidates = vec![];
+    let mut before = None;
+    let mut pages = 0;
+    loop {
+        let signatures = client
+            .get_signatures_for_address_with_config(
+                destination,
+                GetConfirmedSignaturesForAddress2Config {
+                    before,
+                    until: None,
+                    limit: Some(SIGNATURES_PAGE_SIZE),
+                    commitment: Some(CommitmentConfig::confirmed()),
+                },
+            )
+            .await
+            .map_err(|e| anyhow!("Failed to fetch signatures for {}: {}", destination, e))?;
+        pages += 1;
+        let last = match signatures.last() {
+            Some(last) => last,
+            None => break,
+        };
+        before = Some(Signature::from_str(&last.signature)?);
+        for status in signatures
+            .iter()
+            .filter(|status| status.err.is_none() && status.slot >= min_slot)
+        {
+            candidates.push((Signature::from_str(&status.signature)?, status.slot));
+        }
+        if last.slot < min_slot || signatures.len() < SIGNATURES_PAGE_SIZE {
+            break;
+        }
+        if pages >= args.max_signature_pages {
+            warn!(
+                "Stopped scanning the history of {} at slot {} after {} pages, before reaching slot {}: raise --max-signature-pages to find older transfers",
+                destination, last.slot, pages, min_slot
+            );
+            break;
+        }
+    }
+
+    let transactions = stream::iter(candidates)
+        .map(|(signature, slot)| async move {
+            let transaction = client
+                .get_transaction_with_config(
+                    &signature,
+                    RpcTransactionConfig {
+                        encoding: Some(UiTransactionEncoding::JsonParsed),
+                        commitment: Some(CommitmentConfig::confirmed()),
+                        max_supported_transaction_version: Some(0),
+                    },
+                )
+                .await
+                .map_err(|e| anyhow!("Failed to fetch transaction {}: {}", signature, e))?;
+            Ok(PayerTransfer {
+                signature: signature.to_string(),
+                slot,
+                lamports: sum_payer_transfers(&transaction.transaction, payer_pubkey, destination),
+                accounts: transaction_accounts(&transaction.transaction),
+            })
+        })
+        .buffered(args.concurrency)
+        .collect::<Vec<Result<PayerTransfer>>>()
+        .await;
+    let mut transfers = vec![];
+    for transfer in transactions {
+        let transfer = transfer?;
+        if transfer.lamports > 0 {
+            transfers.push(transfer);
+        }
+    }
+    info!(
+        "Found {} transfers from {} to {}",
+        transfers.len(),
+        payer_pubkey,
+        destination
+    );
+    Ok(transfers)
+}
+
+/// Sums the system transfers from the payer to the payouts' `destination` in a transaction,
+/// including transfers made through CPI.
+fn sum_payer_transfers(
+    transaction: &EncodedTransactionWithStatusMeta,
+    payer_pubkey: &Pubkey,
+    destination: &Pubkey,
+) -> u64 {
+    let mut instructions: Vec<&UiInstruction> = vec![];
+    if let EncodedTransaction::Json(ui_transaction) = &transaction.transaction {
+        if let UiMessage::Parsed(message) = &ui_transaction.message {
+            instructions.extend(message.instructions.iter());
+        }
+    }
+    if let Some(meta) = &transaction.meta {
+        if let OptionSerializer::Some(inner_instructions) = &meta.inner_instructions {
+            instructions.extend(
+                inner_instructions
+                    .iter()
+                    .flat_map(|ix| ix.instructions.iter()),
+            );
+        }
+    }
+    instructions
+        .into_iter()
+        .filter_map(|instruction| match instruction {
+            UiInstruction::Parsed(UiParsedInstruction::Parsed(parsed))
+                if parsed.program == "system" =>
+            {
+                transfer_lamports(&parsed.parsed, payer_pubkey, destination)
+            }
+            _ => None,
+        })
+        .sum()
+}
+
+/// Returns the static accounts of a transaction fetched as `JsonParsed`.
+fn transaction_accounts(transaction: &EncodedTransactionWithStatusMeta) -> Vec<String> {
+    match &transaction.transaction {
+        EncodedTransaction::Json(ui_transaction) => match &ui_transaction.message {
+            UiMessage::Parsed(message) => message
+                .account_keys
+                .iter()
+                .map(|account| account.pubkey.clone())
+                .collect(),
+            UiMessage::Raw(message) => message.account_keys.clone(),
+        },
+        _ => vec![],
+    }
+}
+
+/// Returns the lamports of a parsed system transfer instruction from `source` to `destination`.
+fn transfer_lamports(parsed: &Value, source: &Pubkey, destination: &Pubkey) -> Option<u64> {
+    let info = &parsed["info"];
+    if parsed["type"] == "transfer"
+        && info["source"] == source.to_string()
+        && info["destination"] == destination.to_string()
+    {
+        info["lamports"].as_u64()
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rewards::ExcessRewards;
+    use crate::test_utils::temp_ledger_path;
+    use serde_json::json;
+
+    #[test]
+    fn test_reconcile_status() {
+        assert_eq!(reconcile_status(0, 0), ReconcileStatus::Ok);
+        assert_eq!(reconcile_status(1_000, 1_000), ReconcileStatus::Ok);
+        assert_eq!(reconcile_status(1_000, 0), ReconcileStatus::Missing);
+        assert_eq!(reconcile_status(1_000, 400), ReconcileStatus::Underpaid);
+        assert_eq!(reconcile_status(1_000, 1_400), ReconcileStatus::Overpaid);
+        assert_eq!(reconcile_status(0, 400), ReconcileStatus::Overpaid);
+    }
+
+    #[test]
+    fn test_transfer_lamports() {
+        let payer = Pubkey::new_unique();
+        let bond = Pubkey::new_unique();
+        let transfer = json!({
+            "type": "transfer",
+            "info": {
+                "source": payer.to_string(),
+                "destination": bond.to_string(),
+                "lamports": 12_345,
+            },
+        });
+        assert_eq!(transfer_lamports(&transfer, &payer, &bond), Some(12_345));
+        assert_eq!(transfer_lamports(&transfer, &bond, &payer), None);
+
+        let create_account = json!({
+            "type": "createAccount",
+            "info": {
+                "source": payer.to_string(),
+                "newAccount": bond.to_string(),
+                "lamports": 12_345,
+            },
+        });
+        assert_eq!(transfer_lamports(&create_account, &payer, &bond), None);
+    }
+
+    #[test]
+    fn test_inferred_epoch() {
+        let epoch_schedule = EpochSchedule::without_warmup();
+        let transfer = |slot| PayerTransfer {
+            signature: Signature::new_unique().to_string(),
+            slot,
+            lamports: 1_000,
+            accounts: vec![],
+        };
+        let first_slot = epoch_schedule.get_first_slot_in_epoch(701);
+        assert_eq!(inferred_epoch(&epoch_schedule, &transfer(first_slot)), 700);
+        assert_eq!(
+            inferred_epoch(&epoch_schedule, &transfer(first_slot - 1)),
+            699
+        );
+    }
+
+    #[test]
+    fn test_attribute_transfer() {
+        let path = temp_ledger_path("reconcile-attribution");
+        let (bond, other_bond) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let vote = Pubkey::new_unique();
+        let (signature, proposal) = (Signature::new_unique(), Pubkey::new_unique());
+        let mut ledger = PayoutLedger::open(&path).unwrap();
+        // One transaction paying two epochs of the bond and an epoch of another bond.
+        for (bond, epoch, amount) in [(bond, 700, 300), (bond, 701, 700), (other_bond, 701, 500)] {
+            ledger
+                .record_pending(&bond, &vote, epoch, ExcessRewards::default(), 0, amount)
+                .unwrap();
+            ledger.record_confirmed(&bond, epoch, &signature).unwrap();
+        }
+        // A multisig payout whose proposal was executed without the ledger knowing yet.
+        ledger
+            .record_pending(&bond, &vote, 702, ExcessRewards::default(), 0, 900)
+            .unwrap();
+        ledger
+            .record_proposed(&bond, 702, &proposal, &Signature::new_unique())
+            .unwrap();
+
+        let transfer = |signature: Signature, lamports, accounts: &[Pubkey]| PayerTransfer {
+            signature: signature.to_string(),
+            slot: 0,
+            lamports,
+            accounts: accounts.iter().map(Pubkey::to_string).collect(),
+        };
+        assert_eq!(
+            attribute_transfer(&ledger, &bond, &transfer(signature, 1_000, &[])),
+            vec![(700, 300), (701, 700)]
+        );
+        assert_eq!(
+            attribute_transfer(&ledger, &other_bond, &transfer(signature, 500, &[])),
+            vec![(701, 500)]
+        );
+        // Lamports that do not match the recorded amounts go to the latest epoch.
+        assert_eq!(
+            attribute_transfer(&ledger, &bond, &transfer(signature, 1_100, &[])),
+            vec![(700, 300), (701, 800)]
+        );
+        assert_eq!(
+            attribute_transfer(&ledger, &bond, &transfer(signature, 200, &[])),
+            vec![(700, 200), (701, 0)]
+        );
+        let execution = Signature::new_unique();
+        assert_eq!(
+            attribute_transfer(&ledger, &bond, &transfer(execution, 900, &[vote, proposal])),
+            vec![(702, 900)]
+        );
+        assert!(
+            attribute_transfer(&ledger, &other_bond, &transfer(execution, 900, &[proposal]))
+                .is_empty()
+        );
+        assert!(attribute_transfer(&ledger, &bond, &transfer(execution, 900, &[])).is_empty());
+
+        // An epoch paid again with --force-repay counts both payments.
+        let (first, second) = (Signature::new_unique(), Signature::new_unique());
+        for (amount, signature) in [(300, first), (400, second)] {
+            ledger
+                .record_pending(&bond, &vote, 703, ExcessRewards::default(), 0, amount)
+                .unwrap();
+            ledger.record_confirmed(&bond, 703, &signature).unwrap();
+        }
+        assert_eq!(
+            attribute_transfer(&ledger, &bond, &transfer(first, 300, &[])),
+            vec![(703, 300)]
+        );
+        assert_eq!(
+            attribute_transfer(&ledger, &bond, &transfer(second, 400, &[])),
+            vec![(703, 400)]
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
+}
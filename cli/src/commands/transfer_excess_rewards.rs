@@ -1,137 +1,723 @@
 use crate::accounts::fetch_solo_validator_bond;
-use crate::active_stake::fetch_bond_active_stake;
+use crate::approval::{Approval, ApprovalArgs, Approver};
+use crate::commands::offline::{ExportedPayouts, ExportedTransaction};
+use crate::commands::{bond_outstanding_at, fetch_epoch_end_time, resolve_target_epochs};
+use crate::ledger::{apply_carry_forward, PayoutLedger};
 use crate::metrics_helpers::*;
-use crate::rewards::block_rewards::calculate_excess_block_reward;
-use crate::rewards::inflation_rewards::calculate_excess_inflation_reward;
-use crate::rewards::mev_rewards::{calculate_excess_mev_reward, fetch_and_filter_mev_data};
-use crate::transactions::transfer_excess_rewards;
+use crate::multisig::{check_proposals, propose_payouts};
+use crate::providers::{ChainDataProvider, MevDataProvider, MevSourceArgs};
+use crate::rewards::block_rewards::calculate_block_rewards;
+use crate::rewards::{
+    calculate_bond_excess_rewards, or_zero_if_permissive, resolve_validator_active_stake,
+    ExcessRewards, StakeDenominatorArgs,
+};
+use crate::rpc_utils::new_rpc_client;
+use crate::sender::{SendOutcome, TransactionExpiry};
+use crate::signer::pubkey_from_uri;
+use crate::transactions::{
+    batch_bond_transfers, build_payout_transactions, prepare_payouts, BondTransfer, PayoutSigners,
+    TransactionArgs,
+};
 use anchor_client::Cluster;
 use anyhow::{anyhow, Result};
-use dialoguer::Confirm;
-use log::info;
-use solana_client::nonblocking::rpc_client::RpcClient;
-use solana_metrics::{datapoint_info, flush};
-use solana_sdk::{commitment_config::CommitmentConfig, pubkey::Pubkey};
+use log::{info, warn};
+use pye_core_cpi::pye_core::accounts::SoloValidatorBond;
+use solana_metrics::{datapoint_error, datapoint_info, flush};
+use solana_sdk::{epoch_schedule::EpochSchedule, pubkey::Pubkey};
+use std::collections::BTreeMap;
 use std::str::FromStr;
 
 pub struct TransferExcessRewardsArgs {
     pub rpc: String,
-    pub payer_file_path: String,
+    pub payer: String,
     pub bond: String,
     pub concurrency: usize,
     pub dry_run: bool,
     pub block_retry_delay: u64,
+    pub block_batch_size: usize,
+    pub ledger_path: String,
+    pub force_repay: bool,
+    pub epoch: Option<u64>,
+    pub from_epoch: Option<u64>,
+    pub to_epoch: Option<u64>,
+    pub carry_forward: bool,
+    pub mev: MevSourceArgs,
+    pub stake: StakeDenominatorArgs,
+    pub permissive: bool,
+    pub transactions: TransactionArgs,
+    pub approval: ApprovalArgs,
+    /// Write unsigned payout transactions to this file instead of sending them.
+    pub export_path: Option<String>,
+}
+
+/// A payout left to be signed offline, recorded in the ledger once it is exported.
+struct ExportedPayout {
+    epoch: u64,
+    rewards: ExcessRewards,
+    carry_in: i64,
+    amount: u64,
 }
 
 pub async fn handle_transfer_excess_rewards(args: TransferExcessRewardsArgs) -> Result<()> {
-    let client = RpcClient::new_with_commitment(args.rpc.clone(), CommitmentConfig::confirmed());
+    args.stake.check(&args.mev)?;
+    let client = new_rpc_client(args.rpc.clone());
+    let mev_provider = args.mev.mev_data_provider(&args.rpc);
+    if !args.dry_run {
+        // Payouts sent without finding out whether they landed block their epoch until checked,
+        // and proposed payouts until their proposal is executed or rejected.
+        let mut ledger = PayoutLedger::open(&args.ledger_path)?;
+        ledger.check_pending(&client).await?;
+        check_proposals(
+            &client,
+            &args.transactions.multisig.squads_program_id,
+            &mut ledger,
+        )
+        .await?;
+    }
+    process_transfer_excess_rewards(&args, &client, mev_provider.as_ref()).await?;
+    Ok(())
+}
+
+/// Calculates and pays the bond's excess rewards for every target epoch, returning the rewards
+/// computed for each epoch that was not skipped.
+pub async fn process_transfer_excess_rewards(
+    args: &TransferExcessRewardsArgs,
+    client: &dyn ChainDataProvider,
+    mev_provider: &dyn MevDataProvider,
+) -> Result<BTreeMap<u64, ExcessRewards>> {
     let bond_pubkey = Pubkey::from_str(&args.bond).map_err(|e| anyhow!("Invalid Bond: {}", e))?;
 
     // Fetch RewardCommissions configured on SoloValidatorBond.
-    let bond = fetch_solo_validator_bond(&client, &bond_pubkey).await?;
+    let bond = fetch_solo_validator_bond(client, &bond_pubkey).await?;
     let reward_commissions = bond.reward_commissions.clone();
     info!("Current: {:?}", reward_commissions);
 
     // Fetch the current Solana Network epoch.
     let epoch_info = client.get_epoch_info().await?;
+    let epoch_schedule = client.get_epoch_schedule().await?;
     let current_epoch = epoch_info.epoch;
-    let target_epoch = current_epoch - 1;
+    let target_epochs =
+        resolve_target_epochs(args.epoch, args.from_epoch, args.to_epoch, current_epoch)?;
     println!("Current epoch: {}\n", current_epoch);
-    log_reward_commissions(target_epoch, &bond_pubkey, &reward_commissions);
 
-    // Fetch info about MEV rewards for target epoch from Jito's API.
-    let mev_data = fetch_and_filter_mev_data(&bond.validator_vote_account, target_epoch).await?;
-    log_validator_mev_data(target_epoch, &mev_data);
+    let mut ledger = PayoutLedger::open(&args.ledger_path)?;
+    let mut approver = Approver::new(&args.approval, &ledger)?;
+    let mut held = vec![];
+    // Resolved on the first payout, so dry runs and exports don't need the payer's key.
+    let mut signers = None;
+    let mut computed = BTreeMap::new();
+    let mut exported = vec![];
+    // Exported payouts are only recorded once they are all built, so the balance carried between
+    // the epochs of this run is tracked here.
+    let mut export_carry = None;
+    for target_epoch in target_epochs {
+        println!("Target epoch: {}\n", target_epoch);
+        if let Some(record) = ledger.blocking_record(&bond_pubkey, target_epoch) {
+            if !args.force_repay && !args.dry_run {
+                info!(
+                    "SoloValidatorBond {} already has a {:?} payout of {} lamports for epoch {} (signature: {:?}). Pass --force-repay to pay again.",
+                    bond_pubkey, record.status, record.amount, target_epoch, record.signature
+                );
+                continue;
+            }
+        }
+        // Backfilled epochs can predate the bond's issuance or follow its maturity, which can only
+        // be told from the epoch's actual end time.
+        let rewards = match fetch_epoch_end_time(client, &epoch_schedule, target_epoch).await {
+            Ok(epoch_end_ts) if !bond_outstanding_at(&bond, epoch_end_ts) => {
+                info!(
+                    "SoloValidatorBond {} was not outstanding at the end of epoch {} (issued at {}, maturing at {}), skipping",
+                    bond_pubkey, target_epoch, bond.issuance_ts, bond.maturity_ts
+                );
+                continue;
+            }
+            Ok(_) => {
+                log_reward_commissions(target_epoch, &bond_pubkey, &reward_commissions);
+                calculate_epoch_excess_rewards(
+                    args,
+                    client,
+                    mev_provider,
+                    &bond,
+                    &epoch_schedule,
+                    target_epoch,
+                    current_epoch,
+                )
+                .await
+            }
+            Err(e) => Err(e),
+        };
+        let rewards = match rewards {
+            Ok(rewards) => rewards,
+            Err(e) => {
+                datapoint_error!(
+                    "excess_reward_calculation_failure",
+                    ("vote_pubkey", bond.validator_vote_account.to_string(), String),
+                    ("epoch", target_epoch.to_string(), String),
+                    ("bond", bond_pubkey.to_string(), String),
+                    ("error", e.to_string(), String),
+                );
+                if !args.dry_run {
+                    ledger.record_calculation_failed(
+                        &bond_pubkey,
+                        &bond.validator_vote_account,
+                        target_epoch,
+                        &e.to_string(),
+                        None,
+                    )?;
+                }
+                return Err(anyhow!(
+                    "Failed to calculate excess rewards for epoch {}: {}",
+                    target_epoch,
+                    e
+                ));
+            }
+        };
+        let excess_rewards = rewards.total();
+        computed.insert(target_epoch, rewards);
+        println!("Total Excess Rewards: {}\n", excess_rewards);
+
+        // Net the epoch's excess rewards against any negative balance carried from previous
+        // epochs.
+        let carry_in = match (args.carry_forward, export_carry) {
+            (false, _) => 0,
+            (true, Some(carry)) => carry,
+            (true, None) => ledger.carry_balance(&bond_pubkey, target_epoch)?,
+        };
+        let (amount, carry_out) = apply_carry_forward(excess_rewards, carry_in);
+        if args.export_path.is_some() {
+            export_carry = Some(carry_out);
+        }
+        if args.carry_forward {
+            println!(
+                "Carried In: {}, Amount To Transfer: {}, Carried Out: {}\n",
+                carry_in, amount, carry_out
+            );
+        }
+
+        datapoint_info!(
+            "excess_reward",
+            (
+                "vote_pubkey",
+                bond.validator_vote_account.to_string(),
+                String
+            ),
+            ("epoch", target_epoch.to_string(), String),
+            ("bond", bond_pubkey.to_string(), String),
+            ("bond_active_stake", rewards.bond_active_stake as i64, i64),
+            ("excess_inflation_rewards", rewards.inflation, i64),
+            ("excess_mev_rewards", rewards.mev, i64),
+            ("excess_block_rewards", rewards.block, i64),
+            ("total_excess_rewards", excess_rewards, i64),
+            ("carry_in", carry_in, i64),
+            ("carry_out", carry_out, i64),
+        );
+        flush();
+
+        if args.dry_run {
+            info!("Dry run complete for epoch {}", target_epoch);
+            continue;
+        }
+
+        if amount == 0 {
+            info!(
+                "No excess rewards to transfer to SoloValidatorBond for epoch {}\n",
+                target_epoch
+            );
+            if args.carry_forward {
+                ledger.record_carried_forward(
+                    &bond_pubkey,
+                    &bond.validator_vote_account,
+                    target_epoch,
+                    rewards,
+                    carry_in,
+                    carry_out,
+                )?;
+            }
+            continue;
+        }
+
+        // The transaction is built and simulated before asking, so what is approved is what is
+        // sent. With a durable nonce it stays valid however long the approval takes. Multisig
+        // payouts are checked when they are proposed, and exported payouts are built once they
+        // are all approved.
+        let transfer = BondTransfer::new(&bond_pubkey, &bond, amount);
+        let prepared = match (&args.export_path, args.transactions.multisig.multisig) {
+            (None, None) => {
+                let signers = payout_signers(&mut signers, args).await?;
+                let cluster = Cluster::Custom(args.rpc.clone(), args.rpc.replace("http", "ws"));
+                Some(
+                    prepare_payouts(signers, cluster, vec![vec![transfer]], &args.transactions)
+                        .await
+                        .map_err(|e| anyhow!("Failed to transfer excess rewards: {}", e))?,
+                )
+            }
+            _ => None,
+        };
+        let prompt = format!(
+            "Transfer {} lamports in excess rewards for epoch {} to SoloValidatorBond at {}?",
+            amount, target_epoch, bond_pubkey
+        );
+        let approval = approver.approve(&bond_pubkey, target_epoch, amount, &prompt)?;
+        if approval == Approval::Approved && args.export_path.is_some() {
+            exported.push(ExportedPayout {
+                epoch: target_epoch,
+                rewards,
+                carry_in,
+                amount,
+            });
+        } else if approval == Approval::Approved {
+            ledger.record_pending(
+                &bond_pubkey,
+                &bond.validator_vote_account,
+                target_epoch,
+                rewards,
+                carry_in,
+                amount,
+            )?;
+            let (outcome, proposal) = match &prepared {
+                Some(prepared) => (prepared.send(0, &args.transactions.sender).await, None),
+                None => match propose_payouts(
+                    payout_signers(&mut signers, args).await?,
+                    &args.rpc,
+                    vec![vec![transfer]],
+                    &args.transactions,
+                )
+                .await
+                {
+                    Ok(mut proposed) => {
+                        let proposed = proposed.remove(0);
+                        (proposed.outcome, Some(proposed.proposal))
+                    }
+                    Err(e) => (
+                        SendOutcome::Dropped {
+                            signatures: vec![],
+                            error: e.to_string(),
+                        },
+                        None,
+                    ),
+                },
+            };
+            ledger.record_send_outcome(&bond_pubkey, target_epoch, &outcome, proposal.as_ref())?;
+            if !matches!(outcome, SendOutcome::Confirmed { .. }) {
+                return Err(anyhow!("Failed to transfer excess rewards: {:?}", outcome));
+            }
+        } else if let Approval::Held(reason) = approval {
+            warn!(
+                "Held the transfer of {} lamports for epoch {}: {}",
+                amount, target_epoch, reason
+            );
+            datapoint_info!(
+                "excess_reward_held",
+                (
+                    "vote_pubkey",
+                    bond.validator_vote_account.to_string(),
+                    String
+                ),
+                ("epoch", target_epoch.to_string(), String),
+                ("bond", bond_pubkey.to_string(), String),
+                ("amount", amount as i64, i64),
+                ("reason", reason, String),
+            );
+            ledger.record_pending(
+                &bond_pubkey,
+                &bond.validator_vote_account,
+                target_epoch,
+                rewards,
+                carry_in,
+                amount,
+            )?;
+            ledger.record_held(&bond_pubkey, target_epoch, &reason)?;
+            held.push((target_epoch, amount, reason));
+        } else {
+            info!(
+                "Aborted: user declined to transfer excess rewards for epoch {}.",
+                target_epoch
+            );
+        }
+    }
+
+    if !held.is_empty() {
+        println!(
+            "Held {} payouts to SoloValidatorBond at {} above the approval policy:",
+            held.len(),
+            bond_pubkey
+        );
+        for (epoch, amount, reason) in &held {
+            println!("  epoch {}: {} lamports ({})", epoch, amount, reason);
+        }
+        println!("Run again without --yes to confirm them, or raise the policy's thresholds.");
+    }
+
+    if let Some(export_path) = &args.export_path {
+        export_payouts(
+            args,
+            client,
+            &bond_pubkey,
+            &bond,
+            &mut ledger,
+            exported,
+            export_path,
+        )
+        .await?;
+    }
+    Ok(computed)
+}
+
+/// Returns the payout signers, resolving them on first use.
+async fn payout_signers<'a>(
+    signers: &'a mut Option<PayoutSigners>,
+    args: &TransferExcessRewardsArgs,
+) -> Result<&'a PayoutSigners> {
+    Ok(match signers.take() {
+        Some(resolved) => signers.insert(resolved),
+        None => signers.insert(PayoutSigners::resolve(&args.payer, &args.transactions).await?),
+    })
+}
 
-    // Fetch the SoloValidatorBond's active stake during target epoch.
-    let bond_active_stake = fetch_bond_active_stake(
-        &client,
-        &bond.stake_account,
-        &bond.transient_stake_account,
+/// Builds unsigned, durable-nonce payout transactions for `payouts`, writes them to
+/// `export_path` and records the payouts as pending in the ledger until their nonce is advanced.
+async fn export_payouts(
+    args: &TransferExcessRewardsArgs,
+    client: &dyn ChainDataProvider,
+    bond_pubkey: &Pubkey,
+    bond: &SoloValidatorBond,
+    ledger: &mut PayoutLedger,
+    payouts: Vec<ExportedPayout>,
+    export_path: &str,
+) -> Result<()> {
+    if payouts.is_empty() {
+        info!("No payouts to export");
+        return Ok(());
+    }
+    let payer_pubkey =
+        pubkey_from_uri(&args.payer, "payer", &args.transactions.remote_signer).await?;
+    // The nonce authority signs offline, so only its pubkey on the nonce account matters here.
+    let nonces = args.transactions.nonce.fetch_nonces(client, None).await?;
+
+    let transfers = payouts
+        .iter()
+        .map(|payout| BondTransfer::new(bond_pubkey, bond, payout.amount))
+        .collect();
+    let batches =
+        batch_bond_transfers(transfers, usize::MAX, args.transactions.payout_method, true);
+    // Signed transactions cannot be re-signed with a fresh nonce, so none may share an account.
+    if nonces.len() < batches.len() {
+        return Err(anyhow!(
+            "Exporting {} transactions needs as many --nonce-account, got {}",
+            batches.len(),
+            nonces.len()
+        ));
+    }
+    let rpc = new_rpc_client(args.rpc.clone());
+    let payout_transactions =
+        build_payout_transactions(&rpc, &payer_pubkey, batches, &args.transactions, &nonces)
+            .await?;
+
+    let mut epochs = payouts.iter().map(|payout| payout.epoch);
+    // Each payout can land until the nonce its transaction was exported with is advanced.
+    let mut expiries = BTreeMap::new();
+    let transactions = payout_transactions
+        .iter()
+        .map(|transaction| {
+            let batch_epochs: Vec<u64> =
+                epochs.by_ref().take(transaction.transfers.len()).collect();
+            let exported = ExportedTransaction::new(
+                transaction,
+                &batch_epochs,
+                args.transactions.payout_method,
+            )?;
+            for epoch in batch_epochs {
+                expiries.insert(
+                    epoch,
+                    TransactionExpiry::Nonce {
+                        account: exported.nonce_account.clone(),
+                        nonce: transaction.message.recent_blockhash.to_string(),
+                    },
+                );
+            }
+            Ok(exported)
+        })
+        .collect::<Result<Vec<_>>>()?;
+    ExportedPayouts { transactions }.write(export_path)?;
+
+    for payout in payouts {
+        ledger.record_pending(
+            bond_pubkey,
+            &bond.validator_vote_account,
+            payout.epoch,
+            payout.rewards,
+            payout.carry_in,
+            payout.amount,
+        )?;
+        if let Some(expiry) = expiries.remove(&payout.epoch) {
+            ledger.record_exported(bond_pubkey, payout.epoch, expiry)?;
+        }
+    }
+    println!(
+        "Exported {} unsigned transactions to {}\n",
+        payout_transactions.len(),
+        export_path
+    );
+    Ok(())
+}
+
+/// Fetches the validator's MEV data and block rewards for `target_epoch` and calculates the
+/// bond's excess rewards. Unless `args.permissive` is set, any reward component that cannot be
+/// computed fails the whole calculation.
+async fn calculate_epoch_excess_rewards(
+    args: &TransferExcessRewardsArgs,
+    client: &dyn ChainDataProvider,
+    mev_provider: &dyn MevDataProvider,
+    bond: &SoloValidatorBond,
+    epoch_schedule: &EpochSchedule,
+    target_epoch: u64,
+    current_epoch: u64,
+) -> Result<ExcessRewards> {
+    // Fetch info about MEV rewards for target epoch.
+    let mev_data = mev_provider
+        .fetch_validator_info(&bond.validator_vote_account, target_epoch)
+        .await?;
+    log_validator_mev_data(target_epoch, &mev_data);
+    let mev_data = resolve_validator_active_stake(
+        client,
+        &bond.validator_vote_account,
+        mev_data,
+        args.stake.stake_denominator,
         target_epoch,
         current_epoch,
     )
     .await?;
 
-    // Calculate the excess inflation reward to be refunded by validator to SoloValidatorBond.
-    let excess_inflation_reward = calculate_excess_inflation_reward(
-        &client,
-        &bond.stake_account,
-        &bond.transient_stake_account,
+    // Fetch the block rewards the validator earned during target epoch.
+    let validator_block_rewards = or_zero_if_permissive(
+        calculate_block_rewards(
+            client,
+            &bond.validator_vote_account,
+            epoch_schedule,
+            target_epoch,
+            args.concurrency,
+            args.block_batch_size,
+            args.block_retry_delay,
+        )
+        .await,
+        "block rewards",
+        args.permissive,
+    )?;
+
+    calculate_bond_excess_rewards(
+        client,
+        bond,
+        &mev_data,
+        validator_block_rewards,
         target_epoch,
-        &reward_commissions,
+        current_epoch,
+        args.permissive,
     )
-    .await;
+    .await
+}
 
-    // Calculate the excess MEV reward to be refunded by validator to SoloValidatorBond.
-    let excess_mev_commission =
-        calculate_excess_mev_reward(&mev_data, bond_active_stake, &reward_commissions);
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ledger::PayoutStatus;
+    use crate::rewards::StakeDenominator;
+    use crate::test_utils::{
+        fixture, mev_source_args, temp_ledger_path, transaction_args, TARGET_EPOCH,
+    };
+    use solana_sdk::signature::Signature;
 
-    // Calculate the excess block reward to be refunded by validator to SoloValidatorBond.
-    let excess_block_commission = calculate_excess_block_reward(
-        &client,
-        &bond.validator_vote_account,
-        &epoch_info,
-        bond_active_stake,
-        mev_data.active_stake,
-        &reward_commissions,
-        args.concurrency,
-        args.block_retry_delay,
-    )
-    .await?;
+    fn args(
+        bond_pubkey: &Pubkey,
+        ledger_path: &std::path::Path,
+        dry_run: bool,
+    ) -> TransferExcessRewardsArgs {
+        TransferExcessRewardsArgs {
+            rpc: "http://localhost:8899".to_string(),
+            payer: "payer.json".to_string(),
+            bond: bond_pubkey.to_string(),
+            concurrency: 4,
+            dry_run,
+            block_retry_delay: 0,
+            block_batch_size: 2,
+            ledger_path: ledger_path.to_string_lossy().to_string(),
+            force_repay: false,
+            epoch: None,
+            from_epoch: None,
+            to_epoch: None,
+            carry_forward: false,
+            mev: mev_source_args(),
+            stake: StakeDenominatorArgs {
+                stake_denominator: StakeDenominator::Mev,
+            },
+            permissive: false,
+            transactions: transaction_args(),
+            approval: ApprovalArgs {
+                yes: false,
+                approval_policy: None,
+            },
+            export_path: None,
+        }
+    }
 
-    let excess_rewards = excess_inflation_reward + excess_block_commission + excess_mev_commission;
-    println!("Total Excess Rewards: {}\n", excess_rewards);
-
-    datapoint_info!(
-        "excess_reward",
-        (
-            "vote_pubkey",
-            bond.validator_vote_account.to_string(),
-            String
-        ),
-        ("epoch", target_epoch.to_string(), String),
-        ("bond", bond_pubkey.to_string(), String),
-        ("bond_active_stake", bond_active_stake as i64, i64),
-        ("excess_inflation_rewards", excess_inflation_reward, i64),
-        ("excess_mev_rewards", excess_mev_commission, i64),
-        ("excess_block_rewards", excess_block_commission, i64),
-        ("total_excess_rewards", excess_rewards, i64),
-    );
-    flush();
+    #[tokio::test]
+    async fn test_dry_run_computes_excess_rewards() {
+        let fixture = fixture();
+        let ledger_path = temp_ledger_path("dry-run");
+        let computed = process_transfer_excess_rewards(
+            &args(&fixture.bond_pubkey, &ledger_path, true),
+            &fixture.chain,
+            &fixture.mev,
+        )
+        .await
+        .unwrap();
 
-    if excess_rewards <= 0 {
-        info!(
-            "No excess rewards to transfer to SoloValidatorBond for epoch {}\n",
-            target_epoch
+        assert_eq!(
+            computed[&TARGET_EPOCH],
+            ExcessRewards {
+                bond_active_stake: 100_000_000_000,
+                // 10% of a 50,000,000 reward taken instead of 5%.
+                inflation: 2_500_000,
+                // 10% of the bond's 1 SOL share of MEV taken instead of 5%.
+                mev: 50_000_000,
+                // 100% of the bond's 0.2 SOL share of block fees taken instead of 50%.
+                block: 100_000_000,
+            }
         );
-        return Ok(());
+        assert_eq!(computed[&TARGET_EPOCH].total(), 152_500_000);
+        assert!(!ledger_path.exists());
     }
 
-    if args.dry_run {
-        info!("Dry run complete");
-        return Ok(());
+    #[tokio::test]
+    async fn test_network_stake_denominator() {
+        let mut fixture = fixture();
+        // Only 500 SOL is actually delegated to the validator, against the 1,000 SOL in the MEV data.
+        fixture.chain = fixture.chain.with_stake_account(
+            Pubkey::new_unique(),
+            &fixture.vote_pubkey,
+            400_000_000_000,
+        );
+        let ledger_path = temp_ledger_path("network-stake");
+        let mut args = args(&fixture.bond_pubkey, &ledger_path, true);
+        args.stake.stake_denominator = StakeDenominator::Network;
+        let computed = process_transfer_excess_rewards(&args, &fixture.chain, &fixture.mev)
+            .await
+            .unwrap();
+
+        // The bond's share of MEV and block rewards doubles.
+        assert_eq!(computed[&TARGET_EPOCH].mev, 100_000_000);
+        assert_eq!(computed[&TARGET_EPOCH].block, 200_000_000);
+        assert_eq!(computed[&TARGET_EPOCH].inflation, 2_500_000);
     }
 
-    if Confirm::new()
-        .with_prompt(format!(
-            "Transfer {} lamports in excess rewards to SoloValidatorBond at {}?",
-            excess_rewards, bond_pubkey
-        ))
-        .interact()?
-    {
-        let cluster = Cluster::Custom(args.rpc.clone(), args.rpc.replace("http", "ws"));
-        transfer_excess_rewards(
-            args.payer_file_path,
-            cluster,
-            &bond_pubkey,
-            &bond,
-            u64::try_from(excess_rewards)?,
+    #[tokio::test]
+    async fn test_network_stake_denominator_needs_every_stake_account() {
+        let mut fixture = fixture();
+        // The validator's 10% commission earned 10,000,000 lamports, so its stakers were paid
+        // 90,000,000, of which the bond's stake account only got 45,000,000.
+        fixture.chain = fixture.chain.with_inflation_reward(
+            fixture.vote_pubkey,
+            TARGET_EPOCH,
+            10_000_000,
+            10_000_000,
+            10,
+        );
+        let ledger_path = temp_ledger_path("missing-stake");
+        let mut args = args(&fixture.bond_pubkey, &ledger_path, true);
+        args.stake.stake_denominator = StakeDenominator::Network;
+        assert!(
+            process_transfer_excess_rewards(&args, &fixture.chain, &fixture.mev)
+                .await
+                .is_err()
+        );
+
+        // The other stake account still delegated to the validator was paid the rest.
+        let stake_account = Pubkey::new_unique();
+        fixture.chain = fixture
+            .chain
+            .with_stake_account(stake_account, &fixture.vote_pubkey, 100_045_000_000)
+            .with_inflation_reward(stake_account, TARGET_EPOCH, 45_000_000, 100_045_000_000, 10);
+        let computed = process_transfer_excess_rewards(&args, &fixture.chain, &fixture.mev)
+            .await
+            .unwrap();
+        // The bond holds half of the validator's stake.
+        assert_eq!(computed[&TARGET_EPOCH].mev, 250_000_000);
+    }
+
+    #[tokio::test]
+    async fn test_backfill_skips_epochs_outside_bond_term() {
+        let mut fixture = fixture();
+        let mut bond = fixture.bond;
+        bond.issuance_ts = 1_000;
+        let schedule = fixture.chain.epoch_schedule().clone();
+        fixture.chain = fixture
+            .chain
+            .with_bond(fixture.bond_pubkey, &bond)
+            .with_block_time(schedule.get_last_slot_in_epoch(TARGET_EPOCH - 1), 900)
+            .with_block_time(schedule.get_last_slot_in_epoch(TARGET_EPOCH), 1_100);
+        let ledger_path = temp_ledger_path("term");
+        let mut args = args(&fixture.bond_pubkey, &ledger_path, true);
+        args.from_epoch = Some(TARGET_EPOCH - 1);
+        let computed = process_transfer_excess_rewards(&args, &fixture.chain, &fixture.mev)
+            .await
+            .unwrap();
+
+        // The bond was issued during the target epoch: the epoch before is not owed anything.
+        assert_eq!(computed.keys().copied().collect::<Vec<_>>(), [TARGET_EPOCH]);
+    }
+
+    #[tokio::test]
+    async fn test_skips_epoch_already_paid() {
+        let fixture = fixture();
+        let ledger_path = temp_ledger_path("paid");
+        let mut ledger = PayoutLedger::open(&ledger_path).unwrap();
+        ledger
+            .record_pending(
+                &fixture.bond_pubkey,
+                &fixture.vote_pubkey,
+                TARGET_EPOCH,
+                ExcessRewards::default(),
+                0,
+                152_500_000,
+            )
+            .unwrap();
+        ledger
+            .record_confirmed(&fixture.bond_pubkey, TARGET_EPOCH, &Signature::default())
+            .unwrap();
+
+        let computed = process_transfer_excess_rewards(
+            &args(&fixture.bond_pubkey, &ledger_path, false),
+            &fixture.chain,
+            &fixture.mev,
         )
         .await
-        .map_err(|e| anyhow!("Failed to transfer excess rewards: {}", e))
-    } else {
-        info!("Aborted: user declined to transfer excess rewards.");
-        Ok(())
+        .unwrap();
+        assert!(computed.is_empty());
+        std::fs::remove_file(&ledger_path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_held_payout_is_not_exported() {
+        let fixture = fixture();
+        let ledger_path = temp_ledger_path("held-export");
+        let policy_path = ledger_path.with_extension("policy.json");
+        std::fs::write(&policy_path, r#"{"max_bond_payout": 100000000}"#).unwrap();
+        let export_path = ledger_path.with_extension("export.json");
+        let mut args = args(&fixture.bond_pubkey, &ledger_path, false);
+        args.approval = ApprovalArgs {
+            yes: true,
+            approval_policy: Some(policy_path.to_string_lossy().to_string()),
+        };
+        args.export_path = Some(export_path.to_string_lossy().to_string());
+
+        process_transfer_excess_rewards(&args, &fixture.chain, &fixture.mev)
+            .await
+            .unwrap();
+        let ledger = PayoutLedger::open(&ledger_path).unwrap();
+        let record = ledger.get(&fixture.bond_pubkey, TARGET_EPOCH).unwrap();
+        assert_eq!(record.status, PayoutStatus::Held);
+        assert_eq!(record.amount, 152_500_000);
+        assert!(!export_path.exists());
+        std::fs::remove_file(&policy_path).unwrap();
+        std::fs::remove_file(&ledger_path).unwrap();
     }
 }
@@ -1,27 +1,44 @@
-use std::{sync::Arc, time::Duration};
+use std::{
+    collections::{BTreeMap, HashMap},
+    str::FromStr,
+    sync::Arc,
+    time::Duration,
+};
 
 use anchor_client::Cluster;
 use anyhow::{anyhow, Result};
 use clap::Parser;
 use futures::stream::{self, StreamExt};
-use log::info;
+use log::{error, info, warn};
 use pye_core_cpi::pye_core::accounts::SoloValidatorBond;
+use serde::Deserialize;
 use solana_client::nonblocking::rpc_client::RpcClient;
-use solana_commitment_config::CommitmentConfig;
-use solana_metrics::{datapoint_error, datapoint_info, flush};
-use solana_sdk::pubkey::Pubkey;
+use solana_metrics::{datapoint_error, datapoint_info, datapoint_warn, flush};
+use solana_sdk::{epoch_schedule::EpochSchedule, pubkey::Pubkey};
 
 use crate::{
-    accounts::fetch_active_solo_validator_bonds_by_vote_key_and_issuer,
-    active_stake::fetch_bond_active_stake,
+    accounts::{
+        fetch_active_solo_validator_bonds_by_vote_key,
+        fetch_active_solo_validator_bonds_by_vote_key_and_issuer, fetch_solo_validator_bond,
+        fetch_solo_validator_bonds_by_vote_key, fetch_solo_validator_bonds_by_vote_key_and_issuer,
+    },
+    commands::{
+        bond_outstanding_at, fetch_epoch_end_time, resolve_resume_epoch, resolve_target_epochs,
+    },
+    ledger::{apply_carry_forward, retry_backoff, PayoutLedger},
     metrics_helpers::{log_reward_commissions, log_validator_mev_data},
+    multisig::{check_proposals, propose_payouts},
+    providers::{shared::SharedChainData, ChainDataProvider, MevDataProvider, MevSourceArgs},
     rewards::{
-        block_rewards::{calculate_block_rewards, compute_excess_block_commission},
-        inflation_rewards::calculate_excess_inflation_reward,
-        mev_rewards::{calculate_excess_mev_reward, fetch_and_filter_mev_data},
+        block_rewards::calculate_block_rewards, calculate_bond_excess_rewards,
+        mev_rewards::ValidatorInfo, or_zero_if_permissive, resolve_validator_active_stake,
+        StakeDenominatorArgs,
+    },
+    rpc_utils::{new_rpc_client, wait_for_next_epoch},
+    sender::SendOutcome,
+    transactions::{
+        batch_bond_transfers, prepare_payouts, BondTransfer, PayoutSigners, TransactionArgs,
     },
-    rpc_utils::wait_for_next_epoch,
-    transactions::transfer_excess_rewards,
 };
 
 #[derive(Clone, Debug, Parser)]
@@ -41,18 +58,43 @@ pub struct ValidatorBondManagerArgs {
         default_value = "PYEQZ2qYHPQapnw8Ms8MSPMNzoq59NHHfNwAtuV26wx"
     )]
     program_id: Pubkey,
-    /// Validator's vote accoutn
-    #[arg(short, long, env)]
-    vote_pubkey: Pubkey,
-    /// Restricts bond payments to only bonds issued by pubkeys in this list.
+    /// Vote accounts of the validators whose bonds are paid. Can be given several times (or
+    /// comma-separated).
+    #[arg(
+        short,
+        long,
+        env,
+        value_delimiter = ',',
+        required_unless_present = "validators"
+    )]
+    vote_pubkey: Vec<Pubkey>,
+    /// Path to a JSON list of validators to manage, each with its `vote_pubkey` and optionally
+    /// its own `payer` and `issuers`. Adds to `--vote-pubkey`.
+    #[arg(long, env)]
+    validators: Option<String>,
+    /// Restricts bond payments to only bonds issued by pubkeys in this list, for the validators
+    /// without their own `issuers`.
     #[arg(short, long, env, value_delimiter = ',')]
     issuers: Vec<Pubkey>,
-    /// Path to payer keypair
+    /// Pays the bonds of every issuer instead of an `--issuers` allow-list, for the validators
+    /// without their own `issuers`.
+    #[arg(long, env, conflicts_with = "issuers")]
+    all_issuers: bool,
+    /// Never pays bonds issued by pubkeys in this list, even with `--all-issuers`. Used for the
+    /// validators without their own `deny_issuers`.
+    #[arg(long, env, value_delimiter = ',')]
+    deny_issuers: Vec<Pubkey>,
+    /// Payer signer: a keypair path, `prompt://`, `stdin` or the URL of a remote signer. Used for
+    /// the validators without their own `payer`.
     #[arg(short, long, env)]
-    payer: String,
+    payer: Option<String>,
     /// Maximum RPC requests to send concurrently.
     #[arg(long, env, default_value = "50")]
     concurrency: usize,
+    /// Maximum number of bond transfers packed into one transaction. Batches are also capped by
+    /// the transaction size limit.
+    #[arg(long, env, default_value = "10")]
+    max_transfers_per_tx: usize,
     /// Dry mode to calculate excess rewards without transferring.
     #[arg(long, env)]
     dry_run: bool,
@@ -62,187 +104,1240 @@ pub struct ValidatorBondManagerArgs {
     /// The wait time (in secs) between get_block RPC call retries.
     #[arg(long, env, default_value = "1800")]
     block_retry_delay: u64,
+    /// Number of getBlock calls packed into one batched JSON-RPC request. 1 disables batching.
+    #[arg(long, env, default_value = "20")]
+    block_batch_size: usize,
+    /// Path to the local payout ledger used to avoid paying a bond twice for the same epoch.
+    #[arg(long, env, default_value = "pye-payout-ledger.json")]
+    ledger: String,
+    /// Pay bonds even if the ledger shows the epoch as already paid.
+    #[arg(long, env)]
+    force_repay: bool,
+    /// Catch up on every epoch from this one to the last completed epoch before waiting for the
+    /// next epoch boundary. Defaults to the epoch after the last one processed, as recorded in
+    /// the ledger.
+    #[arg(long, env)]
+    from_epoch: Option<u64>,
+    /// Maximum number of completed epochs missed since the last checkpoint (e.g. while the
+    /// manager was down) that are caught up on at startup. Older epochs are usually past the
+    /// RPC's leader schedule and block history.
+    #[arg(long, env, default_value = "3")]
+    max_catch_up_epochs: u64,
+    /// Carry negative net excess rewards into the bond's next epoch instead of dropping them.
+    #[arg(long, env)]
+    carry_forward: bool,
+    #[command(flatten)]
+    mev: MevSourceArgs,
+    #[command(flatten)]
+    stake: StakeDenominatorArgs,
+    /// Treat reward components that cannot be fetched (block rewards, inflation rewards) as 0
+    /// instead of aborting the bond's payout for the epoch. This can underpay bond holders.
+    #[arg(long, env)]
+    permissive: bool,
+    /// The wait time (in secs) before retrying a payout whose excess rewards could not be
+    /// computed, that was not sent because the payer could not afford it or its simulation
+    /// failed, or whose transaction did not land. Doubled after every further failure.
+    #[arg(long, env, default_value = "1800")]
+    retry_delay: u64,
+    /// The maximum wait time (in secs) between retries of a failed payout, and of the manager's
+    /// loop after an error.
+    #[arg(long, env, default_value = "86400")]
+    max_retry_delay: u64,
+    /// Number of retries of a failed payout before giving up on it.
+    #[arg(long, env, default_value = "10")]
+    max_retries: u32,
+    #[command(flatten)]
+    transactions: TransactionArgs,
+}
+
+/// Validator listed in the `--validators` file.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ValidatorConfig {
+    pub vote_pubkey: String,
+    /// Payer signer of the validator's bonds. Defaults to `--payer`.
+    #[serde(default)]
+    pub payer: Option<String>,
+    /// Issuers whose bonds are paid. Defaults to `--issuers`.
+    #[serde(default)]
+    pub issuers: Option<Vec<String>>,
+    /// Pays the bonds of every issuer instead of an `issuers` allow-list.
+    #[serde(default)]
+    pub all_issuers: bool,
+    /// Issuers whose bonds are never paid. Defaults to `--deny-issuers`.
+    #[serde(default)]
+    pub deny_issuers: Option<Vec<String>>,
+}
+
+/// Which issuers' bonds a validator's payouts cover.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IssuerFilter {
+    /// Issuers whose bonds are paid, or `None` to pay every issuer.
+    pub allowed: Option<Vec<Pubkey>>,
+    /// Issuers whose bonds are never paid, even when allowed.
+    pub denied: Vec<Pubkey>,
+}
+
+impl IssuerFilter {
+    pub fn pays(&self, issuer: &Pubkey) -> bool {
+        if self.denied.contains(issuer) {
+            return false;
+        }
+        match &self.allowed {
+            Some(allowed) => allowed.contains(issuer),
+            None => true,
+        }
+    }
+}
+
+/// The bonds an issuer has pointed at a validator.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct IssuerBonds {
+    /// Bonds whose maturity has not been handled yet.
+    pub active: usize,
+    pub matured: usize,
+    /// Whether the manager pays the issuer's bonds.
+    pub paid: bool,
+}
+
+/// Groups a validator's bonds by issuer.
+pub fn group_bonds_by_issuer(
+    bonds: &[(Pubkey, SoloValidatorBond)],
+    filter: &IssuerFilter,
+) -> BTreeMap<Pubkey, IssuerBonds> {
+    let mut issuers: BTreeMap<Pubkey, IssuerBonds> = BTreeMap::new();
+    for (_, bond) in bonds {
+        let group = issuers.entry(bond.issuer).or_insert_with(|| IssuerBonds {
+            paid: filter.pays(&bond.issuer),
+            ..IssuerBonds::default()
+        });
+        if bond.maturity_handled {
+            group.matured += 1;
+        } else {
+            group.active += 1;
+        }
+    }
+    issuers
+}
+
+/// A validator whose bonds the manager pays.
+struct ManagedValidator {
+    vote_pubkey: Pubkey,
+    issuers: IssuerFilter,
+    /// Signers paying the validator's bonds, or `None` in dry runs.
+    signers: Option<PayoutSigners>,
 }
 
 pub async fn handle_validator_bond_manager(args: ValidatorBondManagerArgs) -> Result<()> {
-    let rpc_client = Arc::new(RpcClient::new_with_commitment(
-        args.rpc.clone(),
-        CommitmentConfig::confirmed(),
-    ));
-
-    let epoch_schedule = rpc_client.get_epoch_schedule().await?;
-    let mut current_epoch_info = match rpc_client.get_epoch_info().await {
-        Ok(info) => info,
+    args.stake.check(&args.mev)?;
+    let rpc_client = Arc::new(new_rpc_client(args.rpc.clone()));
+    let mev_provider = args.mev.mev_data_provider(&args.rpc);
+    let mut ledger = PayoutLedger::open(&args.ledger)?;
+    let validators = resolve_validators(&args).await?;
+    for validator in &validators {
+        report_validator_bonds(&args, validator, &rpc_client).await;
+    }
+
+    let mut failures = 0;
+    let current_epoch = loop {
+        match rpc_client.get_epoch_info().await {
+            Ok(info) => break info.epoch,
+            Err(err) => {
+                failures += 1;
+                report_manager_error(
+                    &args,
+                    failures,
+                    &anyhow!("Error getting epoch info: {:?}", err),
+                )
+                .await;
+            }
+        }
+    };
+    let mut state = ManagerState {
+        current_epoch,
+        validators: vec![],
+    };
+    for validator in &validators {
+        let next_epoch = resolve_next_epoch(&args, &ledger, validator, current_epoch)?;
+        state.validators.push(ValidatorState {
+            next_epoch,
+            active_bonds: None,
+        });
+    }
+
+    // Supervisor loop: a failed cycle is reported and retried, resuming from the state it left,
+    // so the daemon keeps running and no epoch is skipped.
+    loop {
+        match run_cycle(
+            &args,
+            &validators,
+            &rpc_client,
+            mev_provider.as_ref(),
+            &mut ledger,
+            &mut state,
+        )
+        .await
+        {
+            Ok(()) => failures = 0,
+            Err(err) => {
+                failures += 1;
+                report_manager_error(&args, failures, &err).await;
+            }
+        }
+    }
+}
+
+/// Resolves the validators given with `--vote-pubkey` and `--validators`, and their signers.
+async fn resolve_validators(args: &ValidatorBondManagerArgs) -> Result<Vec<ManagedValidator>> {
+    let mut configs: Vec<ValidatorConfig> = args
+        .vote_pubkey
+        .iter()
+        .map(|vote_pubkey| ValidatorConfig {
+            vote_pubkey: vote_pubkey.to_string(),
+            payer: None,
+            issuers: None,
+            all_issuers: false,
+            deny_issuers: None,
+        })
+        .collect();
+    if let Some(path) = &args.validators {
+        let data = std::fs::read_to_string(path)
+            .map_err(|e| anyhow!("Failed to read validators from {}: {}", path, e))?;
+        let listed: Vec<ValidatorConfig> = serde_json::from_str(&data)
+            .map_err(|e| anyhow!("Invalid validators file {}: {}", path, e))?;
+        configs.extend(listed);
+    }
+    if configs.is_empty() {
+        return Err(anyhow!(
+            "No validator to manage: pass --vote-pubkey or --validators"
+        ));
+    }
+
+    // Signers are resolved once per payer, so a prompt is not asked again for every validator.
+    let mut payers: HashMap<String, PayoutSigners> = HashMap::new();
+    let mut validators: Vec<ManagedValidator> = vec![];
+    for config in configs {
+        let vote_pubkey = Pubkey::from_str(&config.vote_pubkey)
+            .map_err(|e| anyhow!("Invalid vote account {}: {}", config.vote_pubkey, e))?;
+        if validators.iter().any(|v| v.vote_pubkey == vote_pubkey) {
+            return Err(anyhow!("Validator {} is listed twice", vote_pubkey));
+        }
+        let allowed = match (config.issuers, config.all_issuers) {
+            (Some(_), true) => {
+                return Err(anyhow!(
+                    "Validator {} sets both issuers and all_issuers",
+                    vote_pubkey
+                ))
+            }
+            (Some(issuers), false) => Some(parse_issuers(&issuers)?),
+            (None, true) => None,
+            (None, false) if args.all_issuers => None,
+            (None, false) => Some(args.issuers.clone()),
+        };
+        // An empty allow-list would silently pay no bond at all.
+        if allowed.as_ref().is_some_and(|allowed| allowed.is_empty()) {
+            return Err(anyhow!(
+                "No issuers for validator {}: pass --issuers or --all-issuers",
+                vote_pubkey
+            ));
+        }
+        let denied = match config.deny_issuers {
+            Some(denied) => parse_issuers(&denied)?,
+            None => args.deny_issuers.clone(),
+        };
+        let issuers = IssuerFilter { allowed, denied };
+        let signers = if args.dry_run {
+            None
+        } else {
+            let payer = config.payer.or_else(|| args.payer.clone()).ok_or_else(|| {
+                anyhow!(
+                    "No payer for validator {}: pass --payer or set its payer in --validators",
+                    vote_pubkey
+                )
+            })?;
+            let signers = match payers.get(&payer) {
+                Some(signers) => signers.clone(),
+                None => PayoutSigners::resolve(&payer, &args.transactions).await?,
+            };
+            payers.insert(payer, signers.clone());
+            Some(signers)
+        };
+        match &issuers.allowed {
+            Some(allowed) => info!(
+                "Managing validator {} for issuers {:?}, except {:?}",
+                vote_pubkey, allowed, issuers.denied
+            ),
+            None => info!(
+                "Managing validator {} for all issuers, except {:?}",
+                vote_pubkey, issuers.denied
+            ),
+        }
+        validators.push(ManagedValidator {
+            vote_pubkey,
+            issuers,
+            signers,
+        });
+    }
+    Ok(validators)
+}
+
+fn parse_issuers(issuers: &[String]) -> Result<Vec<Pubkey>> {
+    issuers
+        .iter()
+        .map(|issuer| {
+            Pubkey::from_str(issuer).map_err(|e| anyhow!("Invalid issuer {}: {}", issuer, e))
+        })
+        .collect()
+}
+
+/// Logs every bond pointed at the validator grouped by issuer, and warns about the active bonds
+/// of issuers that are not paid, so operators notice them.
+async fn report_validator_bonds(
+    args: &ValidatorBondManagerArgs,
+    validator: &ManagedValidator,
+    rpc_client: &RpcClient,
+) {
+    let bonds = match fetch_solo_validator_bonds_by_vote_key(
+        rpc_client,
+        &args.program_id,
+        &validator.vote_pubkey,
+    )
+    .await
+    {
+        Ok(bonds) => bonds,
         Err(err) => {
+            warn!(
+                "Failed to list the bonds of validator {}: {}",
+                validator.vote_pubkey, err
+            );
+            return;
+        }
+    };
+    let issuers = group_bonds_by_issuer(&bonds, &validator.issuers);
+    info!(
+        "Validator {} has {} bonds from {} issuers",
+        validator.vote_pubkey,
+        bonds.len(),
+        issuers.len()
+    );
+    for (issuer, group) in &issuers {
+        info!(
+            "  Issuer {}: {} active, {} matured, {}",
+            issuer,
+            group.active,
+            group.matured,
+            if group.paid { "paid" } else { "not paid" }
+        );
+        if !group.paid && group.active > 0 {
+            warn!(
+                "Validator {} has {} active bonds from issuer {} that are not paid",
+                validator.vote_pubkey, group.active, issuer
+            );
+            datapoint_warn!(
+                "unpaid_issuer_bonds",
+                ("vote_pubkey", validator.vote_pubkey.to_string(), String),
+                ("issuer", issuer.to_string(), String),
+                ("active_bonds", group.active as i64, i64),
+            );
+        }
+    }
+}
+
+/// Resolves the first completed epoch to process for the validator: `--from-epoch`, or the
+/// epoch after its checkpoint.
+fn resolve_next_epoch(
+    args: &ValidatorBondManagerArgs,
+    ledger: &PayoutLedger,
+    validator: &ManagedValidator,
+    current_epoch: u64,
+) -> Result<u64> {
+    if let Some(from_epoch) = args.from_epoch {
+        let target_epochs = resolve_target_epochs(None, Some(from_epoch), None, current_epoch)?;
+        info!(
+            "Catching up on epochs {:?} for validator {}",
+            target_epochs, validator.vote_pubkey
+        );
+        return Ok(from_epoch);
+    }
+    let checkpoint = ledger.checkpoint(&validator.vote_pubkey);
+    let next_epoch = resolve_resume_epoch(checkpoint, current_epoch, args.max_catch_up_epochs);
+    if let Some(checkpoint) = checkpoint {
+        if checkpoint + 1 < next_epoch {
+            let err = format!(
+                "Skipping epochs {} to {} missed since the last checkpoint, beyond --max-catch-up-epochs. Pass --from-epoch to backfill them.",
+                checkpoint + 1,
+                next_epoch - 1
+            );
+            warn!("{}", err);
             datapoint_error!(
                 "handle_validator_bond_manager",
-                ("error", err.to_string(), String),
+                ("vote_pubkey", validator.vote_pubkey.to_string(), String),
+                ("error", err, String),
             );
-            return Err(anyhow!("Error getting epoch info: {:?}", err));
         }
-    };
-    loop {
-        // Fetch bonds that are still active prior to waiting for the next epoch, to make sure we
-        // don't miss any.
-        let results: Vec<_> = stream::iter(args.issuers.to_owned())
+    }
+    if next_epoch < current_epoch {
+        info!(
+            "Resuming validator {} from checkpoint {:?}: catching up on epochs {:?}",
+            validator.vote_pubkey,
+            checkpoint,
+            next_epoch..current_epoch
+        );
+    }
+    Ok(next_epoch)
+}
+
+/// Progress of the manager, kept across failed cycles.
+struct ManagerState {
+    /// The current epoch as of the last epoch boundary seen.
+    current_epoch: u64,
+    /// Progress of each managed validator, in the same order.
+    validators: Vec<ValidatorState>,
+}
+
+struct ValidatorState {
+    /// The first completed epoch that has not been processed yet.
+    next_epoch: u64,
+    /// Active bonds fetched during an epoch, with that epoch, to be paid once it completes.
+    active_bonds: Option<(u64, Vec<(Pubkey, SoloValidatorBond)>)>,
+}
+
+/// Processes the completed epochs not processed yet, then waits for the next epoch boundary,
+/// retrying failed payouts in the meantime. A validator that fails is reported and picked up
+/// again on the next cycle, without holding up the others.
+async fn run_cycle(
+    args: &ValidatorBondManagerArgs,
+    validators: &[ManagedValidator],
+    rpc_client: &Arc<RpcClient>,
+    mev_provider: &dyn MevDataProvider,
+    ledger: &mut PayoutLedger,
+    state: &mut ManagerState,
+) -> Result<()> {
+    if !args.dry_run {
+        check_pending_payouts(args, validators, rpc_client.as_ref(), ledger).await?;
+    }
+    let current_epoch = state.current_epoch;
+    let first_epoch = state
+        .validators
+        .iter()
+        .map(|validator_state| validator_state.next_epoch)
+        .min()
+        .unwrap_or(current_epoch);
+    let mut failed = vec![false; validators.len()];
+    for target_epoch in first_epoch..current_epoch {
+        // The validators' common data (MEV data, vote accounts, sysvars) is fetched once per
+        // epoch.
+        let chain_data = SharedChainData::new(rpc_client.as_ref());
+        for (index, validator) in validators.iter().enumerate() {
+            let validator_state = &mut state.validators[index];
+            if failed[index] || validator_state.next_epoch > target_epoch {
+                continue;
+            }
+            // Bonds monitored while the epoch was current are paid for it. Otherwise, bonds that
+            // matured since then are no longer active, so every bond is fetched and filtered by
+            // its term at the end of the epoch.
+            let monitored = match &validator_state.active_bonds {
+                Some((epoch, active_bonds)) if *epoch == target_epoch => Some(active_bonds.clone()),
+                _ => None,
+            };
+            let bonds = match monitored.clone() {
+                Some(active_bonds) => Ok(active_bonds),
+                None => fetch_bonds(args, validator, rpc_client, true).await,
+            };
+            let result = match bonds {
+                Ok(bonds) => {
+                    process_epoch(
+                        args,
+                        validator,
+                        &chain_data,
+                        mev_provider,
+                        ledger,
+                        bonds,
+                        target_epoch,
+                        current_epoch,
+                    )
+                    .await
+                }
+                Err(err) => Err(err),
+            };
+            if let Err(err) = result {
+                failed[index] = true;
+                report_validator_error(validator, target_epoch, &err);
+                continue;
+            }
+            if !args.dry_run {
+                ledger.set_checkpoint(&validator.vote_pubkey, target_epoch)?;
+            }
+            validator_state.next_epoch = target_epoch + 1;
+            if monitored.is_some() {
+                validator_state.active_bonds = None;
+            }
+        }
+    }
+
+    // Fetch bonds that are still active prior to waiting for the next epoch, to make sure we
+    // don't miss any.
+    for (validator, validator_state) in validators.iter().zip(&mut state.validators) {
+        let monitored = matches!(
+            &validator_state.active_bonds,
+            Some((epoch, _)) if *epoch == current_epoch
+        );
+        if monitored || validator_state.next_epoch < current_epoch {
+            continue;
+        }
+        match fetch_bonds(args, validator, rpc_client, false).await {
+            Ok(active_bonds) => {
+                info!(
+                    "Monitoring {} bonds of validator {} for epoch {}",
+                    active_bonds.len(),
+                    validator.vote_pubkey,
+                    current_epoch
+                );
+                validator_state.active_bonds = Some((current_epoch, active_bonds));
+            }
+            Err(err) => report_validator_error(validator, current_epoch, &err),
+        }
+    }
+
+    // We block the flow until the next epoch, or until it is time to retry failed payouts and
+    // validators.
+    let retry_interval = Duration::from_secs(args.retry_delay.max(2 * args.cycle_secs));
+    match tokio::time::timeout(
+        retry_interval,
+        wait_for_next_epoch(rpc_client, current_epoch, args.cycle_secs),
+    )
+    .await
+    {
+        Ok(current_epoch_info) => {
+            // We wait 30 seconds to avoid "Epoch rewards period still active at slot" RPC errors
+            tokio::time::sleep(Duration::from_secs(30)).await;
+            info!(
+                "Epoch boundary detected. New epoch: {}",
+                current_epoch_info.epoch
+            );
+            state.current_epoch = current_epoch_info.epoch;
+        }
+        Err(_) => {
+            for validator in validators {
+                retry_failed_payouts(
+                    args,
+                    validator,
+                    rpc_client.as_ref(),
+                    mev_provider,
+                    ledger,
+                    current_epoch,
+                )
+                .await
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Records the outcome of the payouts whose transaction was sent without finding out whether it
+/// landed and of the proposed payouts, and schedules the retry of those that failed.
+async fn check_pending_payouts(
+    args: &ValidatorBondManagerArgs,
+    validators: &[ManagedValidator],
+    rpc_client: &RpcClient,
+    ledger: &mut PayoutLedger,
+) -> Result<()> {
+    let mut failed = ledger.check_pending(rpc_client).await?;
+    failed.extend(
+        check_proposals(
+            rpc_client,
+            &args.transactions.multisig.squads_program_id,
+            ledger,
+        )
+        .await?,
+    );
+    for (bond_pubkey, epoch) in failed {
+        let Some(record) = ledger.get(&bond_pubkey, epoch) else {
+            continue;
+        };
+        let vote_pubkey = record.vote_pubkey.clone();
+        if let Some(validator) = validators
+            .iter()
+            .find(|validator| validator.vote_pubkey.to_string() == vote_pubkey)
+        {
+            schedule_retry(args, validator, ledger, &bond_pubkey, epoch)?;
+        }
+    }
+    Ok(())
+}
+
+/// Reports a failure of the manager's loop and backs off before it is retried.
+async fn report_manager_error(args: &ValidatorBondManagerArgs, failures: u32, err: &anyhow::Error) {
+    let delay = retry_backoff(args.cycle_secs, args.max_retry_delay, failures);
+    error!(
+        "Validator bond manager failed ({} times in a row), retrying in {}s: {:?}",
+        failures, delay, err
+    );
+    datapoint_error!(
+        "handle_validator_bond_manager",
+        ("failures", failures as i64, i64),
+        ("error", err.to_string(), String),
+    );
+    tokio::time::sleep(Duration::from_secs(delay)).await;
+}
+
+/// Reports a failure to process a validator's bonds for `epoch`, which is retried on the next
+/// cycle.
+fn report_validator_error(validator: &ManagedValidator, epoch: u64, err: &anyhow::Error) {
+    error!(
+        "Failed to process validator {} for epoch {}: {:?}",
+        validator.vote_pubkey, epoch, err
+    );
+    datapoint_error!(
+        "handle_validator_bond_manager",
+        ("vote_pubkey", validator.vote_pubkey.to_string(), String),
+        ("epoch", epoch.to_string(), String),
+        ("error", err.to_string(), String),
+    );
+}
+
+/// Fetches the active bonds of every allowed issuer for the validator, and with
+/// `include_matured` their matured bonds too. Fails if any issuer's bonds cannot be fetched,
+/// rather than leaving its bonds unpaid.
+async fn fetch_bonds(
+    args: &ValidatorBondManagerArgs,
+    validator: &ManagedValidator,
+    rpc_client: &Arc<RpcClient>,
+    include_matured: bool,
+) -> Result<Vec<(Pubkey, SoloValidatorBond)>> {
+    let bonds = match &validator.issuers.allowed {
+        Some(allowed) => stream::iter(allowed.to_owned())
             .map(|issuer_pubkey| {
                 let cloned_client = rpc_client.clone();
                 async move {
-                    match fetch_active_solo_validator_bonds_by_vote_key_and_issuer(
-                        &cloned_client,
-                        &args.program_id,
-                        &args.vote_pubkey,
-                        &issuer_pubkey.clone(),
-                    )
-                    .await
-                    {
-                        Ok(bonds) => Ok(bonds),
-                        Err(err) => {
-                            datapoint_error!(
-                                "handle_validator_bond_manager",
-                                ("error", err.to_string(), String),
-                            );
-                            return Err(anyhow!("Error fetching active bonds: {:?}", err));
-                        }
+                    if include_matured {
+                        fetch_solo_validator_bonds_by_vote_key_and_issuer(
+                            &cloned_client,
+                            &args.program_id,
+                            &validator.vote_pubkey,
+                            &issuer_pubkey,
+                        )
+                        .await
+                    } else {
+                        fetch_active_solo_validator_bonds_by_vote_key_and_issuer(
+                            &cloned_client,
+                            &args.program_id,
+                            &validator.vote_pubkey,
+                            &issuer_pubkey,
+                        )
+                        .await
                     }
                 }
             })
             .buffer_unordered(args.concurrency)
             .collect::<Vec<_>>()
-            .await;
-        let active_bonds: Vec<(Pubkey, SoloValidatorBond)> = results
+            .await
             .into_iter()
-            .filter_map(Result::ok)
-            .flatten()
-            .collect();
+            .collect::<Result<Vec<_>>>()
+            .map(|bonds| bonds.into_iter().flatten().collect()),
+        None if include_matured => {
+            fetch_solo_validator_bonds_by_vote_key(
+                rpc_client,
+                &args.program_id,
+                &validator.vote_pubkey,
+            )
+            .await
+        }
+        None => {
+            fetch_active_solo_validator_bonds_by_vote_key(
+                rpc_client,
+                &args.program_id,
+                &validator.vote_pubkey,
+            )
+            .await
+        }
+    };
+    let bonds: Vec<(Pubkey, SoloValidatorBond)> = match bonds {
+        Ok(bonds) => bonds,
+        Err(err) => {
+            datapoint_error!(
+                "handle_validator_bond_manager",
+                ("vote_pubkey", validator.vote_pubkey.to_string(), String),
+                ("error", err.to_string(), String),
+            );
+            return Err(anyhow!("Error fetching bonds: {:?}", err));
+        }
+    };
+    Ok(bonds
+        .into_iter()
+        .filter(|(_, bond)| validator.issuers.pays(&bond.issuer))
+        .collect())
+}
+
+/// Calculates and pays the excess rewards of every bond outstanding at the end of a single
+/// completed epoch. The epoch fails if its end time is unavailable, since which bonds were
+/// outstanding then cannot be told.
+#[allow(clippy::too_many_arguments)]
+async fn process_epoch(
+    args: &ValidatorBondManagerArgs,
+    validator: &ManagedValidator,
+    chain_data: &dyn ChainDataProvider,
+    mev_provider: &dyn MevDataProvider,
+    ledger: &mut PayoutLedger,
+    active_bonds: Vec<(Pubkey, SoloValidatorBond)>,
+    target_epoch: u64,
+    current_epoch: u64,
+) -> Result<()> {
+    info!(
+        "Processing bonds of validator {} for epoch {}",
+        validator.vote_pubkey, target_epoch
+    );
+    let epoch_schedule = match chain_data.get_epoch_schedule().await {
+        Ok(epoch_schedule) => epoch_schedule,
+        Err(err) => {
+            // Every bond is retried, and filtered by its term then.
+            let err = anyhow!("Error getting epoch schedule: {:?}", err);
+            for (bond_pubkey, _) in &active_bonds {
+                record_calculation_failure(
+                    args,
+                    validator,
+                    ledger,
+                    bond_pubkey,
+                    target_epoch,
+                    &err,
+                )?;
+            }
+            return Ok(());
+        }
+    };
+    let epoch_end_ts = fetch_epoch_end_time(chain_data, &epoch_schedule, target_epoch).await?;
+
+    // For all active bonds, log their commission structures and filter by their term
+    let active_bonds: Vec<(Pubkey, SoloValidatorBond)> = active_bonds
+        .into_iter()
+        .filter(|(bond_pubkey, bond)| {
+            log_reward_commissions(target_epoch, bond_pubkey, &bond.reward_commissions);
+            bond_outstanding_at(bond, epoch_end_ts)
+        })
+        .collect();
+
+    let validator_epoch_rewards = fetch_validator_epoch_rewards(
+        args,
+        validator,
+        chain_data,
+        mev_provider,
+        &epoch_schedule,
+        target_epoch,
+        current_epoch,
+    )
+    .await;
+
+    // Note: could add concurrency in this loop
+    // For each bond calculate the additional rewards required for each category
+    let mut payouts = vec![];
+    for (bond_pubkey, bond) in active_bonds.into_iter() {
+        if let Some(record) = ledger.blocking_record(&bond_pubkey, target_epoch) {
+            if !args.force_repay {
+                info!(
+                    "Skipping bond {} for epoch {}: ledger already has a {:?} payout (signature: {:?})",
+                    bond_pubkey, target_epoch, record.status, record.signature
+                );
+                continue;
+            }
+        }
+        let rewards = match &validator_epoch_rewards {
+            Ok((mev_data, validator_block_rewards)) => {
+                calculate_bond_excess_rewards(
+                    chain_data,
+                    &bond,
+                    mev_data,
+                    *validator_block_rewards,
+                    target_epoch,
+                    current_epoch,
+                    args.permissive,
+                )
+                .await
+            }
+            Err(e) => Err(anyhow!("{}", e)),
+        };
+        let rewards = match rewards {
+            Ok(rewards) => rewards,
+            Err(e) => {
+                record_calculation_failure(
+                    args,
+                    validator,
+                    ledger,
+                    &bond_pubkey,
+                    target_epoch,
+                    &e,
+                )?;
+                continue;
+            }
+        };
+        let excess_rewards = rewards.total();
+        let carry_in = if args.carry_forward {
+            match ledger.carry_balance(&bond_pubkey, target_epoch) {
+                Ok(carry_in) => carry_in,
+                Err(e) => {
+                    record_calculation_failure(
+                        args,
+                        validator,
+                        ledger,
+                        &bond_pubkey,
+                        target_epoch,
+                        &e,
+                    )?;
+                    continue;
+                }
+            }
+        } else {
+            0
+        };
+        let (amount, carry_out) = apply_carry_forward(excess_rewards, carry_in);
 
         info!(
-            "Monitoring {} bonds for epoch {}",
-            active_bonds.len(),
-            current_epoch_info.epoch
+            "Bond: {}\nSOL to transfer: {}\nCarried in: {}\n\n",
+            bond_pubkey, amount, carry_in
         );
-        // We block the flow until the next epoch
-        current_epoch_info =
-            wait_for_next_epoch(&rpc_client, current_epoch_info.epoch, args.cycle_secs).await;
-        // We wait 30 seconds to avoid "Epoch rewards period still active at slot" RPC errors
-        tokio::time::sleep(Duration::from_secs(30)).await;
-        info!(
-            "Epoch boundary detected. New epoch: {}",
-            current_epoch_info.epoch
+
+        datapoint_info!(
+            "excess_reward",
+            ("vote_pubkey", validator.vote_pubkey.to_string(), String),
+            ("epoch", target_epoch.to_string(), String),
+            ("bond", bond_pubkey.to_string(), String),
+            ("bond_active_stake", rewards.bond_active_stake as i64, i64),
+            ("excess_inflation_rewards", rewards.inflation, i64),
+            ("excess_mev_rewards", rewards.mev, i64),
+            ("excess_block_rewards", rewards.block, i64),
+            ("total_excess_rewards", excess_rewards, i64),
+            ("carry_in", carry_in, i64),
+            ("carry_out", carry_out, i64),
         );
-        let target_epoch = current_epoch_info.epoch - 1;
-        let last_slot_of_target = epoch_schedule.get_last_slot_in_epoch(target_epoch);
-
-        let block_time = match rpc_client.get_block_time(last_slot_of_target).await {
-            Err(_) => {
-                // TODO: Get a more accurate time of the end of the epoch to determine if payment
-                // should be made. One idea is catch RpcError::ForUser and check for next block
-                // in subsequent slots
-                let now = chrono::Utc::now();
-                now.timestamp()
-            }
-            Ok(block_time) => block_time,
-        };
 
-        // For all active bonds, log their commission structures and filter by maturity
-        let active_bonds: Vec<(Pubkey, SoloValidatorBond)> = active_bonds
-            .into_iter()
-            .filter(|(bond_pubkey, bond)| {
-                log_reward_commissions(target_epoch, &bond_pubkey, &bond.reward_commissions);
-                bond.maturity_ts > block_time
-            })
-            .collect();
+        if args.dry_run {
+            continue;
+        }
 
-        // Load MEV data
-        let mev_data = fetch_and_filter_mev_data(&args.vote_pubkey, target_epoch).await?;
-        log_validator_mev_data(target_epoch, &mev_data);
+        if amount == 0 {
+            info!(
+                "No excess rewards to transfer to bond {} for epoch {}\n",
+                bond_pubkey, target_epoch
+            );
+            if args.carry_forward {
+                ledger.record_carried_forward(
+                    &bond_pubkey,
+                    &validator.vote_pubkey,
+                    target_epoch,
+                    rewards,
+                    carry_in,
+                    carry_out,
+                )?;
+            }
+            continue;
+        }
 
-        let validators_total_block_rewards = calculate_block_rewards(
-            &rpc_client,
-            &args.vote_pubkey,
-            &current_epoch_info,
-            args.concurrency,
-            args.block_retry_delay,
-        )
-        .await?;
+        // Queue the actual SOL transfer if not a dry run and rewards are greater than 0
+        ledger.record_pending(
+            &bond_pubkey,
+            &validator.vote_pubkey,
+            target_epoch,
+            rewards,
+            carry_in,
+            amount,
+        )?;
+        payouts.push(BondTransfer::new(&bond_pubkey, &bond, amount));
+    }
+    let result = send_payouts(args, validator, ledger, target_epoch, payouts).await;
+    flush();
+    result
+}
 
-        // Note: could add concurrency in this loop
-        // For each bond calculate the additional rewards required for each category
-        for (bond_pubkey, bond) in active_bonds.into_iter() {
-            // Fetch the SoloValidatorBond's active stake during target epoch.
-            let bond_active_stake = fetch_bond_active_stake(
-                &rpc_client,
-                &bond.stake_account,
-                &bond.transient_stake_account,
+/// Packs the payouts recorded as pending in the ledger into as few transactions as fit, sends the
+/// transactions concurrently and records each bond's outcome under the signature of its batch.
+async fn send_payouts(
+    args: &ValidatorBondManagerArgs,
+    validator: &ManagedValidator,
+    ledger: &mut PayoutLedger,
+    target_epoch: u64,
+    payouts: Vec<BondTransfer>,
+) -> Result<()> {
+    if payouts.is_empty() {
+        return Ok(());
+    }
+    let signers = validator
+        .signers
+        .as_ref()
+        .ok_or_else(|| anyhow!("No payer to send payouts with"))?;
+    let batches = batch_bond_transfers(
+        payouts.clone(),
+        args.max_transfers_per_tx,
+        args.transactions.payout_method,
+        !args.transactions.nonce.nonce_account.is_empty(),
+    );
+    info!(
+        "Paying {} bonds for epoch {} in {} transactions",
+        payouts.len(),
+        target_epoch,
+        batches.len()
+    );
+    // Each batch's outcome, and the proposal that will pay it when paying from a multisig.
+    let results: Vec<(Vec<BondTransfer>, SendOutcome, Option<Pubkey>)> =
+        if args.transactions.multisig.multisig.is_some() {
+            match propose_payouts(signers, &args.rpc, batches, &args.transactions).await {
+                Ok(proposed) => proposed
+                    .into_iter()
+                    .map(|proposed| {
+                        (
+                            proposed.transfers,
+                            proposed.outcome,
+                            Some(proposed.proposal),
+                        )
+                    })
+                    .collect(),
+                Err(e) => return fail_payouts(args, validator, ledger, target_epoch, &payouts, &e),
+            }
+        } else {
+            let cluster = Cluster::Custom(args.rpc.clone(), args.rpc.replace("http", "ws"));
+            let prepared = match prepare_payouts(signers, cluster, batches, &args.transactions)
+                .await
+            {
+                Ok(prepared) => prepared,
+                Err(e) => return fail_payouts(args, validator, ledger, target_epoch, &payouts, &e),
+            };
+            stream::iter(0..prepared.len())
+                .map(|index| {
+                    let prepared = &prepared;
+                    async move {
+                        let outcome = prepared.send(index, &args.transactions.sender).await;
+                        (prepared.batch(index).to_vec(), outcome, None)
+                    }
+                })
+                .buffer_unordered(args.concurrency)
+                .collect()
+                .await
+        };
+
+    for (batch, outcome, proposal) in results {
+        for transfer in &batch {
+            ledger.record_send_outcome(
+                &transfer.bond_pubkey,
                 target_epoch,
-                current_epoch_info.epoch,
-            )
-            .await?;
-            // Calculate the excess inflation reward to be refunded by validator to SoloValidatorBond.
-            let excess_inflation_reward = calculate_excess_inflation_reward(
-                &rpc_client,
-                &bond.stake_account,
-                &bond.transient_stake_account,
+                &outcome,
+                proposal.as_ref(),
+            )?;
+        }
+        if let SendOutcome::Confirmed { .. } = outcome {
+            continue;
+        }
+        // The outcome is on record, so the daemon carries on with the next batches and epochs.
+        // Transactions that may still land are left pending under their signature rather than
+        // failed, so that their bonds are not paid twice.
+        if let SendOutcome::Unknown {
+            signature, error, ..
+        } = &outcome
+        {
+            warn!(
+                "Could not tell whether transaction {} paying {} bonds for epoch {} landed, leaving them pending: {}",
+                signature,
+                batch.len(),
                 target_epoch,
-                &bond.reward_commissions,
-            )
-            .await;
-
-            // Calculate the excess MEV reward to be refunded by validator to SoloValidatorBond.
-            let excess_mev_commission =
-                calculate_excess_mev_reward(&mev_data, bond_active_stake, &bond.reward_commissions);
-
-            // Calculate the excess block reward to be funded by validator to SoloValidatorBond.
-            let excess_block_commission = compute_excess_block_commission(
-                validators_total_block_rewards,
-                bond_active_stake,
-                mev_data.active_stake,
-                bond.reward_commissions.block_rewards_bps,
+                error
+            );
+            datapoint_warn!(
+                "excess_reward_transfer_unknown",
+                ("vote_pubkey", validator.vote_pubkey.to_string(), String),
+                ("epoch", target_epoch.to_string(), String),
+                ("bonds", batch.len() as i64, i64),
+                ("signature", signature.to_string(), String),
+                ("error", error.clone(), String),
             );
+            continue;
+        }
+        // Transactions that did not land are retried.
+        for transfer in &batch {
+            schedule_retry(args, validator, ledger, &transfer.bond_pubkey, target_epoch)?;
+        }
+        error!(
+            "Failed to transfer excess rewards to {} bonds for epoch {}: {:?}",
+            batch.len(),
+            target_epoch,
+            outcome
+        );
+        datapoint_error!(
+            "excess_reward_transfer_failure",
+            ("vote_pubkey", validator.vote_pubkey.to_string(), String),
+            ("epoch", target_epoch.to_string(), String),
+            ("bonds", batch.len() as i64, i64),
+            (
+                "signature",
+                outcome
+                    .signature()
+                    .map(|signature| signature.to_string())
+                    .unwrap_or_default(),
+                String
+            ),
+            ("outcome", format!("{:?}", outcome), String),
+        );
+    }
+    Ok(())
+}
 
-            let excess_rewards =
-                excess_inflation_reward + excess_block_commission + excess_mev_commission;
+/// Fails every payout of the epoch when nothing could be sent, and retries them once the payer
+/// has been topped up or the simulation issue resolved.
+fn fail_payouts(
+    args: &ValidatorBondManagerArgs,
+    validator: &ManagedValidator,
+    ledger: &mut PayoutLedger,
+    target_epoch: u64,
+    payouts: &[BondTransfer],
+    e: &anyhow::Error,
+) -> Result<()> {
+    error!("Not paying any bond for epoch {}: {}", target_epoch, e);
+    for payout in payouts {
+        let bond_pubkey = &payout.bond_pubkey;
+        ledger.record_failed(bond_pubkey, target_epoch, &e.to_string())?;
+        schedule_retry(args, validator, ledger, bond_pubkey, target_epoch)?;
+    }
+    Ok(())
+}
 
-            info!(
-                "Bond: {}\nSOL to transfer: {}\n\n",
-                bond_pubkey, excess_rewards
-            );
+/// Schedules the next retry of a failed payout, backing off exponentially with its failed
+/// attempts. Gives up after `--max-retries` attempts.
+fn schedule_retry(
+    args: &ValidatorBondManagerArgs,
+    validator: &ManagedValidator,
+    ledger: &mut PayoutLedger,
+    bond_pubkey: &Pubkey,
+    target_epoch: u64,
+) -> Result<()> {
+    let attempts = ledger
+        .get(bond_pubkey, target_epoch)
+        .map_or(1, |record| record.attempts);
+    if attempts > args.max_retries {
+        error!(
+            "Giving up on paying bond {} for epoch {} after {} failed attempts",
+            bond_pubkey, target_epoch, attempts
+        );
+        datapoint_error!(
+            "excess_reward_retries_exhausted",
+            ("vote_pubkey", validator.vote_pubkey.to_string(), String),
+            ("epoch", target_epoch.to_string(), String),
+            ("bond", bond_pubkey.to_string(), String),
+            ("attempts", attempts as i64, i64),
+        );
+        return Ok(());
+    }
+    let delay = retry_backoff(args.retry_delay, args.max_retry_delay, attempts);
+    info!(
+        "Retrying bond {} for epoch {} in {}s (failed attempts: {})",
+        bond_pubkey, target_epoch, delay, attempts
+    );
+    ledger.schedule_retry(
+        bond_pubkey,
+        target_epoch,
+        chrono::Utc::now().timestamp() + delay as i64,
+    )
+}
+
+/// Fetches the validator's MEV data and total block rewards for `target_epoch`, which are shared
+/// by all of its bonds.
+async fn fetch_validator_epoch_rewards(
+    args: &ValidatorBondManagerArgs,
+    validator: &ManagedValidator,
+    chain_data: &dyn ChainDataProvider,
+    mev_provider: &dyn MevDataProvider,
+    epoch_schedule: &EpochSchedule,
+    target_epoch: u64,
+    current_epoch: u64,
+) -> Result<(ValidatorInfo, u64)> {
+    let mev_data = mev_provider
+        .fetch_validator_info(&validator.vote_pubkey, target_epoch)
+        .await?;
+    log_validator_mev_data(target_epoch, &mev_data);
+    let mev_data = resolve_validator_active_stake(
+        chain_data,
+        &validator.vote_pubkey,
+        mev_data,
+        args.stake.stake_denominator,
+        target_epoch,
+        current_epoch,
+    )
+    .await?;
+
+    let validator_block_rewards = or_zero_if_permissive(
+        calculate_block_rewards(
+            chain_data,
+            &validator.vote_pubkey,
+            epoch_schedule,
+            target_epoch,
+            args.concurrency,
+            args.block_batch_size,
+            args.block_retry_delay,
+        )
+        .await,
+        "block rewards",
+        args.permissive,
+    )?;
+    Ok((mev_data, validator_block_rewards))
+}
 
-            datapoint_info!(
-                "excess_reward",
-                ("vote_pubkey", args.vote_pubkey.to_string(), String),
+/// Records why a bond's excess rewards for `target_epoch` could not be computed and schedules a
+/// retry, rather than paying a partial amount.
+fn record_calculation_failure(
+    args: &ValidatorBondManagerArgs,
+    validator: &ManagedValidator,
+    ledger: &mut PayoutLedger,
+    bond_pubkey: &Pubkey,
+    target_epoch: u64,
+    err: &anyhow::Error,
+) -> Result<()> {
+    error!(
+        "Not paying bond {} for epoch {}: {}",
+        bond_pubkey, target_epoch, err
+    );
+    datapoint_error!(
+        "excess_reward_calculation_failure",
+        ("vote_pubkey", validator.vote_pubkey.to_string(), String),
+        ("epoch", target_epoch.to_string(), String),
+        ("bond", bond_pubkey.to_string(), String),
+        ("error", err.to_string(), String),
+    );
+    if args.dry_run {
+        return Ok(());
+    }
+    ledger.record_calculation_failed(
+        bond_pubkey,
+        &validator.vote_pubkey,
+        target_epoch,
+        &err.to_string(),
+        None,
+    )?;
+    schedule_retry(args, validator, ledger, bond_pubkey, target_epoch)
+}
+
+/// Re-runs the failed payouts whose retry is due, one epoch at a time. A failure is reported
+/// and left for the next retry, without holding up the other epochs.
+async fn retry_failed_payouts(
+    args: &ValidatorBondManagerArgs,
+    validator: &ManagedValidator,
+    rpc_client: &RpcClient,
+    mev_provider: &dyn MevDataProvider,
+    ledger: &mut PayoutLedger,
+    current_epoch: u64,
+) {
+    let now = chrono::Utc::now().timestamp();
+    let mut due: BTreeMap<u64, Vec<Pubkey>> = BTreeMap::new();
+    for record in ledger.due_retries(&validator.vote_pubkey, now) {
+        if let Ok(bond_pubkey) = Pubkey::from_str(&record.bond) {
+            due.entry(record.epoch).or_default().push(bond_pubkey);
+        }
+    }
+    for (target_epoch, bond_pubkeys) in due {
+        info!(
+            "Retrying {} failed payouts of validator {} for epoch {}",
+            bond_pubkeys.len(),
+            validator.vote_pubkey,
+            target_epoch
+        );
+        let mut bonds = vec![];
+        for bond_pubkey in bond_pubkeys {
+            match fetch_solo_validator_bond(rpc_client, &bond_pubkey).await {
+                Ok(bond) => bonds.push((bond_pubkey, bond)),
+                Err(e) => {
+                    if let Err(e) = record_calculation_failure(
+                        args,
+                        validator,
+                        ledger,
+                        &bond_pubkey,
+                        target_epoch,
+                        &e,
+                    ) {
+                        error!(
+                            "Failed to record the failure of bond {}: {}",
+                            bond_pubkey, e
+                        );
+                    }
+                }
+            }
+        }
+        if let Err(e) = process_epoch(
+            args,
+            validator,
+            rpc_client,
+            mev_provider,
+            ledger,
+            bonds,
+            target_epoch,
+            current_epoch,
+        )
+        .await
+        {
+            error!(
+                "Failed to retry payouts for epoch {}: {:?}",
+                target_epoch, e
+            );
+            datapoint_error!(
+                "handle_validator_bond_manager",
+                ("vote_pubkey", validator.vote_pubkey.to_string(), String),
                 ("epoch", target_epoch.to_string(), String),
-                ("bond", bond_pubkey.to_string(), String),
-                ("bond_active_stake", bond_active_stake as i64, i64),
-                ("excess_inflation_rewards", excess_inflation_reward, i64),
-                ("excess_mev_rewards", excess_mev_commission, i64),
-                ("excess_block_rewards", excess_block_commission, i64),
-                ("total_excess_rewards", excess_rewards, i64),
+                ("error", e.to_string(), String),
             );
+        }
+    }
+}
 
-            if excess_rewards <= 0 {
-                info!(
-                    "No excess rewards to transfer to bond {} for epoch {}\n",
-                    bond_pubkey, target_epoch
-                );
-                continue;
-            }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::fake::solo_validator_bond;
 
-            // Make the actual SOL transfer if not a dry run and rewards are greater than 0
-            if !args.dry_run {
-                // transfer_excess_rewards_with_delegate_tips
-                let cluster = Cluster::Custom(args.rpc.clone(), args.rpc.replace("http", "ws"));
-                transfer_excess_rewards(
-                    args.payer.clone(),
-                    cluster,
-                    &bond_pubkey,
-                    &bond,
-                    u64::try_from(excess_rewards)?,
-                )
-                .await
-                .map_err(|e| anyhow!("Failed to transfer excess rewards: {}", e))?
+    #[test]
+    fn test_group_bonds_by_issuer() {
+        let vote_pubkey = Pubkey::new_unique();
+        let bond = |issuer: Pubkey, matured: bool| {
+            let mut bond = solo_validator_bond(vote_pubkey, Pubkey::new_unique(), 0, 0, 0);
+            bond.issuer = issuer;
+            bond.maturity_handled = matured;
+            (Pubkey::new_unique(), bond)
+        };
+        let (allowed, denied, unlisted) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+        let bonds = vec![
+            bond(allowed, false),
+            bond(allowed, true),
+            bond(denied, false),
+            bond(unlisted, false),
+            bond(unlisted, false),
+        ];
+
+        let filter = IssuerFilter {
+            allowed: Some(vec![allowed, denied]),
+            denied: vec![denied],
+        };
+        let issuers = group_bonds_by_issuer(&bonds, &filter);
+        assert_eq!(
+            issuers[&allowed],
+            IssuerBonds {
+                active: 1,
+                matured: 1,
+                paid: true
             }
-        }
-        flush();
+        );
+        assert!(!issuers[&denied].paid);
+        assert_eq!(
+            issuers[&unlisted],
+            IssuerBonds {
+                active: 2,
+                matured: 0,
+                paid: false
+            }
+        );
+
+        // Every issuer is paid in all-issuers mode, but the deny-list still applies.
+        let filter = IssuerFilter {
+            allowed: None,
+            denied: vec![denied],
+        };
+        assert!(filter.pays(&unlisted));
+        assert!(!filter.pays(&denied));
+        assert!(group_bonds_by_issuer(&bonds, &filter)[&unlisted].paid);
     }
 }
@@ -0,0 +1,103 @@
+//! Account layouts of the Pye program, derived from its IDL at build time (see `build.rs`).
+
+use anchor_lang::{AnchorSerialize, Discriminator};
+use pye_core_cpi::pye_core::accounts::SoloValidatorBond;
+use solana_client::rpc_filter::{Memcmp, RpcFilterType};
+use solana_sdk::pubkey::Pubkey;
+
+include!(concat!(env!("OUT_DIR"), "/layout.rs"));
+
+/// Builds `getProgramAccounts` filters matching SoloValidatorBond fields at fixed offsets, e.g.
+/// `BondFilters::new().field(solo_validator_bond::ISSUER, &issuer)`.
+#[derive(Debug, Clone)]
+pub struct BondFilters {
+    filters: Vec<RpcFilterType>,
+}
+
+impl Default for BondFilters {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BondFilters {
+    /// Matches SoloValidatorBond accounts only.
+    pub fn new() -> Self {
+        Self {
+            filters: vec![RpcFilterType::Memcmp(Memcmp::new_base58_encoded(
+                0,
+                SoloValidatorBond::DISCRIMINATOR,
+            ))],
+        }
+    }
+
+    /// Matches bonds whose field at `offset` (one of the `solo_validator_bond` constants) is
+    /// `value`, serialized as in the account.
+    pub fn field<T: AnchorSerialize>(mut self, offset: usize, value: &T) -> Self {
+        let mut bytes = vec![];
+        value
+            .serialize(&mut bytes)
+            .expect("serializing to a Vec cannot fail");
+        self.filters
+            .push(RpcFilterType::Memcmp(Memcmp::new_base58_encoded(
+                offset, &bytes,
+            )));
+        self
+    }
+
+    pub fn vote_account(self, vote_pubkey: &Pubkey) -> Self {
+        self.field(solo_validator_bond::VALIDATOR_VOTE_ACCOUNT, vote_pubkey)
+    }
+
+    pub fn issuer(self, issuer_pubkey: &Pubkey) -> Self {
+        self.field(solo_validator_bond::ISSUER, issuer_pubkey)
+    }
+
+    /// Matches bonds whose maturity has not been handled yet.
+    pub fn active(self) -> Self {
+        self.field(solo_validator_bond::MATURITY_HANDLED, &false)
+    }
+
+    pub fn build(self) -> Vec<RpcFilterType> {
+        self.filters
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::fake::solo_validator_bond;
+    use anchor_lang::AccountSerialize;
+
+    #[test]
+    fn test_offsets_match_serialized_bond() {
+        let vote_pubkey = Pubkey::new_unique();
+        let mut bond = solo_validator_bond(vote_pubkey, Pubkey::new_unique(), 0, 0, 0);
+        bond.maturity_handled = true;
+        bond.issuance_close_ts = 1_234;
+        let mut data = vec![];
+        bond.try_serialize(&mut data).unwrap();
+
+        let at = |offset: usize, len: usize| &data[offset..offset + len];
+        assert_eq!(
+            at(solo_validator_bond::VALIDATOR_VOTE_ACCOUNT, 32),
+            vote_pubkey.as_ref()
+        );
+        assert_eq!(
+            at(solo_validator_bond::STAKE_ACCOUNT, 32),
+            bond.stake_account.as_ref()
+        );
+        assert_eq!(
+            at(solo_validator_bond::MATURITY_TS, 8),
+            bond.maturity_ts.to_le_bytes()
+        );
+        assert_eq!(at(solo_validator_bond::BUMP_SEED, 1), [bond.bump_seed]);
+        assert_eq!(at(solo_validator_bond::MATURITY_HANDLED, 1), [1]);
+        assert_eq!(
+            at(solo_validator_bond::ISSUANCE_CLOSE_TS, 8),
+            1_234i64.to_le_bytes()
+        );
+        assert_eq!(at(solo_validator_bond::ISSUER, 32), bond.issuer.as_ref());
+        assert_eq!(at(solo_validator_bond::SINGLE_DEPOSITOR, 1), [0]);
+    }
+}
@@ -0,0 +1,1228 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use anyhow::{anyhow, Result};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signature;
+
+use crate::rewards::ExcessRewards;
+use crate::sender::{check_outcome, is_expired, SendOutcome, TransactionExpiry, TransactionRpc};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PayoutStatus {
+    /// The transfer is about to be sent. A record left in this state means the process stopped
+    /// before the outcome was known, so the transfer may or may not have landed.
+    Pending,
+    Confirmed,
+    /// The payout was proposed to the members of the multisig paying it, and is paid once they
+    /// approve and execute the proposal. It is confirmed once the executing transaction is found.
+    Proposed,
+    Failed,
+    /// The payout is above the approval policy's thresholds and was not sent. It is paid by a
+    /// later run once approved.
+    Held,
+    /// The net amount owed was not positive, so it was carried into the bond's next epoch
+    /// instead of being paid.
+    CarriedForward,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PayoutRecord {
+    pub bond: String,
+    pub epoch: u64,
+    pub vote_pubkey: String,
+    pub rewards: ExcessRewards,
+    /// Balance carried into this epoch from previous epochs.
+    #[serde(default)]
+    pub carry_in: i64,
+    /// Balance carried out of this epoch into the bond's next one, never positive.
+    #[serde(default)]
+    pub carry_out: i64,
+    pub amount: u64,
+    pub status: PayoutStatus,
+    /// Transaction that paid, or tried to pay, the bond. For a multisig payout, the transaction
+    /// executing its proposal.
+    pub signature: Option<String>,
+    /// When the transaction of a pending payout, sent without finding out whether it landed or
+    /// exported to be signed offline, can no longer land.
+    #[serde(default)]
+    pub expiry: Option<TransactionExpiry>,
+    /// Multisig proposal that pays the bond, if paid from a multisig.
+    #[serde(default)]
+    pub proposal: Option<String>,
+    /// Transaction that created the multisig proposal, which pays nothing by itself.
+    #[serde(default)]
+    pub proposal_signature: Option<String>,
+    /// Earlier transactions that paid, or tried to pay, the bond for the epoch before it was
+    /// paid again with `--force-repay`.
+    #[serde(default)]
+    pub previous_payments: Vec<PreviousPayment>,
+    pub error: Option<String>,
+    /// Unix timestamp after which a failed payout should be retried.
+    #[serde(default)]
+    pub retry_at: Option<i64>,
+    /// Number of times paying the bond for the epoch has failed.
+    #[serde(default)]
+    pub attempts: u32,
+    /// Unix timestamp of the last status change.
+    pub updated_at: i64,
+}
+
+/// A transaction that paid, or tried to pay, a bond for an epoch before it was paid again.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PreviousPayment {
+    pub signature: String,
+    pub amount: u64,
+}
+
+impl PayoutRecord {
+    fn new(
+        bond_pubkey: &Pubkey,
+        vote_pubkey: &Pubkey,
+        epoch: u64,
+        rewards: ExcessRewards,
+        status: PayoutStatus,
+    ) -> Self {
+        Self {
+            bond: bond_pubkey.to_string(),
+            epoch,
+            vote_pubkey: vote_pubkey.to_string(),
+            rewards,
+            carry_in: 0,
+            carry_out: 0,
+            amount: 0,
+            status,
+            signature: None,
+            expiry: None,
+            proposal: None,
+            proposal_signature: None,
+            previous_payments: vec![],
+            error: None,
+            retry_at: None,
+            attempts: 0,
+            updated_at: chrono::Utc::now().timestamp(),
+        }
+    }
+
+    /// Signatures of the transactions that paid, or tried to pay, the bond for the epoch.
+    pub fn payment_signatures(&self) -> impl Iterator<Item = &str> {
+        self.signature.as_deref().into_iter().chain(
+            self.previous_payments
+                .iter()
+                .map(|payment| payment.signature.as_str()),
+        )
+    }
+
+    /// Amount the transaction `signature` paid the bond for the epoch, if it is one of the
+    /// record's payments.
+    pub fn amount_paid_by(&self, signature: &str) -> Option<u64> {
+        if self.signature.as_deref() == Some(signature) {
+            return Some(self.amount);
+        }
+        self.previous_payments
+            .iter()
+            .find(|payment| payment.signature == signature)
+            .map(|payment| payment.amount)
+    }
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct LedgerState {
+    /// Payout records keyed by `<bond>:<epoch>`.
+    payouts: BTreeMap<String, PayoutRecord>,
+    /// Last epoch fully processed by the manager, keyed by vote account.
+    #[serde(default)]
+    checkpoints: BTreeMap<String, u64>,
+}
+
+/// Local record of excess reward payouts, keyed by bond and target epoch. Used to make sure a
+/// bond is never paid twice for the same epoch across re-runs and restarts.
+///
+/// Several processes can share a ledger: every change is made under an exclusive lock on
+/// `<ledger>.lock`, to the ledger as re-read from disk, so changes made by other processes since
+/// it was loaded are kept.
+pub struct PayoutLedger {
+    path: PathBuf,
+    state: LedgerState,
+    /// Keys of the records by transaction signature, rebuilt on load. A transaction paying
+    /// several bonds or epochs maps to all of their records.
+    signatures: HashMap<String, Vec<String>>,
+}
+
+fn record_key(bond_pubkey: &Pubkey, epoch: u64) -> String {
+    format!("{}:{}", bond_pubkey, epoch)
+}
+
+/// Nets an epoch's excess rewards against the balance carried from previous epochs. Returns the
+/// amount to pay and the balance to carry into the next epoch, which is never positive.
+pub fn apply_carry_forward(total_excess_rewards: i64, carry_in: i64) -> (u64, i64) {
+    let net = total_excess_rewards + carry_in;
+    if net > 0 {
+        (net as u64, 0)
+    } else {
+        (0, net)
+    }
+}
+
+/// Seconds to wait before retrying a payout that failed `attempts` times: `base_secs`, doubled
+/// after every further failure, up to `max_secs`.
+pub fn retry_backoff(base_secs: u64, max_secs: u64, attempts: u32) -> u64 {
+    let doublings = attempts.saturating_sub(1).min(63);
+    base_secs
+        .saturating_mul(1u64 << doublings)
+        .min(max_secs.max(base_secs))
+}
+
+impl PayoutLedger {
+    /// Loads the ledger at `path`, starting an empty one if the file does not exist yet.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let mut ledger = Self {
+            path: path.as_ref().to_path_buf(),
+            state: LedgerState::default(),
+            signatures: HashMap::new(),
+        };
+        ledger.reload()?;
+        Ok(ledger)
+    }
+
+    /// Re-reads the ledger from disk, picking up the changes made by other processes.
+    pub fn reload(&mut self) -> Result<()> {
+        let path = &self.path;
+        self.state = if path.exists() {
+            let data = fs::read_to_string(path)
+                .map_err(|e| anyhow!("Failed to read ledger {}: {}", path.display(), e))?;
+            serde_json::from_str(&data)
+                .map_err(|e| anyhow!("Failed to parse ledger {}: {}", path.display(), e))?
+        } else {
+            LedgerState::default()
+        };
+        self.signatures.clear();
+        let keys: Vec<(String, String)> = self
+            .state
+            .payouts
+            .iter()
+            .flat_map(|(key, record)| {
+                record
+                    .payment_signatures()
+                    .map(|signature| (signature.to_string(), key.clone()))
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+        for (signature, key) in keys {
+            self.index_signature(signature, key);
+        }
+        Ok(())
+    }
+
+    /// Applies `f` to the ledger re-read from disk and saves it, holding the ledger's lock
+    /// throughout.
+    fn modify<T>(&mut self, f: impl FnOnce(&mut Self) -> Result<T>) -> Result<T> {
+        let lock_path = self.sibling_path("lock");
+        let lock = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&lock_path)
+            .and_then(|lock| lock.lock().map(|_| lock))
+            .map_err(|e| anyhow!("Failed to lock ledger {}: {}", lock_path.display(), e))?;
+        self.reload()?;
+        let result = f(self)?;
+        self.save()?;
+        drop(lock);
+        Ok(result)
+    }
+
+    /// `<ledger file name>.<extension>`, next to the ledger.
+    fn sibling_path(&self, extension: &str) -> PathBuf {
+        let mut name = self.path.file_name().unwrap_or_default().to_os_string();
+        name.push(format!(".{}", extension));
+        self.path.with_file_name(name)
+    }
+
+    /// Writes the ledger to a temporary file, syncs it to disk and renames it over the original,
+    /// so a crash mid-write never leaves a truncated ledger behind.
+    fn save(&self) -> Result<()> {
+        let data = serde_json::to_string_pretty(&self.state)?;
+        let tmp_path = self.sibling_path(&format!("{}.tmp", std::process::id()));
+        let write = |tmp_path: &Path| -> std::io::Result<()> {
+            let mut file = File::create(tmp_path)?;
+            file.write_all(data.as_bytes())?;
+            file.sync_all()
+        };
+        write(&tmp_path)
+            .map_err(|e| anyhow!("Failed to write ledger {}: {}", tmp_path.display(), e))?;
+        fs::rename(&tmp_path, &self.path)
+            .map_err(|e| anyhow!("Failed to write ledger {}: {}", self.path.display(), e))
+    }
+
+    /// Fails if the bond's record for `epoch` on disk is not `seen`, the one this process based
+    /// its decision to record a new payout on, e.g. because another process paid the epoch since.
+    fn check_unchanged(
+        &self,
+        bond_pubkey: &Pubkey,
+        epoch: u64,
+        seen: Option<&PayoutRecord>,
+    ) -> Result<()> {
+        if self.get(bond_pubkey, epoch) != seen {
+            return Err(anyhow!(
+                "The payout of bond {} for epoch {} was changed by another process, run again to pick it up",
+                bond_pubkey,
+                epoch
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn get(&self, bond_pubkey: &Pubkey, epoch: u64) -> Option<&PayoutRecord> {
+        self.state.payouts.get(&record_key(bond_pubkey, epoch))
+    }
+
+    /// Returns every record paid by the transaction `signature`, including records paid again
+    /// since.
+    pub fn find_by_signature(&self, signature: &str) -> Vec<&PayoutRecord> {
+        // A record's signature changes when it is retried, leaving the signatures of attempts
+        // that paid nothing behind in the index.
+        self.signatures
+            .get(signature)
+            .into_iter()
+            .flatten()
+            .filter_map(|key| self.state.payouts.get(key))
+            .filter(|record| record.amount_paid_by(signature).is_some())
+            .collect()
+    }
+
+    /// Returns the payouts still waiting on the multisig proposal `proposal` to be executed.
+    pub fn find_by_proposal(&self, proposal: &str) -> Vec<&PayoutRecord> {
+        self.proposed()
+            .into_iter()
+            .filter(|record| record.proposal.as_deref() == Some(proposal))
+            .collect()
+    }
+
+    fn index_signature(&mut self, signature: String, key: String) {
+        let keys = self.signatures.entry(signature).or_default();
+        if !keys.contains(&key) {
+            keys.push(key);
+        }
+    }
+
+    /// Returns the existing record if it prevents paying the bond for `epoch` again, i.e. the
+    /// payout was confirmed or its outcome is unknown.
+    pub fn blocking_record(&self, bond_pubkey: &Pubkey, epoch: u64) -> Option<&PayoutRecord> {
+        self.get(bond_pubkey, epoch).filter(|record| {
+            matches!(
+                record.status,
+                PayoutStatus::Confirmed
+                    | PayoutStatus::Pending
+                    | PayoutStatus::Proposed
+                    | PayoutStatus::CarriedForward
+            )
+        })
+    }
+
+    /// Returns the balance carried into `epoch`: the balance carried out of the bond's latest
+    /// earlier epoch on record. Fails while an earlier epoch of the bond has failed, as the
+    /// balance it carries out is only known once it is recomputed, and carrying past it would
+    /// apply the balance carried into it twice.
+    pub fn carry_balance(&self, bond_pubkey: &Pubkey, epoch: u64) -> Result<i64> {
+        let bond = bond_pubkey.to_string();
+        let earlier: Vec<&PayoutRecord> = self
+            .state
+            .payouts
+            .values()
+            .filter(|record| record.bond == bond && record.epoch < epoch)
+            .collect();
+        if let Some(failed) = earlier
+            .iter()
+            .filter(|record| record.status == PayoutStatus::Failed)
+            .min_by_key(|record| record.epoch)
+        {
+            return Err(anyhow!(
+                "Epoch {} of bond {} failed and must be paid before the balance carried into epoch {} is known",
+                failed.epoch,
+                bond,
+                epoch
+            ));
+        }
+        Ok(earlier
+            .iter()
+            .max_by_key(|record| record.epoch)
+            .map_or(0, |record| record.carry_out))
+    }
+
+    pub fn checkpoint(&self, vote_pubkey: &Pubkey) -> Option<u64> {
+        self.state
+            .checkpoints
+            .get(&vote_pubkey.to_string())
+            .copied()
+    }
+
+    /// Records that every bond of `vote_pubkey` was processed for `epoch`. The checkpoint never
+    /// moves back, e.g. when older epochs are backfilled.
+    pub fn set_checkpoint(&mut self, vote_pubkey: &Pubkey, epoch: u64) -> Result<()> {
+        self.modify(|ledger| {
+            let checkpoint = ledger
+                .state
+                .checkpoints
+                .entry(vote_pubkey.to_string())
+                .or_insert(epoch);
+            *checkpoint = (*checkpoint).max(epoch);
+            Ok(())
+        })
+    }
+
+    pub fn record_pending(
+        &mut self,
+        bond_pubkey: &Pubkey,
+        vote_pubkey: &Pubkey,
+        epoch: u64,
+        rewards: ExcessRewards,
+        carry_in: i64,
+        amount: u64,
+    ) -> Result<()> {
+        let seen = self.get(bond_pubkey, epoch).cloned();
+        self.modify(|ledger| {
+            ledger.check_unchanged(bond_pubkey, epoch, seen.as_ref())?;
+            ledger.insert(
+                bond_pubkey,
+                PayoutRecord {
+                    carry_in,
+                    amount,
+                    ..PayoutRecord::new(
+                        bond_pubkey,
+                        vote_pubkey,
+                        epoch,
+                        rewards,
+                        PayoutStatus::Pending,
+                    )
+                },
+            );
+            Ok(())
+        })
+    }
+
+    /// Records an epoch whose net excess rewards were not positive, carrying `carry_out` into the
+    /// bond's next epoch.
+    pub fn record_carried_forward(
+        &mut self,
+        bond_pubkey: &Pubkey,
+        vote_pubkey: &Pubkey,
+        epoch: u64,
+        rewards: ExcessRewards,
+        carry_in: i64,
+        carry_out: i64,
+    ) -> Result<()> {
+        let seen = self.get(bond_pubkey, epoch).cloned();
+        self.modify(|ledger| {
+            ledger.check_unchanged(bond_pubkey, epoch, seen.as_ref())?;
+            ledger.insert(
+                bond_pubkey,
+                PayoutRecord {
+                    carry_in,
+                    carry_out,
+                    ..PayoutRecord::new(
+                        bond_pubkey,
+                        vote_pubkey,
+                        epoch,
+                        rewards,
+                        PayoutStatus::CarriedForward,
+                    )
+                },
+            );
+            Ok(())
+        })
+    }
+
+    /// Records that the bond's excess rewards for `epoch` could not be computed, so nothing was
+    /// paid. The payout is retried once `retry_at` has passed, if set.
+    pub fn record_calculation_failed(
+        &mut self,
+        bond_pubkey: &Pubkey,
+        vote_pubkey: &Pubkey,
+        epoch: u64,
+        error: &str,
+        retry_at: Option<i64>,
+    ) -> Result<()> {
+        self.modify(|ledger| {
+            // A record from an earlier attempt keeps the amounts it was computed with.
+            let record = ledger
+                .state
+                .payouts
+                .entry(record_key(bond_pubkey, epoch))
+                .or_insert_with(|| {
+                    PayoutRecord::new(
+                        bond_pubkey,
+                        vote_pubkey,
+                        epoch,
+                        ExcessRewards::default(),
+                        PayoutStatus::Failed,
+                    )
+                });
+            record.status = PayoutStatus::Failed;
+            record.error = Some(error.to_string());
+            record.retry_at = retry_at;
+            record.attempts += 1;
+            record.updated_at = chrono::Utc::now().timestamp();
+            Ok(())
+        })
+    }
+
+    /// Returns the failed payouts of `vote_pubkey` whose retry is due at `now`.
+    pub fn due_retries(&self, vote_pubkey: &Pubkey, now: i64) -> Vec<&PayoutRecord> {
+        let vote_pubkey = vote_pubkey.to_string();
+        self.state
+            .payouts
+            .values()
+            .filter(|record| {
+                record.status == PayoutStatus::Failed
+                    && record.vote_pubkey == vote_pubkey
+                    && record.retry_at.is_some_and(|retry_at| retry_at <= now)
+            })
+            .collect()
+    }
+
+    /// Inserts `record`, keeping the count of failed attempts of the record it replaces.
+    fn insert(&mut self, bond_pubkey: &Pubkey, mut record: PayoutRecord) {
+        let key = record_key(bond_pubkey, record.epoch);
+        if let Some(previous) = self.state.payouts.get(&key) {
+            record.attempts = previous.attempts;
+            // A record paid again keeps the earlier payments that may have landed.
+            record.previous_payments = previous.previous_payments.clone();
+            let may_have_paid = matches!(
+                previous.status,
+                PayoutStatus::Confirmed | PayoutStatus::Pending
+            );
+            if let (true, Some(signature)) = (may_have_paid, &previous.signature) {
+                record.previous_payments.push(PreviousPayment {
+                    signature: signature.clone(),
+                    amount: previous.amount,
+                });
+            }
+        }
+        self.state.payouts.insert(key, record);
+    }
+
+    pub fn record_confirmed(
+        &mut self,
+        bond_pubkey: &Pubkey,
+        epoch: u64,
+        signature: &Signature,
+    ) -> Result<()> {
+        self.update(bond_pubkey, epoch, |record| {
+            record.status = PayoutStatus::Confirmed;
+            record.signature = Some(signature.to_string());
+            record.error = None;
+            record.retry_at = None;
+        })
+    }
+
+    /// Records the multisig proposal that pays the bond, created by the transaction `signature`.
+    /// The bond is not paid until the proposal is executed.
+    pub fn record_proposed(
+        &mut self,
+        bond_pubkey: &Pubkey,
+        epoch: u64,
+        proposal: &Pubkey,
+        signature: &Signature,
+    ) -> Result<()> {
+        self.update(bond_pubkey, epoch, |record| {
+            record.status = PayoutStatus::Proposed;
+            record.proposal = Some(proposal.to_string());
+            record.proposal_signature = Some(signature.to_string());
+            record.signature = None;
+            record.error = None;
+            record.retry_at = None;
+        })
+    }
+
+    /// Returns the lamports paid, or on their way to be paid, to all bonds for each epoch:
+    /// the amounts of pending, confirmed and proposed payouts and of the payments they replaced.
+    pub fn paid_by_epoch(&self) -> BTreeMap<u64, u64> {
+        let mut paid = BTreeMap::<u64, u64>::new();
+        for record in self.state.payouts.values() {
+            let amount = match record.status {
+                PayoutStatus::Pending | PayoutStatus::Confirmed | PayoutStatus::Proposed => {
+                    record.amount
+                }
+                _ => 0,
+            };
+            let previous: u64 = record
+                .previous_payments
+                .iter()
+                .map(|payment| payment.amount)
+                .sum();
+            *paid.entry(record.epoch).or_default() += amount + previous;
+        }
+        paid
+    }
+
+    /// Returns the payouts proposed to a multisig whose proposal has not been executed yet.
+    pub fn proposed(&self) -> Vec<&PayoutRecord> {
+        self.state
+            .payouts
+            .values()
+            .filter(|record| record.status == PayoutStatus::Proposed)
+            .collect()
+    }
+
+    pub fn record_failed(&mut self, bond_pubkey: &Pubkey, epoch: u64, error: &str) -> Result<()> {
+        self.update(bond_pubkey, epoch, |record| {
+            record.status = PayoutStatus::Failed;
+            record.error = Some(error.to_string());
+            record.attempts += 1;
+        })
+    }
+
+    /// Records that the pending payout was held back by the approval policy, for `reason`.
+    pub fn record_held(&mut self, bond_pubkey: &Pubkey, epoch: u64, reason: &str) -> Result<()> {
+        self.update(bond_pubkey, epoch, |record| {
+            record.status = PayoutStatus::Held;
+            record.error = Some(reason.to_string());
+        })
+    }
+
+    /// Schedules a failed payout to be retried at `retry_at`.
+    pub fn schedule_retry(
+        &mut self,
+        bond_pubkey: &Pubkey,
+        epoch: u64,
+        retry_at: i64,
+    ) -> Result<()> {
+        self.update(bond_pubkey, epoch, |record| {
+            record.retry_at = Some(retry_at);
+        })
+    }
+
+    /// Records that the pending payout was exported in a transaction signed offline, which can
+    /// land until `expiry`.
+    pub fn record_exported(
+        &mut self,
+        bond_pubkey: &Pubkey,
+        epoch: u64,
+        expiry: TransactionExpiry,
+    ) -> Result<()> {
+        self.update(bond_pubkey, epoch, |record| {
+            record.expiry = Some(expiry);
+        })
+    }
+
+    /// Records the signature of the exported transaction paying the pending payout, before it is
+    /// broadcast.
+    pub fn record_submitted(
+        &mut self,
+        bond_pubkey: &Pubkey,
+        epoch: u64,
+        signature: &Signature,
+    ) -> Result<()> {
+        self.update(bond_pubkey, epoch, |record| {
+            record.signature = Some(signature.to_string());
+        })
+    }
+
+    /// Records the outcome of the payout's transaction, or of the transaction creating the
+    /// multisig `proposal` that pays it. A payout whose transaction may still land is left
+    /// pending, so that it is not paid again before it is checked.
+    pub fn record_send_outcome(
+        &mut self,
+        bond_pubkey: &Pubkey,
+        epoch: u64,
+        outcome: &SendOutcome,
+        proposal: Option<&Pubkey>,
+    ) -> Result<()> {
+        // The transaction creating a proposal is kept apart from the one paying the bond.
+        let set_signature = |record: &mut PayoutRecord, signature: &Signature| match proposal {
+            Some(proposal) => {
+                record.proposal = Some(proposal.to_string());
+                record.proposal_signature = Some(signature.to_string());
+            }
+            None => record.signature = Some(signature.to_string()),
+        };
+        match outcome {
+            SendOutcome::Confirmed { signature } => match proposal {
+                Some(proposal) => self.record_proposed(bond_pubkey, epoch, proposal, signature),
+                None => self.record_confirmed(bond_pubkey, epoch, signature),
+            },
+            SendOutcome::Failed { signature, error } => self.update(bond_pubkey, epoch, |record| {
+                record.status = PayoutStatus::Failed;
+                set_signature(record, signature);
+                record.error = Some(error.clone());
+                record.attempts += 1;
+            }),
+            SendOutcome::Dropped { error, .. } => self.record_failed(bond_pubkey, epoch, error),
+            SendOutcome::Unknown {
+                signature,
+                expiry,
+                error,
+            } => self.update(bond_pubkey, epoch, |record| {
+                record.status = PayoutStatus::Pending;
+                set_signature(record, signature);
+                record.expiry = Some(expiry.clone());
+                record.error = Some(error.clone());
+            }),
+        }
+    }
+
+    /// Checks the pending payouts whose transaction was sent without finding out whether it
+    /// landed, and records the outcome of those that landed or can no longer land. Exported
+    /// payouts that were never submitted fail once their nonce has been advanced. Returns the
+    /// payouts that failed.
+    pub async fn check_pending(&mut self, rpc: &dyn TransactionRpc) -> Result<Vec<(Pubkey, u64)>> {
+        let pending: Vec<PayoutRecord> = self
+            .state
+            .payouts
+            .values()
+            .filter(|record| record.status == PayoutStatus::Pending && record.expiry.is_some())
+            .cloned()
+            .collect();
+        let mut failed = vec![];
+        for record in pending {
+            let Some(expiry) = &record.expiry else {
+                continue;
+            };
+            let bond_pubkey = Pubkey::from_str(&record.bond)
+                .map_err(|e| anyhow!("Invalid bond {} in ledger: {}", record.bond, e))?;
+            // A pending multisig payout waits on the transaction creating its proposal.
+            let signature = match &record.proposal {
+                Some(_) => &record.proposal_signature,
+                None => &record.signature,
+            };
+            let Some(signature) = signature else {
+                match is_expired(rpc, expiry).await {
+                    Ok(true) => {
+                        info!(
+                            "Exported payout of bond {} for epoch {} was not submitted before its nonce was advanced",
+                            bond_pubkey, record.epoch
+                        );
+                        self.record_failed(
+                            &bond_pubkey,
+                            record.epoch,
+                            "Exported transaction was not submitted before its nonce was advanced",
+                        )?;
+                        failed.push((bond_pubkey, record.epoch));
+                    }
+                    Ok(false) => {}
+                    Err(e) => warn!(
+                        "Failed to check the nonce of the exported payout of bond {} for epoch {}: {}",
+                        bond_pubkey, record.epoch, e
+                    ),
+                }
+                continue;
+            };
+            let signature = Signature::from_str(signature)
+                .map_err(|e| anyhow!("Invalid signature {} in ledger: {}", signature, e))?;
+            let proposal = match &record.proposal {
+                Some(proposal) => Some(
+                    Pubkey::from_str(proposal)
+                        .map_err(|e| anyhow!("Invalid proposal {} in ledger: {}", proposal, e))?,
+                ),
+                None => None,
+            };
+            match check_outcome(rpc, &signature, expiry).await {
+                Ok(Some(outcome)) => {
+                    info!(
+                        "Pending payout of bond {} for epoch {} resolved: {:?}",
+                        bond_pubkey, record.epoch, outcome
+                    );
+                    self.record_send_outcome(
+                        &bond_pubkey,
+                        record.epoch,
+                        &outcome,
+                        proposal.as_ref(),
+                    )?;
+                    if !matches!(outcome, SendOutcome::Confirmed { .. }) {
+                        failed.push((bond_pubkey, record.epoch));
+                    }
+                }
+                Ok(None) => info!(
+                    "Transaction {} paying bond {} for epoch {} may still land",
+                    signature, bond_pubkey, record.epoch
+                ),
+                Err(e) => warn!(
+                    "Failed to check transaction {} paying bond {} for epoch {}: {}",
+                    signature, bond_pubkey, record.epoch, e
+                ),
+            }
+        }
+        Ok(failed)
+    }
+
+    fn update(
+        &mut self,
+        bond_pubkey: &Pubkey,
+        epoch: u64,
+        f: impl FnOnce(&mut PayoutRecord),
+    ) -> Result<()> {
+        self.modify(|ledger| {
+            let record = ledger
+                .state
+                .payouts
+                .get_mut(&record_key(bond_pubkey, epoch))
+                .ok_or_else(|| {
+                    anyhow!("No ledger record for bond {} epoch {}", bond_pubkey, epoch)
+                })?;
+            f(record);
+            record.updated_at = chrono::Utc::now().timestamp();
+            if let Some(signature) = record.signature.clone() {
+                ledger.index_signature(signature, record_key(bond_pubkey, epoch));
+            }
+            Ok(())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::temp_ledger_path;
+
+    #[test]
+    fn test_confirmed_payout_blocks_repayment() {
+        let path = temp_ledger_path("confirmed");
+        let bond = Pubkey::new_unique();
+        let vote = Pubkey::new_unique();
+        let mut ledger = PayoutLedger::open(&path).unwrap();
+        assert!(ledger.blocking_record(&bond, 700).is_none());
+
+        ledger
+            .record_pending(&bond, &vote, 700, ExcessRewards::default(), 0, 1_000)
+            .unwrap();
+        assert!(ledger.blocking_record(&bond, 700).is_some());
+
+        ledger
+            .record_confirmed(&bond, 700, &Signature::default())
+            .unwrap();
+        let reopened = PayoutLedger::open(&path).unwrap();
+        let record = reopened.blocking_record(&bond, 700).unwrap();
+        assert_eq!(record.status, PayoutStatus::Confirmed);
+        assert_eq!(record.amount, 1_000);
+        assert!(reopened.blocking_record(&bond, 701).is_none());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_find_by_signature() {
+        let path = temp_ledger_path("signature");
+        let bond = Pubkey::new_unique();
+        let (first, second) = (Signature::new_unique(), Signature::new_unique());
+        let mut ledger = PayoutLedger::open(&path).unwrap();
+        ledger
+            .record_pending(
+                &bond,
+                &Pubkey::new_unique(),
+                700,
+                ExcessRewards::default(),
+                0,
+                1_000,
+            )
+            .unwrap();
+        ledger
+            .record_send_outcome(
+                &bond,
+                700,
+                &SendOutcome::Failed {
+                    signature: first,
+                    error: "insufficient funds".to_string(),
+                },
+                None,
+            )
+            .unwrap();
+        ledger.record_confirmed(&bond, 700, &second).unwrap();
+
+        let reopened = PayoutLedger::open(&path).unwrap();
+        let records = reopened.find_by_signature(&second.to_string());
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].epoch, 700);
+        assert!(reopened.find_by_signature(&first.to_string()).is_empty());
+        assert!(ledger.find_by_signature(&first.to_string()).is_empty());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_find_by_signature_shared_by_records() {
+        let path = temp_ledger_path("shared-signature");
+        let (bond, other_bond) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let vote = Pubkey::new_unique();
+        let signature = Signature::new_unique();
+        let mut ledger = PayoutLedger::open(&path).unwrap();
+        // One transaction paying two epochs of a bond and an epoch of another bond.
+        for (bond, epoch) in [(bond, 700), (bond, 701), (other_bond, 701)] {
+            ledger
+                .record_pending(&bond, &vote, epoch, ExcessRewards::default(), 0, 1_000)
+                .unwrap();
+            ledger.record_confirmed(&bond, epoch, &signature).unwrap();
+        }
+
+        let reopened = PayoutLedger::open(&path).unwrap();
+        for ledger in [&ledger, &reopened] {
+            let mut paid: Vec<(String, u64)> = ledger
+                .find_by_signature(&signature.to_string())
+                .into_iter()
+                .map(|record| (record.bond.clone(), record.epoch))
+                .collect();
+            paid.sort();
+            let mut expected = vec![
+                (bond.to_string(), 700),
+                (bond.to_string(), 701),
+                (other_bond.to_string(), 701),
+            ];
+            expected.sort();
+            assert_eq!(paid, expected);
+        }
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_unknown_send_outcome_stays_pending() {
+        let path = temp_ledger_path("unknown");
+        let bond = Pubkey::new_unique();
+        let signature = Signature::new_unique();
+        let mut ledger = PayoutLedger::open(&path).unwrap();
+        ledger
+            .record_pending(
+                &bond,
+                &Pubkey::new_unique(),
+                700,
+                ExcessRewards::default(),
+                0,
+                1_000,
+            )
+            .unwrap();
+        ledger
+            .record_send_outcome(
+                &bond,
+                700,
+                &SendOutcome::Unknown {
+                    signature,
+                    expiry: TransactionExpiry::BlockHeight(1_000),
+                    error: "RPC unavailable".to_string(),
+                },
+                None,
+            )
+            .unwrap();
+
+        // The transaction may have landed, so the payout is not sent again before it is checked.
+        let record = ledger.blocking_record(&bond, 700).unwrap();
+        assert_eq!(record.status, PayoutStatus::Pending);
+        assert_eq!(record.signature, Some(signature.to_string()));
+        assert_eq!(record.expiry, Some(TransactionExpiry::BlockHeight(1_000)));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_failed_payout_does_not_block() {
+        let path = temp_ledger_path("failed");
+        let bond = Pubkey::new_unique();
+        let mut ledger = PayoutLedger::open(&path).unwrap();
+        ledger
+            .record_pending(
+                &bond,
+                &Pubkey::new_unique(),
+                700,
+                ExcessRewards::default(),
+                0,
+                1_000,
+            )
+            .unwrap();
+        ledger
+            .record_failed(&bond, 700, "blockhash expired")
+            .unwrap();
+        assert!(ledger.blocking_record(&bond, 700).is_none());
+        assert_eq!(
+            ledger.get(&bond, 700).unwrap().error.as_deref(),
+            Some("blockhash expired")
+        );
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_proposed_payout_blocks_repayment() {
+        let path = temp_ledger_path("proposed");
+        let bond = Pubkey::new_unique();
+        let proposal = Pubkey::new_unique();
+        let mut ledger = PayoutLedger::open(&path).unwrap();
+        ledger
+            .record_pending(
+                &bond,
+                &Pubkey::new_unique(),
+                700,
+                ExcessRewards::default(),
+                0,
+                1_000,
+            )
+            .unwrap();
+        let (created, executed) = (Signature::new_unique(), Signature::new_unique());
+        ledger
+            .record_proposed(&bond, 700, &proposal, &created)
+            .unwrap();
+        let reopened = PayoutLedger::open(&path).unwrap();
+        let record = reopened.blocking_record(&bond, 700).cloned().unwrap();
+        assert_eq!(record.status, PayoutStatus::Proposed);
+        assert_eq!(record.proposal, Some(proposal.to_string()));
+        assert_eq!(record.proposal_signature, Some(created.to_string()));
+        // Creating the proposal paid nothing.
+        assert_eq!(record.signature, None);
+        assert!(reopened.find_by_signature(&created.to_string()).is_empty());
+        assert_eq!(reopened.proposed().len(), 1);
+
+        // The bond is paid by the transaction executing the proposal.
+        ledger.record_confirmed(&bond, 700, &executed).unwrap();
+        let record = ledger.blocking_record(&bond, 700).unwrap();
+        assert_eq!(record.status, PayoutStatus::Confirmed);
+        assert_eq!(record.proposal_signature, Some(created.to_string()));
+        assert_eq!(ledger.find_by_signature(&executed.to_string()).len(), 1);
+        assert!(ledger.proposed().is_empty());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_apply_carry_forward() {
+        assert_eq!(apply_carry_forward(1_000, 0), (1_000, 0));
+        assert_eq!(apply_carry_forward(-400, 0), (0, -400));
+        assert_eq!(apply_carry_forward(1_000, -400), (600, 0));
+        assert_eq!(apply_carry_forward(300, -400), (0, -100));
+        assert_eq!(apply_carry_forward(-300, -400), (0, -700));
+        assert_eq!(apply_carry_forward(0, 0), (0, 0));
+    }
+
+    #[test]
+    fn test_carried_forward_epoch_updates_balance() {
+        let path = temp_ledger_path("carry");
+        let bond = Pubkey::new_unique();
+        let vote = Pubkey::new_unique();
+        let mut ledger = PayoutLedger::open(&path).unwrap();
+        assert_eq!(ledger.carry_balance(&bond, 701).unwrap(), 0);
+
+        ledger
+            .record_carried_forward(&bond, &vote, 700, ExcessRewards::default(), 0, -400)
+            .unwrap();
+        let reopened = PayoutLedger::open(&path).unwrap();
+        assert_eq!(reopened.carry_balance(&bond, 701).unwrap(), -400);
+        assert_eq!(reopened.carry_balance(&bond, 705).unwrap(), -400);
+        // The balance only flows forward, so backfilling an earlier epoch does not see it.
+        assert_eq!(reopened.carry_balance(&bond, 700).unwrap(), 0);
+        assert_eq!(
+            reopened.blocking_record(&bond, 700).unwrap().status,
+            PayoutStatus::CarriedForward
+        );
+
+        // Paying epoch 702 settles the balance for the epochs after it.
+        ledger
+            .record_pending(&bond, &vote, 702, ExcessRewards::default(), -400, 600)
+            .unwrap();
+        assert_eq!(ledger.carry_balance(&bond, 703).unwrap(), 0);
+        assert_eq!(ledger.carry_balance(&bond, 702).unwrap(), -400);
+        // A failed payout is recomputed from the balance carried before it, and the epochs after
+        // it wait until it is.
+        ledger
+            .record_failed(&bond, 702, "blockhash expired")
+            .unwrap();
+        assert_eq!(ledger.carry_balance(&bond, 702).unwrap(), -400);
+        assert!(ledger.carry_balance(&bond, 703).is_err());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_retried_epoch_between_others_carries_once() {
+        let path = temp_ledger_path("carry-retry");
+        let bond = Pubkey::new_unique();
+        let vote = Pubkey::new_unique();
+        let mut ledger = PayoutLedger::open(&path).unwrap();
+        ledger
+            .record_carried_forward(&bond, &vote, 700, ExcessRewards::default(), 0, -400)
+            .unwrap();
+        ledger
+            .record_calculation_failed(&bond, &vote, 701, "block rewards unavailable", Some(100))
+            .unwrap();
+
+        // Epoch 702 cannot start from epoch 700's balance while epoch 701 may still consume it.
+        assert!(ledger.carry_balance(&bond, 702).is_err());
+
+        // Retrying epoch 701 carries epoch 700's balance into it, and on into epoch 702.
+        let carry_in = ledger.carry_balance(&bond, 701).unwrap();
+        assert_eq!(carry_in, -400);
+        let (amount, carry_out) = apply_carry_forward(300, carry_in);
+        assert_eq!(amount, 0);
+        ledger
+            .record_carried_forward(
+                &bond,
+                &vote,
+                701,
+                ExcessRewards::default(),
+                carry_in,
+                carry_out,
+            )
+            .unwrap();
+        assert_eq!(ledger.carry_balance(&bond, 702).unwrap(), -100);
+        let (amount, _) = apply_carry_forward(1_000, ledger.carry_balance(&bond, 702).unwrap());
+        assert_eq!(amount, 900);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_repaid_record_keeps_previous_payments() {
+        let path = temp_ledger_path("repaid");
+        let bond = Pubkey::new_unique();
+        let vote = Pubkey::new_unique();
+        let (failed, first, second) = (
+            Signature::new_unique(),
+            Signature::new_unique(),
+            Signature::new_unique(),
+        );
+        let mut ledger = PayoutLedger::open(&path).unwrap();
+        ledger
+            .record_pending(&bond, &vote, 700, ExcessRewards::default(), 0, 300)
+            .unwrap();
+        ledger
+            .record_send_outcome(
+                &bond,
+                700,
+                &SendOutcome::Failed {
+                    signature: failed,
+                    error: "insufficient funds".to_string(),
+                },
+                None,
+            )
+            .unwrap();
+        // A failed attempt paid nothing, so it is not kept.
+        ledger
+            .record_pending(&bond, &vote, 700, ExcessRewards::default(), 0, 300)
+            .unwrap();
+        ledger.record_confirmed(&bond, 700, &first).unwrap();
+        // Paid again with --force-repay.
+        ledger
+            .record_pending(&bond, &vote, 700, ExcessRewards::default(), 0, 400)
+            .unwrap();
+        ledger.record_confirmed(&bond, 700, &second).unwrap();
+
+        let reopened = PayoutLedger::open(&path).unwrap();
+        let record = reopened.get(&bond, 700).unwrap();
+        assert_eq!(
+            record.previous_payments,
+            vec![PreviousPayment {
+                signature: first.to_string(),
+                amount: 300,
+            }]
+        );
+        assert_eq!(record.amount_paid_by(&first.to_string()), Some(300));
+        assert_eq!(record.amount_paid_by(&second.to_string()), Some(400));
+        assert_eq!(reopened.find_by_signature(&first.to_string()).len(), 1);
+        assert!(reopened.find_by_signature(&failed.to_string()).is_empty());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_concurrent_ledgers_keep_each_others_records() {
+        let path = temp_ledger_path("concurrent");
+        let (bond, other_bond) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let vote = Pubkey::new_unique();
+        // A long-running process and a short one, both loaded before either wrote.
+        let mut daemon = PayoutLedger::open(&path).unwrap();
+        let mut run = PayoutLedger::open(&path).unwrap();
+        run.record_pending(&bond, &vote, 700, ExcessRewards::default(), 0, 1_000)
+            .unwrap();
+        run.record_confirmed(&bond, 700, &Signature::new_unique())
+            .unwrap();
+
+        daemon
+            .record_pending(&other_bond, &vote, 700, ExcessRewards::default(), 0, 500)
+            .unwrap();
+        daemon.set_checkpoint(&vote, 700).unwrap();
+        // The daemon's writes kept the other process's payout, and picked it up.
+        assert!(daemon.blocking_record(&bond, 700).is_some());
+        let reopened = PayoutLedger::open(&path).unwrap();
+        assert_eq!(
+            reopened.get(&bond, 700).unwrap().status,
+            PayoutStatus::Confirmed
+        );
+        assert!(reopened.get(&other_bond, 700).is_some());
+        assert_eq!(reopened.checkpoint(&vote), Some(700));
+
+        // Paying an epoch another process paid since it was last read is refused.
+        run.record_confirmed(&bond, 700, &Signature::new_unique())
+            .unwrap();
+        let mut stale = PayoutLedger::open(&path).unwrap();
+        run.record_failed(&bond, 700, "retried elsewhere").unwrap();
+        assert!(stale
+            .record_pending(&bond, &vote, 700, ExcessRewards::default(), 0, 1_000)
+            .is_err());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_calculation_failure_is_retried_when_due() {
+        let path = temp_ledger_path("retry");
+        let bond = Pubkey::new_unique();
+        let vote = Pubkey::new_unique();
+        let mut ledger = PayoutLedger::open(&path).unwrap();
+        ledger
+            .record_calculation_failed(&bond, &vote, 700, "block rewards unavailable", Some(100))
+            .unwrap();
+
+        assert!(ledger.blocking_record(&bond, 700).is_none());
+        assert!(ledger.due_retries(&vote, 99).is_empty());
+        let due = ledger.due_retries(&vote, 100);
+        assert_eq!(due.len(), 1);
+        assert_eq!(due[0].epoch, 700);
+        assert_eq!(due[0].error.as_deref(), Some("block rewards unavailable"));
+        assert!(ledger.due_retries(&Pubkey::new_unique(), 100).is_empty());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_failed_attempts_back_off() {
+        let path = temp_ledger_path("attempts");
+        let bond = Pubkey::new_unique();
+        let vote = Pubkey::new_unique();
+        let mut ledger = PayoutLedger::open(&path).unwrap();
+        ledger
+            .record_calculation_failed(&bond, &vote, 700, "MEV data unavailable", None)
+            .unwrap();
+        let rewards = ExcessRewards {
+            bond_active_stake: 1_000,
+            inflation: 10,
+            mev: 20,
+            block: 30,
+        };
+        ledger
+            .record_pending(&bond, &vote, 700, rewards, 0, 1_000)
+            .unwrap();
+        ledger
+            .record_failed(&bond, 700, "blockhash expired")
+            .unwrap();
+        ledger
+            .record_calculation_failed(&bond, &vote, 700, "MEV data unavailable", Some(100))
+            .unwrap();
+        let reopened = PayoutLedger::open(&path).unwrap();
+        let record = reopened.get(&bond, 700).unwrap();
+        assert_eq!(record.attempts, 3);
+        // The amounts computed by the earlier attempt are kept.
+        assert_eq!(record.rewards, rewards);
+        assert_eq!(record.retry_at, Some(100));
+
+        assert_eq!(retry_backoff(1_800, 86_400, 1), 1_800);
+        assert_eq!(retry_backoff(1_800, 86_400, 3), 7_200);
+        assert_eq!(retry_backoff(1_800, 86_400, 10), 86_400);
+        assert_eq!(retry_backoff(1_800, 86_400, u32::MAX), 86_400);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_checkpoint_only_moves_forward() {
+        let path = temp_ledger_path("checkpoint");
+        let vote = Pubkey::new_unique();
+        let mut ledger = PayoutLedger::open(&path).unwrap();
+        assert_eq!(ledger.checkpoint(&vote), None);
+        ledger.set_checkpoint(&vote, 700).unwrap();
+        ledger.set_checkpoint(&vote, 650).unwrap();
+        let reopened = PayoutLedger::open(&path).unwrap();
+        assert_eq!(reopened.checkpoint(&vote), Some(700));
+        assert_eq!(reopened.checkpoint(&Pubkey::new_unique()), None);
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,14 +1,31 @@
 use anyhow::Result;
+use approval::ApprovalArgs;
 use clap::{Parser, Subcommand};
+use commands::offline::*;
+use commands::reconcile::*;
 use commands::transfer_excess_rewards::*;
 use commands::validator_bond_manager::*;
+use providers::MevSourceArgs;
+use rewards::StakeDenominatorArgs;
+use transactions::TransactionArgs;
 
 pub mod accounts;
 pub mod active_stake;
+pub mod approval;
 pub mod commands;
+pub mod layout;
+pub mod ledger;
 pub mod metrics_helpers;
+pub mod multisig;
+pub mod nonce;
+pub mod priority_fees;
+pub mod providers;
 pub mod rewards;
 pub mod rpc_utils;
+pub mod sender;
+pub mod signer;
+#[cfg(test)]
+pub mod test_utils;
 pub mod transactions;
 
 #[derive(Parser, Debug)]
@@ -20,7 +37,8 @@ struct Cli {
 
 #[derive(Subcommand, Debug)]
 enum Commands {
-    /// Transfer excess rewards collected for the last completed epoch to SoloValiatorBond.
+    /// Transfer excess rewards collected for the last completed epoch (or the given past epochs)
+    /// to SoloValiatorBond.
     TransferExcessRewards {
         /// RPC Endpoint
         #[arg(
@@ -30,7 +48,8 @@ enum Commands {
             default_value = "https://api.mainnet-beta.solana.com"
         )]
         rpc: String,
-        /// Path to payer keypair
+        /// Payer signer: a keypair path, `prompt://`, `stdin` or the URL of a remote signer. With
+        /// `--export`, the payer's pubkey is enough.
         #[arg(short, long, env)]
         payer: String,
         /// SoloValidatorBond's pubkey
@@ -45,6 +64,49 @@ enum Commands {
         /// The wait time (in secs) between get_block RPC call retries.
         #[arg(long, env, default_value = "1800")]
         block_retry_delay: u64,
+        /// Number of getBlock calls packed into one batched JSON-RPC request. 1 disables batching.
+        #[arg(long, env, default_value = "20")]
+        block_batch_size: usize,
+        /// Path to the local payout ledger used to avoid paying a bond twice for the same epoch.
+        #[arg(long, env, default_value = "pye-payout-ledger.json")]
+        ledger: String,
+        /// Pay the bond even if the ledger shows the epoch as already paid.
+        #[arg(long, env)]
+        force_repay: bool,
+        /// Single past epoch to pay excess rewards for. Defaults to the last completed epoch.
+        #[arg(long, env, conflicts_with_all = ["from_epoch", "to_epoch"])]
+        epoch: Option<u64>,
+        /// First epoch of a range of past epochs to pay excess rewards for.
+        #[arg(long, env)]
+        from_epoch: Option<u64>,
+        /// Last epoch (inclusive) of the range. Defaults to the last completed epoch.
+        #[arg(long, env, requires = "from_epoch")]
+        to_epoch: Option<u64>,
+        /// Carry negative net excess rewards into the bond's next epoch instead of dropping them.
+        #[arg(long, env)]
+        carry_forward: bool,
+        #[command(flatten)]
+        mev: MevSourceArgs,
+        #[command(flatten)]
+        stake: StakeDenominatorArgs,
+        /// Treat reward components that cannot be fetched (block rewards, inflation rewards) as 0
+        /// instead of aborting the bond's payout for the epoch. This can underpay bond holders.
+        #[arg(long, env)]
+        permissive: bool,
+        #[command(flatten)]
+        transactions: TransactionArgs,
+        #[command(flatten)]
+        approval: ApprovalArgs,
+        /// Write the unsigned payout transactions to this file instead of sending them, to be
+        /// signed offline with `sign` and broadcast with `submit`.
+        #[arg(
+            long,
+            env,
+            visible_alias = "sign-only",
+            requires = "nonce_account",
+            conflicts_with_all = ["dry_run", "multisig"]
+        )]
+        export: Option<String>,
     },
 
     /// Will run the excess rewards stuff for all bonds owned by a validator
@@ -52,6 +114,24 @@ enum Commands {
         #[command(flatten)]
         args: ValidatorBondManagerArgs,
     },
+
+    /// Compare the excess rewards owed to bonds over an epoch range with the transfers made
+    Reconcile {
+        #[command(flatten)]
+        args: ReconcileArgs,
+    },
+
+    /// Sign payout transactions exported by `transfer-excess-rewards --export`
+    Sign {
+        #[command(flatten)]
+        args: SignArgs,
+    },
+
+    /// Broadcast signed payout transactions and record their outcome in the ledger
+    Submit {
+        #[command(flatten)]
+        args: SubmitArgs,
+    },
 }
 
 #[tokio::main]
@@ -71,17 +151,46 @@ async fn main() -> Result<()> {
             concurrency,
             dry_run,
             block_retry_delay,
+            block_batch_size,
+            ledger,
+            force_repay,
+            epoch,
+            from_epoch,
+            to_epoch,
+            carry_forward,
+            mev,
+            stake,
+            permissive,
+            transactions,
+            approval,
+            export,
         } => {
             handle_transfer_excess_rewards(TransferExcessRewardsArgs {
                 rpc,
-                payer_file_path: payer,
+                payer,
                 bond,
                 concurrency,
                 dry_run,
                 block_retry_delay,
+                block_batch_size,
+                ledger_path: ledger,
+                force_repay,
+                epoch,
+                from_epoch,
+                to_epoch,
+                carry_forward,
+                mev,
+                stake,
+                permissive,
+                transactions,
+                approval,
+                export_path: export,
             })
             .await
         }
         Commands::ValidatorBondManager { args } => handle_validator_bond_manager(args).await,
+        Commands::Reconcile { args } => handle_reconcile(args).await,
+        Commands::Sign { args } => handle_sign(args).await,
+        Commands::Submit { args } => handle_submit(args).await,
     }
 }
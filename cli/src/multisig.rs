@@ -0,0 +1,849 @@
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+use anchor_lang::prelude::{borsh, AnchorDeserialize, AnchorSerialize};
+use anchor_lang::system_program;
+use anyhow::{anyhow, Result};
+use clap::Parser;
+use log::{info, warn};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_client::rpc_config::RpcTransactionConfig;
+use solana_commitment_config::CommitmentConfig;
+use solana_sdk::{
+    account::Account,
+    hash::hash,
+    instruction::{AccountMeta, Instruction},
+    message::{Message, VersionedMessage},
+    packet::PACKET_DATA_SIZE,
+    pubkey,
+    pubkey::Pubkey,
+    signature::{Signature, SIGNATURE_BYTES},
+    signer::Signer,
+    transaction::Transaction,
+};
+use solana_transaction_status_client_types::UiTransactionEncoding;
+
+use crate::ledger::PayoutLedger;
+use crate::providers::ChainDataProvider;
+use crate::rpc_utils::new_rpc_client;
+use crate::sender::{send_with_rebroadcast, SendOutcome};
+use crate::transactions::{
+    build_payout_transactions, payout_instructions, BondTransfer, PayoutSigners, TransactionArgs,
+};
+
+/// Squads v4 multisig program on mainnet and devnet.
+pub const SQUADS_PROGRAM_ID: Pubkey = pubkey!("SQDS4ep65T869zMMBKyuUq6aD6EgTu8psMjkvj52pCf");
+
+const SEED_PREFIX: &[u8] = b"multisig";
+const SEED_VAULT: &[u8] = b"vault";
+const SEED_TRANSACTION: &[u8] = b"transaction";
+const SEED_PROPOSAL: &[u8] = b"proposal";
+
+/// Compute units requested by a transaction creating a vault transaction and its proposal.
+const PROPOSAL_COMPUTE_UNITS: u32 = 100_000;
+
+/// Pay from a Squads multisig vault by proposing the payouts to its members instead of sending
+/// them.
+#[derive(Parser, Debug, Clone)]
+pub struct MultisigArgs {
+    /// Squads v4 multisig whose vault pays the excess rewards. The payouts are created as vault
+    /// transaction proposals, to be approved and executed by the multisig members. `--payer`
+    /// must be a member allowed to initiate transactions; it also pays the proposals' rent.
+    #[arg(long, env)]
+    pub multisig: Option<Pubkey>,
+    /// Index of the multisig vault paying the excess rewards.
+    #[arg(long, env, default_value = "0", requires = "multisig")]
+    pub vault_index: u8,
+    /// Also cast the payer's approval vote on the proposals it creates.
+    #[arg(long, env, requires = "multisig")]
+    pub approve_proposals: bool,
+    /// The Squads multisig program ID
+    #[arg(long, env, default_value_t = SQUADS_PROGRAM_ID)]
+    pub squads_program_id: Pubkey,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Member {
+    pub key: Pubkey,
+    pub permissions: u8,
+}
+
+/// Layout of the Squads `Multisig` account, without the Anchor discriminator.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Multisig {
+    pub create_key: Pubkey,
+    pub config_authority: Pubkey,
+    pub threshold: u16,
+    pub time_lock: u32,
+    /// Index of the last vault or config transaction created.
+    pub transaction_index: u64,
+    pub stale_transaction_index: u64,
+    pub rent_collector: Option<Pubkey>,
+    pub bump: u8,
+    pub members: Vec<Member>,
+}
+
+impl Multisig {
+    pub fn discriminator() -> [u8; 8] {
+        anchor_discriminator("account:Multisig")
+    }
+
+    pub fn try_from_account(account: &Account, program_id: &Pubkey) -> Result<Self> {
+        let mut data = squads_account_data(account, program_id, "Multisig")?;
+        Self::deserialize(&mut data).map_err(|e| anyhow!("Failed to decode Multisig: {}", e))
+    }
+}
+
+/// Status of a Squads `Proposal`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq, Eq)]
+pub enum ProposalStatus {
+    Draft {
+        timestamp: i64,
+    },
+    Active {
+        timestamp: i64,
+    },
+    Rejected {
+        timestamp: i64,
+    },
+    Approved {
+        timestamp: i64,
+    },
+    /// Deprecated by Squads, kept for the layout.
+    Executing,
+    Executed {
+        timestamp: i64,
+    },
+    Cancelled {
+        timestamp: i64,
+    },
+}
+
+/// Leading fields of the Squads `Proposal` account, without the Anchor discriminator. The votes
+/// that follow are not decoded.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Proposal {
+    pub multisig: Pubkey,
+    pub transaction_index: u64,
+    pub status: ProposalStatus,
+}
+
+impl Proposal {
+    pub fn discriminator() -> [u8; 8] {
+        anchor_discriminator("account:Proposal")
+    }
+
+    pub fn try_from_account(account: &Account, program_id: &Pubkey) -> Result<Self> {
+        let mut data = squads_account_data(account, program_id, "Proposal")?;
+        Self::deserialize(&mut data).map_err(|e| anyhow!("Failed to decode Proposal: {}", e))
+    }
+}
+
+/// Data of the Squads account `name`, after its Anchor discriminator.
+fn squads_account_data<'a>(
+    account: &'a Account,
+    program_id: &Pubkey,
+    name: &str,
+) -> Result<&'a [u8]> {
+    if account.owner != *program_id {
+        return Err(anyhow!(
+            "{} account is owned by {}, expected {}",
+            name,
+            account.owner,
+            program_id
+        ));
+    }
+    let discriminator = anchor_discriminator(&format!("account:{}", name));
+    if account.data.len() < 8 || account.data[..8] != discriminator {
+        return Err(anyhow!("Account is not a Squads {}", name));
+    }
+    Ok(&account.data[8..])
+}
+
+pub async fn fetch_multisig(
+    client: &dyn ChainDataProvider,
+    multisig: &Pubkey,
+    program_id: &Pubkey,
+) -> Result<Multisig> {
+    let account = client
+        .get_account(multisig)
+        .await?
+        .ok_or_else(|| anyhow!("Multisig {} not found", multisig))?;
+    Multisig::try_from_account(&account, program_id)
+}
+
+fn anchor_discriminator(name: &str) -> [u8; 8] {
+    let mut discriminator = [0u8; 8];
+    discriminator.copy_from_slice(&hash(name.as_bytes()).to_bytes()[..8]);
+    discriminator
+}
+
+pub fn vault_address(program_id: &Pubkey, multisig: &Pubkey, vault_index: u8) -> Pubkey {
+    Pubkey::find_program_address(
+        &[SEED_PREFIX, multisig.as_ref(), SEED_VAULT, &[vault_index]],
+        program_id,
+    )
+    .0
+}
+
+pub fn transaction_address(program_id: &Pubkey, multisig: &Pubkey, index: u64) -> Pubkey {
+    Pubkey::find_program_address(
+        &[
+            SEED_PREFIX,
+            multisig.as_ref(),
+            SEED_TRANSACTION,
+            &index.to_le_bytes(),
+        ],
+        program_id,
+    )
+    .0
+}
+
+pub fn proposal_address(program_id: &Pubkey, multisig: &Pubkey, index: u64) -> Pubkey {
+    Pubkey::find_program_address(
+        &[
+            SEED_PREFIX,
+            multisig.as_ref(),
+            SEED_TRANSACTION,
+            &index.to_le_bytes(),
+            SEED_PROPOSAL,
+        ],
+        program_id,
+    )
+    .0
+}
+
+/// Serializes the instructions executed by the vault as the Squads `TransactionMessage`: a
+/// legacy message whose vectors are prefixed with a u8 length, except instruction data which
+/// uses a u16 length.
+pub fn vault_transaction_message(vault: &Pubkey, instructions: &[Instruction]) -> Vec<u8> {
+    let message = Message::new(instructions, Some(vault));
+    let header = message.header;
+    let num_signers = header.num_required_signatures;
+    let num_writable_signers = num_signers - header.num_readonly_signed_accounts;
+    let num_writable_non_signers =
+        message.account_keys.len() as u8 - num_signers - header.num_readonly_unsigned_accounts;
+
+    let mut data = vec![
+        num_signers,
+        num_writable_signers,
+        num_writable_non_signers,
+        message.account_keys.len() as u8,
+    ];
+    for key in &message.account_keys {
+        data.extend_from_slice(key.as_ref());
+    }
+    data.push(message.instructions.len() as u8);
+    for instruction in &message.instructions {
+        data.push(instruction.program_id_index);
+        data.push(instruction.accounts.len() as u8);
+        data.extend_from_slice(&instruction.accounts);
+        data.extend_from_slice(&(instruction.data.len() as u16).to_le_bytes());
+        data.extend_from_slice(&instruction.data);
+    }
+    // No address lookup tables.
+    data.push(0);
+    data
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+struct VaultTransactionCreateArgs {
+    vault_index: u8,
+    ephemeral_signers: u8,
+    transaction_message: Vec<u8>,
+    memo: Option<String>,
+}
+
+#[derive(AnchorSerialize)]
+struct ProposalCreateArgs {
+    transaction_index: u64,
+    draft: bool,
+}
+
+#[derive(AnchorSerialize)]
+struct ProposalVoteArgs {
+    memo: Option<String>,
+}
+
+fn instruction_data(name: &str, args: &impl AnchorSerialize) -> Vec<u8> {
+    let mut data = anchor_discriminator(&format!("global:{}", name)).to_vec();
+    args.serialize(&mut data)
+        .expect("serializing to a Vec cannot fail");
+    data
+}
+
+/// Instructions creating vault transaction `transaction_index` of `multisig`, which executes
+/// `vault_instructions`, and its proposal. `creator` pays the rent of both accounts and votes
+/// for the proposal if `approve` is set.
+pub fn proposal_instructions(
+    program_id: &Pubkey,
+    multisig: &Pubkey,
+    creator: &Pubkey,
+    vault_index: u8,
+    transaction_index: u64,
+    vault_instructions: &[Instruction],
+    approve: bool,
+) -> Vec<Instruction> {
+    let vault = vault_address(program_id, multisig, vault_index);
+    let transaction = transaction_address(program_id, multisig, transaction_index);
+    let proposal = proposal_address(program_id, multisig, transaction_index);
+
+    let mut instructions = vec![
+        Instruction {
+            program_id: *program_id,
+            accounts: vec![
+                AccountMeta::new(*multisig, false),
+                AccountMeta::new(transaction, false),
+                AccountMeta::new_readonly(*creator, true),
+                AccountMeta::new(*creator, true),
+                AccountMeta::new_readonly(system_program::ID, false),
+            ],
+            data: instruction_data(
+                "vault_transaction_create",
+                &VaultTransactionCreateArgs {
+                    vault_index,
+                    ephemeral_signers: 0,
+                    transaction_message: vault_transaction_message(&vault, vault_instructions),
+                    memo: None,
+                },
+            ),
+        },
+        Instruction {
+            program_id: *program_id,
+            accounts: vec![
+                AccountMeta::new_readonly(*multisig, false),
+                AccountMeta::new(proposal, false),
+                AccountMeta::new_readonly(*creator, true),
+                AccountMeta::new(*creator, true),
+                AccountMeta::new_readonly(system_program::ID, false),
+            ],
+            data: instruction_data(
+                "proposal_create",
+                &ProposalCreateArgs {
+                    transaction_index,
+                    draft: false,
+                },
+            ),
+        },
+    ];
+    if approve {
+        instructions.push(Instruction {
+            program_id: *program_id,
+            accounts: vec![
+                AccountMeta::new_readonly(*multisig, false),
+                AccountMeta::new(*creator, true),
+                AccountMeta::new(proposal, false),
+            ],
+            data: instruction_data("proposal_approve", &ProposalVoteArgs { memo: None }),
+        });
+    }
+    instructions
+}
+
+/// A batch of transfers proposed to the multisig members as one vault transaction.
+#[derive(Debug, Clone)]
+pub struct ProposedPayout {
+    pub transfers: Vec<BondTransfer>,
+    pub transaction_index: u64,
+    pub proposal: Pubkey,
+    /// Outcome of the transaction creating the proposal.
+    pub outcome: SendOutcome,
+}
+
+/// Creates one vault transaction proposal per batch of transfers, paid from the multisig vault.
+/// The payouts are checked against the vault's balance and simulated first; any failure there
+/// aborts them all. Proposals are created in order, and the batches after one that could not be
+/// proposed are not proposed either.
+pub async fn propose_payouts(
+    signers: &PayoutSigners,
+    rpc_url: &str,
+    batches: Vec<Vec<BondTransfer>>,
+    tx_args: &TransactionArgs,
+) -> Result<Vec<ProposedPayout>> {
+    let args = &tx_args.multisig;
+    let multisig = args
+        .multisig
+        .ok_or_else(|| anyhow!("--multisig is required to propose payouts"))?;
+    let creator = &signers.payer;
+    let rpc = new_rpc_client(rpc_url.to_string());
+    let vault = vault_address(&args.squads_program_id, &multisig, args.vault_index);
+    info!("Proposing payouts from vault {} of {}", vault, multisig);
+
+    let payouts = build_payout_transactions(&rpc, &vault, batches, tx_args, &[]).await?;
+    // The proposals are created one after the other, so they can share the nonce accounts.
+    let nonces = tx_args
+        .nonce
+        .fetch_nonces(&rpc, Some(&signers.nonce_authority_pubkey()))
+        .await?;
+    let mut transaction_index = fetch_multisig(&rpc, &multisig, &args.squads_program_id)
+        .await?
+        .transaction_index;
+
+    let mut proposed = vec![];
+    let mut failed = false;
+    for (index, payout) in payouts.into_iter().enumerate() {
+        transaction_index += 1;
+        let proposal = proposal_address(&args.squads_program_id, &multisig, transaction_index);
+        let outcome = if failed {
+            SendOutcome::Dropped {
+                signatures: vec![],
+                error: "Not proposed: a previous proposal failed".to_string(),
+            }
+        } else {
+            // Compute budget instructions cannot be executed by the vault, so only the payout
+            // instructions are proposed.
+            let vault_instructions =
+                payout_instructions(&vault, &payout.transfers, tx_args.payout_method);
+            let compute_budget = tx_args
+                .priority_fees
+                .compute_budget(&rpc, &[creator.pubkey(), multisig], PROPOSAL_COMPUTE_UNITS)
+                .await;
+            let instructions = [
+                compute_budget.instructions(),
+                proposal_instructions(
+                    &args.squads_program_id,
+                    &multisig,
+                    &creator.pubkey(),
+                    args.vault_index,
+                    transaction_index,
+                    &vault_instructions,
+                    args.approve_proposals,
+                ),
+            ]
+            .concat();
+            let nonce = (!nonces.is_empty()).then(|| nonces[index % nonces.len()]);
+            let message = match &nonce {
+                Some(nonce) => Message::new_with_nonce(
+                    instructions,
+                    Some(&creator.pubkey()),
+                    &nonce.account,
+                    &nonce.authority,
+                ),
+                None => Message::new(&instructions, Some(&creator.pubkey())),
+            };
+            let size = 1
+                + message.header.num_required_signatures as usize * SIGNATURE_BYTES
+                + message.serialize().len();
+            if size > PACKET_DATA_SIZE {
+                SendOutcome::Dropped {
+                    signatures: vec![],
+                    error: format!(
+                        "Proposal transaction is {} bytes, over the {} byte limit: lower --max-transfers-per-tx",
+                        size, PACKET_DATA_SIZE
+                    ),
+                }
+            } else {
+                let signers = signers.signers();
+                let sign = |blockhash| {
+                    let mut signed = Transaction::new_unsigned(message.clone());
+                    signed.try_sign(&signers, blockhash)?;
+                    Ok(signed)
+                };
+                let nonce_account = nonce.as_ref().map(|nonce| &nonce.account);
+                send_with_rebroadcast(&rpc, &tx_args.sender, nonce_account, &sign).await
+            }
+        };
+        match &outcome {
+            SendOutcome::Confirmed { signature } => println!(
+                "Proposal {} (transaction {}) created: {}\n",
+                proposal, transaction_index, signature
+            ),
+            outcome => {
+                failed = true;
+                println!("Proposal not created: {:?}\n", outcome);
+            }
+        }
+        proposed.push(ProposedPayout {
+            transfers: payout.transfers,
+            transaction_index,
+            proposal,
+            outcome,
+        });
+    }
+    Ok(proposed)
+}
+
+/// Whether `message` executes the vault transaction of `proposal` through `program_id`.
+fn executes_proposal(message: &VersionedMessage, program_id: &Pubkey, proposal: &Pubkey) -> bool {
+    let keys = message.static_account_keys();
+    let execute = anchor_discriminator("global:vault_transaction_execute");
+    message.instructions().iter().any(|instruction| {
+        keys.get(instruction.program_id_index as usize) == Some(program_id)
+            && instruction.data.starts_with(&execute)
+            && instruction
+                .accounts
+                .get(1)
+                .and_then(|index| keys.get(*index as usize))
+                == Some(proposal)
+    })
+}
+
+/// Finds the successful transaction that executed `proposal`, among the ones it was used by.
+async fn find_proposal_execution(
+    rpc: &RpcClient,
+    program_id: &Pubkey,
+    proposal: &Pubkey,
+) -> Result<Option<Signature>> {
+    let statuses = rpc
+        .get_signatures_for_address(proposal)
+        .await
+        .map_err(|e| anyhow!("Failed to fetch signatures for {}: {}", proposal, e))?;
+    for status in statuses.iter().filter(|status| status.err.is_none()) {
+        let signature = Signature::from_str(&status.signature)?;
+        let transaction = rpc
+            .get_transaction_with_config(
+                &signature,
+                RpcTransactionConfig {
+                    encoding: Some(UiTransactionEncoding::Base64),
+                    commitment: Some(CommitmentConfig::confirmed()),
+                    max_supported_transaction_version: Some(0),
+                },
+            )
+            .await
+            .map_err(|e| anyhow!("Failed to fetch transaction {}: {}", signature, e))?;
+        if let Some(transaction) = transaction.transaction.transaction.decode() {
+            if executes_proposal(&transaction.message, program_id, proposal) {
+                return Ok(Some(signature));
+            }
+        }
+    }
+    Ok(None)
+}
+
+/// Checks the proposals of the proposed payouts. Payouts whose proposal was executed are
+/// confirmed with the executing transaction, and those whose proposal was rejected or cancelled
+/// fail. Returns the payouts that failed.
+pub async fn check_proposals(
+    rpc: &RpcClient,
+    program_id: &Pubkey,
+    ledger: &mut PayoutLedger,
+) -> Result<Vec<(Pubkey, u64)>> {
+    let mut payouts: BTreeMap<Pubkey, Vec<(Pubkey, u64)>> = BTreeMap::new();
+    for record in ledger.proposed() {
+        let Some(proposal) = &record.proposal else {
+            continue;
+        };
+        let proposal = Pubkey::from_str(proposal)
+            .map_err(|e| anyhow!("Invalid proposal {} in ledger: {}", proposal, e))?;
+        let bond_pubkey = Pubkey::from_str(&record.bond)
+            .map_err(|e| anyhow!("Invalid bond {} in ledger: {}", record.bond, e))?;
+        payouts
+            .entry(proposal)
+            .or_default()
+            .push((bond_pubkey, record.epoch));
+    }
+
+    let mut failed = vec![];
+    for (proposal, payouts) in payouts {
+        let account = rpc
+            .get_account_with_commitment(&proposal, CommitmentConfig::confirmed())
+            .await
+            .map_err(|e| anyhow!("Failed to fetch proposal {}: {}", proposal, e))?
+            .value;
+        // An executed proposal can be closed to reclaim its rent.
+        let status = match &account {
+            Some(account) => Some(Proposal::try_from_account(account, program_id)?.status),
+            None => None,
+        };
+        match status {
+            Some(ProposalStatus::Executed { .. }) | None => {
+                match find_proposal_execution(rpc, program_id, &proposal).await? {
+                    Some(signature) => {
+                        info!("Proposal {} executed by {}", proposal, signature);
+                        for (bond_pubkey, epoch) in payouts {
+                            ledger.record_confirmed(&bond_pubkey, epoch, &signature)?;
+                        }
+                    }
+                    None => warn!(
+                        "No transaction executing proposal {} was found, its payouts stay proposed",
+                        proposal
+                    ),
+                }
+            }
+            Some(ProposalStatus::Rejected { .. }) | Some(ProposalStatus::Cancelled { .. }) => {
+                info!("Proposal {} was rejected or cancelled", proposal);
+                for (bond_pubkey, epoch) in payouts {
+                    ledger.record_failed(
+                        &bond_pubkey,
+                        epoch,
+                        &format!("Proposal {} was rejected or cancelled", proposal),
+                    )?;
+                    failed.push((bond_pubkey, epoch));
+                }
+            }
+            Some(status) => info!("Proposal {} is not executed yet: {:?}", proposal, status),
+        }
+    }
+    Ok(failed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::system_instruction::transfer;
+
+    #[test]
+    fn test_multisig_account() {
+        let members = vec![
+            Member {
+                key: Pubkey::new_unique(),
+                permissions: 7,
+            },
+            Member {
+                key: Pubkey::new_unique(),
+                permissions: 2,
+            },
+        ];
+        let multisig = Multisig {
+            create_key: Pubkey::new_unique(),
+            config_authority: Pubkey::default(),
+            threshold: 2,
+            time_lock: 0,
+            transaction_index: 41,
+            stale_transaction_index: 40,
+            rent_collector: None,
+            bump: 255,
+            members,
+        };
+        let mut data = Multisig::discriminator().to_vec();
+        multisig.serialize(&mut data).unwrap();
+        let mut account = Account {
+            lamports: 1_000_000,
+            data,
+            owner: SQUADS_PROGRAM_ID,
+            executable: false,
+            rent_epoch: 0,
+        };
+        assert_eq!(
+            Multisig::try_from_account(&account, &SQUADS_PROGRAM_ID).unwrap(),
+            multisig
+        );
+        // The transaction index sits right after the fixed-size fields.
+        assert_eq!(account.data[78..86], 41u64.to_le_bytes());
+
+        account.owner = Pubkey::new_unique();
+        assert!(Multisig::try_from_account(&account, &SQUADS_PROGRAM_ID).is_err());
+    }
+
+    #[test]
+    fn test_vault_transaction_message() {
+        let vault = Pubkey::new_unique();
+        let (bond_a, bond_b) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let data = vault_transaction_message(
+            &vault,
+            &[
+                transfer(&vault, &bond_a, 1_000),
+                transfer(&vault, &bond_b, 2_000),
+            ],
+        );
+
+        // The vault is the only (writable) signer, the bonds are writable and the system
+        // program is read-only.
+        assert_eq!(data[..4], [1, 1, 2, 4]);
+        let keys: Vec<&[u8]> = data[4..4 + 4 * 32].chunks(32).collect();
+        assert_eq!(keys[0], vault.as_ref());
+        assert_eq!(keys[3], system_program::ID.as_ref());
+        let instructions = &data[4 + 4 * 32..];
+        assert_eq!(instructions[0], 2);
+        // Program index, the two account indexes, then the 12 bytes of transfer data.
+        assert_eq!(instructions[1..5], [3, 2, 0, 1]);
+        assert_eq!(instructions[5..7], 12u16.to_le_bytes());
+        let transfer_data = transfer(&vault, &bond_a, 1_000).data;
+        assert_eq!(instructions[7..19], transfer_data[..]);
+        assert_eq!(instructions[19..23], [3, 2, 0, 2]);
+        // No address table lookups after the second instruction.
+        assert_eq!(data.len(), 4 + 4 * 32 + 1 + 2 * 18 + 1);
+        assert_eq!(*data.last().unwrap(), 0);
+    }
+
+    #[test]
+    fn test_proposal_instructions() {
+        let multisig = Pubkey::new_unique();
+        let creator = Pubkey::new_unique();
+        let vault = vault_address(&SQUADS_PROGRAM_ID, &multisig, 0);
+        let instructions = proposal_instructions(
+            &SQUADS_PROGRAM_ID,
+            &multisig,
+            &creator,
+            0,
+            42,
+            &[transfer(&vault, &Pubkey::new_unique(), 1_000)],
+            true,
+        );
+        assert_eq!(instructions.len(), 3);
+        assert_eq!(
+            instructions[0].data[..8],
+            [48, 250, 78, 168, 208, 226, 218, 211]
+        );
+        assert_eq!(
+            instructions[0].accounts[1].pubkey,
+            transaction_address(&SQUADS_PROGRAM_ID, &multisig, 42)
+        );
+        assert_eq!(
+            instructions[1].data[..8],
+            [220, 60, 73, 224, 30, 108, 79, 159]
+        );
+        assert_eq!(instructions[1].data[8..16], 42u64.to_le_bytes());
+        let proposal = proposal_address(&SQUADS_PROGRAM_ID, &multisig, 42);
+        assert_eq!(instructions[1].accounts[1].pubkey, proposal);
+        assert_eq!(
+            instructions[2].data[..8],
+            [144, 37, 164, 136, 188, 216, 42, 248]
+        );
+        assert_eq!(instructions[2].accounts[2].pubkey, proposal);
+
+        assert_eq!(
+            proposal_instructions(&SQUADS_PROGRAM_ID, &multisig, &creator, 0, 42, &[], false).len(),
+            2
+        );
+    }
+
+    /// Rebuilds the vault instructions from a Squads `TransactionMessage` the way the program
+    /// does when it executes the vault transaction.
+    fn execute_transaction_message(data: &[u8]) -> Vec<Instruction> {
+        let (num_signers, num_writable_signers, num_writable_non_signers) =
+            (data[0] as usize, data[1] as usize, data[2] as usize);
+        let num_keys = data[3] as usize;
+        let keys: Vec<Pubkey> = data[4..4 + 32 * num_keys]
+            .chunks(32)
+            .map(|key| Pubkey::try_from(key).unwrap())
+            .collect();
+        let meta = |index: usize| AccountMeta {
+            pubkey: keys[index],
+            is_signer: index < num_signers,
+            is_writable: if index < num_signers {
+                index < num_writable_signers
+            } else {
+                index - num_signers < num_writable_non_signers
+            },
+        };
+        fn take<'a>(rest: &mut &'a [u8], len: usize) -> &'a [u8] {
+            let (taken, remaining) = rest.split_at(len);
+            *rest = remaining;
+            taken
+        }
+        let mut rest = &data[4 + 32 * num_keys..];
+        let mut instructions = vec![];
+        for _ in 0..take(&mut rest, 1)[0] {
+            let program_id = keys[take(&mut rest, 1)[0] as usize];
+            let num_accounts = take(&mut rest, 1)[0] as usize;
+            let accounts = take(&mut rest, num_accounts)
+                .iter()
+                .map(|index| meta(*index as usize))
+                .collect();
+            let data_len = u16::from_le_bytes(take(&mut rest, 2).try_into().unwrap()) as usize;
+            instructions.push(Instruction {
+                program_id,
+                accounts,
+                data: take(&mut rest, data_len).to_vec(),
+            });
+        }
+        // No address table lookups follow the last instruction.
+        assert_eq!(rest, [0]);
+        instructions
+    }
+
+    #[test]
+    fn test_proposal_round_trip() {
+        let multisig = Pubkey::new_unique();
+        let vault = vault_address(&SQUADS_PROGRAM_ID, &multisig, 1);
+        let vault_instructions = vec![
+            transfer(&vault, &Pubkey::new_unique(), 1_000),
+            transfer(&vault, &Pubkey::new_unique(), 2_000),
+        ];
+        let instructions = proposal_instructions(
+            &SQUADS_PROGRAM_ID,
+            &multisig,
+            &Pubkey::new_unique(),
+            1,
+            7,
+            &vault_instructions,
+            false,
+        );
+
+        let args =
+            VaultTransactionCreateArgs::deserialize(&mut &instructions[0].data[8..]).unwrap();
+        assert_eq!(args.vault_index, 1);
+        assert_eq!(args.ephemeral_signers, 0);
+        assert_eq!(
+            execute_transaction_message(&args.transaction_message),
+            vault_instructions
+        );
+    }
+
+    #[test]
+    fn test_proposal_account() {
+        let proposal = Proposal {
+            multisig: Pubkey::new_unique(),
+            transaction_index: 7,
+            status: ProposalStatus::Executed {
+                timestamp: 1_700_000_000,
+            },
+        };
+        let mut data = Proposal::discriminator().to_vec();
+        proposal.serialize(&mut data).unwrap();
+        // Bump and votes, which are not decoded.
+        data.extend_from_slice(&[254, 1, 0, 0, 0]);
+        data.extend_from_slice(Pubkey::new_unique().as_ref());
+        let account = Account {
+            lamports: 1_000_000,
+            data,
+            owner: SQUADS_PROGRAM_ID,
+            executable: false,
+            rent_epoch: 0,
+        };
+        assert_eq!(
+            Proposal::try_from_account(&account, &SQUADS_PROGRAM_ID).unwrap(),
+            proposal
+        );
+        // The status variant follows the multisig and the transaction index.
+        assert_eq!(account.data[48], 5);
+        assert!(Multisig::try_from_account(&account, &SQUADS_PROGRAM_ID).is_err());
+    }
+
+    #[test]
+    fn test_executes_proposal() {
+        let multisig = Pubkey::new_unique();
+        let member = Pubkey::new_unique();
+        let proposal = proposal_address(&SQUADS_PROGRAM_ID, &multisig, 7);
+        let execute = |proposal: Pubkey| Instruction {
+            program_id: SQUADS_PROGRAM_ID,
+            accounts: vec![
+                AccountMeta::new_readonly(multisig, false),
+                AccountMeta::new(proposal, false),
+                AccountMeta::new_readonly(
+                    transaction_address(&SQUADS_PROGRAM_ID, &multisig, 7),
+                    false,
+                ),
+                AccountMeta::new_readonly(member, true),
+            ],
+            data: anchor_discriminator("global:vault_transaction_execute").to_vec(),
+        };
+        let message = |instructions: &[Instruction]| {
+            VersionedMessage::Legacy(Message::new(instructions, Some(&member)))
+        };
+
+        assert!(executes_proposal(
+            &message(&[execute(proposal)]),
+            &SQUADS_PROGRAM_ID,
+            &proposal
+        ));
+        // Executing another proposal.
+        let other = proposal_address(&SQUADS_PROGRAM_ID, &multisig, 8);
+        assert!(!executes_proposal(
+            &message(&[execute(other)]),
+            &SQUADS_PROGRAM_ID,
+            &proposal
+        ));
+        // Approving the proposal.
+        let approve =
+            proposal_instructions(&SQUADS_PROGRAM_ID, &multisig, &member, 0, 7, &[], true)
+                .pop()
+                .unwrap();
+        assert!(!executes_proposal(
+            &message(&[approve]),
+            &SQUADS_PROGRAM_ID,
+            &proposal
+        ));
+    }
+}
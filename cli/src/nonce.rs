@@ -0,0 +1,162 @@
+use anyhow::{anyhow, Result};
+use clap::Parser;
+use solana_sdk::{
+    account::Account,
+    hash::Hash,
+    nonce::state::{State, Versions},
+    pubkey::Pubkey,
+};
+
+use crate::providers::ChainDataProvider;
+
+/// A durable nonce account and its current nonce, which stands in for the recent blockhash of
+/// the transactions it signs so they never expire.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Nonce {
+    pub account: Pubkey,
+    pub authority: Pubkey,
+    pub blockhash: Hash,
+}
+
+/// Durable nonce used by the transactions the CLI sends, so they stay valid while payouts are
+/// reviewed and approved.
+#[derive(Parser, Debug, Clone)]
+pub struct NonceArgs {
+    /// Durable nonce account used instead of a recent blockhash. Can be given several times (or
+    /// comma-separated): transactions are spread over the accounts, and transactions sharing an
+    /// account are sent one after the other.
+    #[arg(long, env, value_delimiter = ',')]
+    pub nonce_account: Vec<Pubkey>,
+    /// Signer of the nonce accounts' authority, in the same formats as `--payer`. Defaults to
+    /// the payer.
+    #[arg(long, env, requires = "nonce_account")]
+    pub nonce_authority: Option<String>,
+}
+
+impl NonceArgs {
+    /// Fetches the current nonce of every `--nonce-account`, checking that `authority`, if
+    /// given, is their authority.
+    pub async fn fetch_nonces(
+        &self,
+        client: &dyn ChainDataProvider,
+        authority: Option<&Pubkey>,
+    ) -> Result<Vec<Nonce>> {
+        let mut nonces = vec![];
+        for nonce_account in &self.nonce_account {
+            let nonce = fetch_nonce(client, nonce_account).await?;
+            if let Some(authority) = authority {
+                if nonce.authority != *authority {
+                    return Err(anyhow!(
+                        "Nonce account {} is controlled by {}, not {}",
+                        nonce_account,
+                        nonce.authority,
+                        authority
+                    ));
+                }
+            }
+            nonces.push(nonce);
+        }
+        Ok(nonces)
+    }
+}
+
+/// Fetches the current nonce of `nonce_account`.
+pub async fn fetch_nonce(client: &dyn ChainDataProvider, nonce_account: &Pubkey) -> Result<Nonce> {
+    let account = client
+        .get_account(nonce_account)
+        .await?
+        .ok_or_else(|| anyhow!("Nonce account {} not found", nonce_account))?;
+    parse_nonce_account(nonce_account, &account)
+}
+
+pub fn parse_nonce_account(nonce_account: &Pubkey, account: &Account) -> Result<Nonce> {
+    let versions = account
+        .deserialize_data::<Versions>()
+        .map_err(|e| anyhow!("{} is not a nonce account: {}", nonce_account, e))?;
+    match versions.state() {
+        State::Initialized(data) => Ok(Nonce {
+            account: *nonce_account,
+            authority: data.authority,
+            blockhash: data.blockhash(),
+        }),
+        State::Uninitialized => Err(anyhow!(
+            "Nonce account {} is not initialized",
+            nonce_account
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anchor_lang::system_program;
+    use solana_sdk::nonce::state::{Data, DurableNonce};
+
+    use super::*;
+    use crate::providers::fake::FakeChainData;
+
+    fn nonce_account_data(authority: &Pubkey, blockhash: &Hash) -> Account {
+        let state = Versions::new(State::Initialized(Data::new(
+            *authority,
+            DurableNonce::from_blockhash(blockhash),
+            5_000,
+        )));
+        Account::new_data(1_447_680, &state, &system_program::ID).unwrap()
+    }
+
+    #[test]
+    fn test_parse_nonce_account() {
+        let nonce_account = Pubkey::new_unique();
+        let authority = Pubkey::new_unique();
+        let blockhash = Hash::new_unique();
+        let durable_nonce = DurableNonce::from_blockhash(&blockhash);
+        let account = nonce_account_data(&authority, &blockhash);
+        assert_eq!(
+            parse_nonce_account(&nonce_account, &account).unwrap(),
+            Nonce {
+                account: nonce_account,
+                authority,
+                blockhash: *durable_nonce.as_hash(),
+            }
+        );
+
+        let uninitialized = Account::new_data(
+            1_447_680,
+            &Versions::new(State::Uninitialized),
+            &system_program::ID,
+        )
+        .unwrap();
+        assert!(parse_nonce_account(&nonce_account, &uninitialized).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_fetch_nonces_checks_authority() {
+        let authority = Pubkey::new_unique();
+        let (first, second) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let chain = FakeChainData::new(800)
+            .with_account(first, nonce_account_data(&authority, &Hash::new_unique()))
+            .with_account(second, nonce_account_data(&authority, &Hash::new_unique()));
+        let args = NonceArgs {
+            nonce_account: vec![first, second],
+            nonce_authority: None,
+        };
+
+        let nonces = args.fetch_nonces(&chain, Some(&authority)).await.unwrap();
+        assert_eq!(
+            nonces.iter().map(|nonce| nonce.account).collect::<Vec<_>>(),
+            vec![first, second]
+        );
+        assert!(args
+            .fetch_nonces(&chain, Some(&Pubkey::new_unique()))
+            .await
+            .is_err());
+        // Without an expected authority, any initialized nonce account is accepted.
+        assert_eq!(args.fetch_nonces(&chain, None).await.unwrap(), nonces);
+        assert!(NonceArgs {
+            nonce_account: vec![Pubkey::new_unique()],
+            nonce_authority: None,
+        }
+        .fetch_nonces(&chain, None)
+        .await
+        .is_err());
+    }
+}
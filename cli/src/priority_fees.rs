@@ -0,0 +1,156 @@
+use clap::Parser;
+use log::{info, warn};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_metrics::datapoint_info;
+use solana_sdk::{
+    compute_budget::ComputeBudgetInstruction, instruction::Instruction, pubkey::Pubkey,
+};
+
+/// Compute budget set on every transaction the CLI sends.
+#[derive(Parser, Debug, Clone)]
+pub struct PriorityFeeArgs {
+    /// Compute unit limit requested by each transaction. Defaults to an estimate based on the
+    /// payout method and the number of transfers in the transaction.
+    #[arg(long, env)]
+    pub compute_unit_limit: Option<u32>,
+    /// Fixed compute unit price in micro-lamports. Also used as the fallback when recent
+    /// prioritization fees cannot be fetched.
+    #[arg(long, env, default_value = "0")]
+    pub compute_unit_price: u64,
+    /// Derive the compute unit price from this percentile (0-100) of the recent prioritization
+    /// fees paid by transactions writing to the accounts of the transfer (payer and bond).
+    #[arg(long, env, value_parser = clap::value_parser!(u8).range(0..=100))]
+    pub priority_fee_percentile: Option<u8>,
+    /// Upper bound (in micro-lamports) on the compute unit price derived from
+    /// `--priority-fee-percentile`.
+    #[arg(long, env, default_value = "1000000")]
+    pub max_compute_unit_price: u64,
+}
+
+impl PriorityFeeArgs {
+    /// Returns the compute unit price, in micro-lamports, for a transaction writing to
+    /// `writable_accounts`.
+    pub async fn compute_unit_price(&self, rpc: &RpcClient, writable_accounts: &[Pubkey]) -> u64 {
+        let Some(percentile) = self.priority_fee_percentile else {
+            return self.compute_unit_price;
+        };
+        match rpc.get_recent_prioritization_fees(writable_accounts).await {
+            Ok(recent_fees) => {
+                let fees: Vec<u64> = recent_fees.iter().map(|f| f.prioritization_fee).collect();
+                let price = prioritization_fee_percentile(&fees, percentile)
+                    .min(self.max_compute_unit_price);
+                info!(
+                    "Compute unit price: {} micro-lamports (p{} of {} recent slots)",
+                    price,
+                    percentile,
+                    fees.len()
+                );
+                datapoint_info!(
+                    "priority_fee",
+                    ("percentile", percentile as i64, i64),
+                    ("samples", fees.len() as i64, i64),
+                    ("compute_unit_price", price as i64, i64),
+                );
+                price
+            }
+            Err(e) => {
+                warn!(
+                    "Failed to fetch recent prioritization fees, using {} micro-lamports: {}",
+                    self.compute_unit_price, e
+                );
+                self.compute_unit_price
+            }
+        }
+    }
+
+    /// Compute budget of a transaction writing to `writable_accounts`. `estimated_compute_units`
+    /// is used unless `--compute-unit-limit` is set.
+    pub async fn compute_budget(
+        &self,
+        rpc: &RpcClient,
+        writable_accounts: &[Pubkey],
+        estimated_compute_units: u32,
+    ) -> ComputeBudget {
+        let unit_price = self.compute_unit_price(rpc, writable_accounts).await;
+        let unit_limit = self.compute_unit_limit.unwrap_or(estimated_compute_units);
+        info!("Compute unit limit: {}", unit_limit);
+        ComputeBudget {
+            unit_limit,
+            unit_price,
+        }
+    }
+}
+
+/// Compute unit limit and price (in micro-lamports) of a transaction.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ComputeBudget {
+    pub unit_limit: u32,
+    pub unit_price: u64,
+}
+
+impl ComputeBudget {
+    /// Compute budget instructions to prepend to the transaction.
+    pub fn instructions(&self) -> Vec<Instruction> {
+        vec![
+            ComputeBudgetInstruction::set_compute_unit_limit(self.unit_limit),
+            ComputeBudgetInstruction::set_compute_unit_price(self.unit_price),
+        ]
+    }
+
+    /// Priority fee, in lamports, paid on top of the signature fees.
+    pub fn priority_fee(&self) -> u64 {
+        (self.unit_limit as u64 * self.unit_price).div_ceil(1_000_000)
+    }
+}
+
+/// Nearest-rank `percentile` of `fees`, or 0 if there are none.
+pub fn prioritization_fee_percentile(fees: &[u64], percentile: u8) -> u64 {
+    if fees.is_empty() {
+        return 0;
+    }
+    let mut sorted = fees.to_vec();
+    sorted.sort_unstable();
+    let rank = (sorted.len() * percentile as usize).div_ceil(100);
+    sorted[rank.saturating_sub(1)]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::compute_budget;
+
+    #[test]
+    fn test_prioritization_fee_percentile() {
+        let fees = [0, 0, 5_000, 100, 20, 0, 1_000, 10, 0, 50];
+        assert_eq!(prioritization_fee_percentile(&fees, 0), 0);
+        assert_eq!(prioritization_fee_percentile(&fees, 50), 10);
+        assert_eq!(prioritization_fee_percentile(&fees, 75), 100);
+        assert_eq!(prioritization_fee_percentile(&fees, 90), 1_000);
+        assert_eq!(prioritization_fee_percentile(&fees, 100), 5_000);
+        assert_eq!(prioritization_fee_percentile(&[], 75), 0);
+    }
+
+    #[test]
+    fn test_compute_budget() {
+        let budget = ComputeBudget {
+            unit_limit: 10_000,
+            unit_price: 25_000,
+        };
+        let ixs = budget.instructions();
+        assert_eq!(ixs.len(), 2);
+        assert!(ixs.iter().all(|ix| ix.program_id == compute_budget::id()));
+        assert_eq!(
+            ixs[1],
+            ComputeBudgetInstruction::set_compute_unit_price(25_000)
+        );
+        assert_eq!(budget.priority_fee(), 250);
+        assert_eq!(
+            ComputeBudget {
+                unit_limit: 1_300,
+                unit_price: 1,
+            }
+            .priority_fee(),
+            1
+        );
+    }
+}
@@ -0,0 +1,381 @@
+//! In-memory providers used to run the excess reward calculations offline in tests.
+
+use std::collections::HashMap;
+
+use anchor_lang::AccountSerialize;
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use pye_core_cpi::pye_core::accounts::SoloValidatorBond;
+use pye_core_cpi::pye_core::types::{RewardCommissions, SoloValidatorRedemptionCache};
+use solana_client::rpc_response::{
+    RpcInflationReward, RpcLeaderSchedule, RpcVoteAccountInfo, RpcVoteAccountStatus,
+};
+use solana_sdk::{
+    account::{create_account_for_test, Account},
+    epoch_info::EpochInfo,
+    epoch_schedule::EpochSchedule,
+    pubkey,
+    pubkey::Pubkey,
+    stake::{
+        stake_flags::StakeFlags,
+        state::{Delegation, Meta, Stake, StakeStateV2},
+    },
+    stake_history::StakeHistory,
+    sysvar::{self, slot_history::SlotHistory},
+};
+use solana_transaction_status_client_types::UiConfirmedBlock;
+
+use super::{ChainDataProvider, MevDataProvider};
+use crate::rewards::mev_rewards::ValidatorInfo;
+use crate::rpc_utils::PriorityFeeKeeperError;
+
+pub const STAKE_RENT_EXEMPT_RESERVE: u64 = 2_282_880;
+const STAKE_PROGRAM_ID: Pubkey = pubkey!("Stake11111111111111111111111111111111111111");
+const VOTE_PROGRAM_ID: Pubkey = pubkey!("Vote111111111111111111111111111111111111111");
+
+/// Chain data held in memory. Blocks that were not added are reported as skipped.
+pub struct FakeChainData {
+    epoch_schedule: EpochSchedule,
+    current_epoch: u64,
+    accounts: HashMap<Pubkey, Account>,
+    inflation_rewards: HashMap<(Pubkey, u64), RpcInflationReward>,
+    vote_accounts: Vec<RpcVoteAccountInfo>,
+    leader_schedules: HashMap<(String, u64), Vec<usize>>,
+    blocks: HashMap<u64, UiConfirmedBlock>,
+    block_times: HashMap<u64, i64>,
+    /// Vote transactions in each block, as the voting node and its vote account.
+    votes: HashMap<u64, Vec<(Pubkey, Pubkey)>>,
+}
+
+impl FakeChainData {
+    pub fn new(current_epoch: u64) -> Self {
+        let mut accounts = HashMap::new();
+        accounts.insert(
+            sysvar::stake_history::ID,
+            create_account_for_test(&StakeHistory::default()),
+        );
+        accounts.insert(
+            sysvar::slot_history::ID,
+            create_account_for_test(&SlotHistory::default()),
+        );
+        Self {
+            epoch_schedule: EpochSchedule::without_warmup(),
+            current_epoch,
+            accounts,
+            inflation_rewards: HashMap::new(),
+            vote_accounts: vec![],
+            leader_schedules: HashMap::new(),
+            blocks: HashMap::new(),
+            block_times: HashMap::new(),
+            votes: HashMap::new(),
+        }
+    }
+
+    pub fn epoch_schedule(&self) -> &EpochSchedule {
+        &self.epoch_schedule
+    }
+
+    pub fn with_account(mut self, pubkey: Pubkey, account: Account) -> Self {
+        self.accounts.insert(pubkey, account);
+        self
+    }
+
+    pub fn with_bond(self, bond_pubkey: Pubkey, bond: &SoloValidatorBond) -> Self {
+        let mut data = vec![];
+        bond.try_serialize(&mut data).unwrap();
+        self.with_account(
+            bond_pubkey,
+            Account {
+                lamports: 1_000_000,
+                data,
+                owner: pye_core_cpi::pye_core::ID,
+                executable: false,
+                rent_epoch: 0,
+            },
+        )
+    }
+
+    /// Adds a stake account delegated to `vote_pubkey` since genesis, so that all of its stake
+    /// is effective in every epoch.
+    pub fn with_stake_account(self, pubkey: Pubkey, vote_pubkey: &Pubkey, stake: u64) -> Self {
+        let state = StakeStateV2::Stake(
+            Meta {
+                rent_exempt_reserve: STAKE_RENT_EXEMPT_RESERVE,
+                ..Meta::default()
+            },
+            Stake {
+                delegation: Delegation::new(vote_pubkey, stake, 0),
+                credits_observed: 0,
+            },
+            StakeFlags::empty(),
+        );
+        let account =
+            Account::new_data(stake + STAKE_RENT_EXEMPT_RESERVE, &state, &STAKE_PROGRAM_ID)
+                .unwrap();
+        self.with_account(pubkey, account)
+    }
+
+    pub fn with_inflation_reward(
+        mut self,
+        pubkey: Pubkey,
+        epoch: u64,
+        amount: u64,
+        post_balance: u64,
+        commission: u8,
+    ) -> Self {
+        self.inflation_rewards.insert(
+            (pubkey, epoch),
+            RpcInflationReward {
+                epoch,
+                effective_slot: self.epoch_schedule.get_first_slot_in_epoch(epoch + 1),
+                amount,
+                post_balance,
+                commission: Some(commission),
+            },
+        );
+        self
+    }
+
+    pub fn with_validator(mut self, vote_pubkey: &Pubkey, node_pubkey: &Pubkey) -> Self {
+        self.vote_accounts.push(RpcVoteAccountInfo {
+            vote_pubkey: vote_pubkey.to_string(),
+            node_pubkey: node_pubkey.to_string(),
+            activated_stake: 0,
+            commission: 0,
+            epoch_vote_account: true,
+            epoch_credits: vec![],
+            last_vote: 0,
+            root_slot: 0,
+        });
+        self
+    }
+
+    /// Sets the slot indices, relative to the first slot of `epoch`, that `node_pubkey` leads.
+    pub fn with_leader_slots(
+        mut self,
+        node_pubkey: &Pubkey,
+        epoch: u64,
+        indices: Vec<usize>,
+    ) -> Self {
+        self.leader_schedules
+            .insert((node_pubkey.to_string(), epoch), indices);
+        self
+    }
+
+    pub fn with_block_time(mut self, slot: u64, block_time: i64) -> Self {
+        self.block_times.insert(slot, block_time);
+        self
+    }
+
+    /// Adds a vote of `vote_pubkey`, paid for by `node_pubkey`, to the block at `slot`.
+    pub fn with_vote(mut self, slot: u64, node_pubkey: &Pubkey, vote_pubkey: &Pubkey) -> Self {
+        self.votes
+            .entry(slot)
+            .or_default()
+            .push((*node_pubkey, *vote_pubkey));
+        self
+    }
+
+    /// Adds a block at `slot` whose leader earned `fees` lamports.
+    pub fn with_block(mut self, slot: u64, node_pubkey: &Pubkey, fees: u64) -> Self {
+        let block = serde_json::from_value(serde_json::json!({
+            "previousBlockhash": "11111111111111111111111111111111",
+            "blockhash": "11111111111111111111111111111111",
+            "parentSlot": slot.saturating_sub(1),
+            "rewards": [{
+                "pubkey": node_pubkey.to_string(),
+                "lamports": fees,
+                "postBalance": fees,
+                "rewardType": "Fee",
+                "commission": null,
+            }],
+            "blockTime": null,
+            "blockHeight": null,
+        }))
+        .unwrap();
+        self.blocks.insert(slot, block);
+        self
+    }
+}
+
+#[async_trait]
+impl ChainDataProvider for FakeChainData {
+    async fn get_epoch_info(&self) -> Result<EpochInfo> {
+        let slots_in_epoch = self.epoch_schedule.get_slots_in_epoch(self.current_epoch);
+        let absolute_slot = self
+            .epoch_schedule
+            .get_first_slot_in_epoch(self.current_epoch);
+        Ok(EpochInfo {
+            epoch: self.current_epoch,
+            slot_index: 0,
+            slots_in_epoch,
+            absolute_slot,
+            block_height: absolute_slot,
+            transaction_count: None,
+        })
+    }
+
+    async fn get_epoch_schedule(&self) -> Result<EpochSchedule> {
+        Ok(self.epoch_schedule.clone())
+    }
+
+    async fn get_account(&self, pubkey: &Pubkey) -> Result<Option<Account>> {
+        Ok(self.accounts.get(pubkey).cloned())
+    }
+
+    async fn get_inflation_reward(
+        &self,
+        addresses: &[Pubkey],
+        epoch: u64,
+    ) -> Result<Vec<Option<RpcInflationReward>>> {
+        Ok(addresses
+            .iter()
+            .map(|address| self.inflation_rewards.get(&(*address, epoch)).cloned())
+            .collect())
+    }
+
+    async fn get_vote_accounts(&self) -> Result<RpcVoteAccountStatus> {
+        Ok(RpcVoteAccountStatus {
+            current: self.vote_accounts.clone(),
+            delinquent: vec![],
+        })
+    }
+
+    async fn get_stake_accounts(&self, vote_pubkey: &Pubkey) -> Result<Vec<(Pubkey, Account)>> {
+        Ok(self
+            .accounts
+            .iter()
+            .filter(|(_, account)| account.owner == STAKE_PROGRAM_ID)
+            .filter(|(_, account)| {
+                account
+                    .deserialize_data::<StakeStateV2>()
+                    .ok()
+                    .and_then(|state| state.delegation())
+                    .is_some_and(|delegation| delegation.voter_pubkey == *vote_pubkey)
+            })
+            .map(|(pubkey, account)| (*pubkey, account.clone()))
+            .collect())
+    }
+
+    async fn get_leader_schedule(
+        &self,
+        slot: u64,
+        identity: &str,
+    ) -> Result<Option<RpcLeaderSchedule>> {
+        let epoch = self.epoch_schedule.get_epoch(slot);
+        Ok(self
+            .leader_schedules
+            .get(&(identity.to_string(), epoch))
+            .map(|indices| HashMap::from([(identity.to_string(), indices.clone())])))
+    }
+
+    async fn get_block(
+        &self,
+        slot: u64,
+        _slot_history: &SlotHistory,
+    ) -> std::result::Result<UiConfirmedBlock, PriorityFeeKeeperError> {
+        self.blocks
+            .get(&slot)
+            .cloned()
+            .ok_or(PriorityFeeKeeperError::SkippedBlock)
+    }
+
+    async fn get_block_time(&self, slot: u64) -> Result<i64> {
+        self.block_times
+            .get(&slot)
+            .copied()
+            .ok_or_else(|| anyhow!("No block time for slot {}", slot))
+    }
+
+    async fn get_block_transaction_accounts(&self, slot: u64) -> Result<Vec<Vec<(String, bool)>>> {
+        let votes = self
+            .votes
+            .get(&slot)
+            .ok_or_else(|| anyhow!("No block at slot {}", slot))?;
+        Ok(votes
+            .iter()
+            .map(|(node_pubkey, vote_pubkey)| {
+                vec![
+                    (node_pubkey.to_string(), true),
+                    (vote_pubkey.to_string(), true),
+                    (VOTE_PROGRAM_ID.to_string(), false),
+                ]
+            })
+            .collect())
+    }
+}
+
+/// MEV data held in memory, keyed by vote account and epoch.
+#[derive(Default)]
+pub struct FakeMevData {
+    validators: HashMap<(Pubkey, u64), ValidatorInfo>,
+}
+
+impl FakeMevData {
+    pub fn with_validator(mut self, epoch: u64, info: ValidatorInfo) -> Self {
+        let vote_pubkey = info.vote_account.parse().unwrap();
+        self.validators.insert((vote_pubkey, epoch), info);
+        self
+    }
+}
+
+#[async_trait]
+impl MevDataProvider for FakeMevData {
+    async fn fetch_validator_info(
+        &self,
+        vote_pubkey: &Pubkey,
+        target_epoch: u64,
+    ) -> Result<ValidatorInfo> {
+        self.validators
+            .get(&(*vote_pubkey, target_epoch))
+            .cloned()
+            .ok_or_else(|| {
+                anyhow!(
+                    "No MEV data for vote account {} in epoch {}",
+                    vote_pubkey,
+                    target_epoch
+                )
+            })
+    }
+}
+
+/// Builds an active SoloValidatorBond for `vote_pubkey` with the given commissions.
+pub fn solo_validator_bond(
+    vote_pubkey: Pubkey,
+    stake_account: Pubkey,
+    inflation_bps: u16,
+    mev_tips_bps: u16,
+    block_rewards_bps: u16,
+) -> SoloValidatorBond {
+    SoloValidatorBond {
+        validator_vote_account: vote_pubkey,
+        stake_account,
+        transient_stake_account: Pubkey::default(),
+        issuance_ts: 0,
+        maturity_ts: i64::MAX,
+        principal_token_mint: Pubkey::new_unique(),
+        yield_token_mint: Pubkey::new_unique(),
+        bump_seed: 255,
+        maturity_handled: false,
+        completely_unstaked: false,
+        stake_withdrawn: false,
+        _padding: [0; 4],
+        transient_lamports: 0,
+        redemption_cache: SoloValidatorRedemptionCache {
+            lamports_for_pts: 0,
+            lamports_for_yts: 0,
+            was_slashed: false,
+            padding: [0; 7],
+        },
+        issuance_close_ts: 0,
+        reward_commissions: RewardCommissions {
+            inflation_bps,
+            mev_tips_bps,
+            block_rewards_bps,
+            padding: [0; 2],
+        },
+        issuer: Pubkey::new_unique(),
+        single_depositor: None,
+        reserve: [0; 143],
+    }
+}
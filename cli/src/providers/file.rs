@@ -0,0 +1,156 @@
+use std::fs;
+use std::sync::Mutex;
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use solana_sdk::pubkey::Pubkey;
+
+use super::kobe::{filter_mev_data, ValidatorsResponse};
+use super::MevDataProvider;
+use crate::rewards::mev_rewards::ValidatorInfo;
+
+/// Reads MEV rewards from a local JSON file in the Kobe `ValidatorsResponse` shape.
+///
+/// An `{epoch}` placeholder in the path is replaced with the target epoch, so a directory of
+/// per-epoch snapshots can be replayed. Without it, the file only holds one epoch's data, and
+/// reading it for a second epoch fails.
+pub struct FileMevDataProvider {
+    path: String,
+    /// Epoch a path without `{epoch}` was first read for.
+    epoch: Mutex<Option<u64>>,
+}
+
+impl FileMevDataProvider {
+    pub fn new(path: &str) -> Self {
+        Self {
+            path: path.to_string(),
+            epoch: Mutex::new(None),
+        }
+    }
+
+    pub fn path_for_epoch(&self, target_epoch: u64) -> String {
+        self.path.replace("{epoch}", &target_epoch.to_string())
+    }
+
+    pub fn read_mev_data(&self, target_epoch: u64) -> Result<ValidatorsResponse> {
+        if !self.path.contains("{epoch}") {
+            let mut epoch = self.epoch.lock().unwrap();
+            match *epoch {
+                Some(epoch) if epoch != target_epoch => {
+                    return Err(anyhow!(
+                        "MEV data file {} was read for epoch {} and cannot be used for epoch {} too: put {{epoch}} in its path to read a file per epoch",
+                        self.path,
+                        epoch,
+                        target_epoch
+                    ));
+                }
+                _ => *epoch = Some(target_epoch),
+            }
+        }
+        let path = self.path_for_epoch(target_epoch);
+        let contents = fs::read_to_string(&path)
+            .map_err(|e| anyhow!("Failed to read MEV data from {}: {}", path, e))?;
+        serde_json::from_str(&contents)
+            .map_err(|e| anyhow!("Failed to parse MEV data from {}: {}", path, e))
+    }
+}
+
+#[async_trait]
+impl MevDataProvider for FileMevDataProvider {
+    async fn fetch_validator_info(
+        &self,
+        vote_pubkey: &Pubkey,
+        target_epoch: u64,
+    ) -> Result<ValidatorInfo> {
+        let response = self.read_mev_data(target_epoch)?;
+        filter_mev_data(&response, vote_pubkey)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_reads_validator_from_epoch_file() {
+        let vote_pubkey = Pubkey::new_unique();
+        let dir = std::env::temp_dir();
+        let template = dir
+            .join(format!("pye-mev-{}-{{epoch}}.json", std::process::id()))
+            .to_string_lossy()
+            .to_string();
+        let provider = FileMevDataProvider::new(&template);
+        let path = provider.path_for_epoch(700);
+        assert!(path.ends_with("-700.json"));
+        fs::write(
+            &path,
+            serde_json::json!({
+                "validators": [{
+                    "vote_account": vote_pubkey.to_string(),
+                    "mev_commission_bps": 800,
+                    "mev_rewards": 12_345,
+                    "running_jito": true,
+                    "active_stake": 1_000,
+                }]
+            })
+            .to_string(),
+        )
+        .unwrap();
+
+        let info = provider
+            .fetch_validator_info(&vote_pubkey, 700)
+            .await
+            .unwrap();
+        assert_eq!(info.mev_commission_bps, 800);
+        assert_eq!(info.mev_rewards, 12_345);
+        assert!(provider
+            .fetch_validator_info(&Pubkey::new_unique(), 700)
+            .await
+            .is_err());
+        // No snapshot exists for another epoch.
+        assert!(provider
+            .fetch_validator_info(&vote_pubkey, 701)
+            .await
+            .is_err());
+        let _ = fs::remove_file(&path);
+    }
+
+    #[tokio::test]
+    async fn test_rejects_untemplated_file_for_second_epoch() {
+        let vote_pubkey = Pubkey::new_unique();
+        let path = std::env::temp_dir()
+            .join(format!("pye-mev-single-{}.json", std::process::id()))
+            .to_string_lossy()
+            .to_string();
+        fs::write(
+            &path,
+            serde_json::json!({
+                "validators": [{
+                    "vote_account": vote_pubkey.to_string(),
+                    "mev_commission_bps": 800,
+                    "mev_rewards": 12_345,
+                    "running_jito": true,
+                    "active_stake": 1_000,
+                }]
+            })
+            .to_string(),
+        )
+        .unwrap();
+
+        let provider = FileMevDataProvider::new(&path);
+        assert!(provider
+            .fetch_validator_info(&vote_pubkey, 700)
+            .await
+            .is_ok());
+        assert!(provider
+            .fetch_validator_info(&vote_pubkey, 700)
+            .await
+            .is_ok());
+        // The file holds a single epoch's data.
+        assert!(provider
+            .fetch_validator_info(&vote_pubkey, 701)
+            .await
+            .is_err());
+        let _ = fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,136 @@
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use reqwest::Client;
+use serde::Deserialize;
+use solana_sdk::pubkey::Pubkey;
+use tokio::sync::Mutex;
+
+use super::MevDataProvider;
+use crate::rewards::mev_rewards::ValidatorInfo;
+
+pub const KOBE_VALIDATORS_URL: &str = "https://kobe.mainnet.jito.network/api/v1/validators";
+/// Attempts made, an hour apart, while waiting for Kobe to publish an epoch's MEV data.
+pub const KOBE_MAX_ATTEMPTS: u64 = 12;
+
+#[derive(Deserialize, Debug)]
+pub struct ValidatorsResponse {
+    pub validators: Vec<ValidatorInfo>,
+}
+
+/// Fetches MEV rewards from Jito's Kobe API, or any mirror serving the same response. The last
+/// epoch's response is kept, so validators processed together share a single request.
+pub struct KobeMevDataProvider {
+    http: Client,
+    url: String,
+    max_attempts: u64,
+    retry_delay: Duration,
+    cached: Mutex<Option<(u64, ValidatorsResponse)>>,
+}
+
+impl Default for KobeMevDataProvider {
+    fn default() -> Self {
+        Self::new(KOBE_VALIDATORS_URL, KOBE_MAX_ATTEMPTS)
+    }
+}
+
+impl KobeMevDataProvider {
+    pub fn new(url: &str, max_attempts: u64) -> Self {
+        Self {
+            http: Client::new(),
+            url: url.to_string(),
+            max_attempts,
+            retry_delay: Duration::from_secs(3600),
+            cached: Mutex::new(None),
+        }
+    }
+
+    // REVIEW: When does MEV epoch data get uploaded to the API? If operators are waiting for epoch
+    // transition, there could be a race condition for MEV epoch data
+    pub async fn fetch_mev_data(&self, target_epoch: u64) -> Result<ValidatorsResponse> {
+        self.http
+            .post(&self.url)
+            .header("Content-Type", "application/json")
+            .json(&serde_json::json!({ "epoch": target_epoch }))
+            .send()
+            .await
+            .map_err(|e| anyhow!("Failed to send request: {}", e))?
+            .error_for_status()
+            .map_err(|e| anyhow!("Server returned error status: {}", e))?
+            .json::<ValidatorsResponse>()
+            .await
+            .map_err(|e| anyhow!("Failed to deserialize response: {}", e))
+    }
+
+    pub async fn fetch_mev_with_retry(&self, target_epoch: u64) -> Result<ValidatorsResponse> {
+        let mut attempt: u64 = 0;
+        loop {
+            match self.fetch_mev_data(target_epoch).await {
+                Ok(res) => {
+                    // We check the sum of rewards. If it's 0, then we know the Jito API hasn't been properly updated so we should wait
+                    let total_mev_rewards = res
+                        .validators
+                        .iter()
+                        .fold(0u64, |accum, x| accum + x.mev_rewards);
+                    if total_mev_rewards == 0 {
+                        attempt += 1;
+                        if attempt >= self.max_attempts {
+                            return Err(anyhow!("jito mev: Max attempts reached"));
+                        } else {
+                            tokio::time::sleep(self.retry_delay).await;
+                        }
+                    } else {
+                        return Ok(res);
+                    }
+                }
+                Err(err) => {
+                    attempt += 1;
+                    if attempt >= self.max_attempts {
+                        return Err(err);
+                    } else {
+                        tokio::time::sleep(self.retry_delay).await;
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl MevDataProvider for KobeMevDataProvider {
+    async fn fetch_validator_info(
+        &self,
+        vote_pubkey: &Pubkey,
+        target_epoch: u64,
+    ) -> Result<ValidatorInfo> {
+        let mut cached = self.cached.lock().await;
+        let response = match cached.take() {
+            Some((epoch, response)) if epoch == target_epoch => response,
+            _ => self.fetch_mev_with_retry(target_epoch).await?,
+        };
+        let info = filter_mev_data(&response, vote_pubkey);
+        *cached = Some((target_epoch, response));
+        info
+    }
+}
+
+pub fn filter_mev_data(
+    response: &ValidatorsResponse,
+    vote_pubkey: &Pubkey,
+) -> Result<ValidatorInfo> {
+    let vote_str = vote_pubkey.to_string();
+    let validator = response
+        .validators
+        .iter()
+        .find(|v| v.vote_account == vote_str);
+
+    if let Some(info) = validator {
+        Ok(info.clone())
+    } else {
+        Err(anyhow!(
+            "Validator with vote account {} not found in Jito MEV data. Assuming that validator does not have MEV.\n",
+            vote_str
+        ))
+    }
+}
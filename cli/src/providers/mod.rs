@@ -0,0 +1,151 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use clap::Parser;
+use solana_client::rpc_response::{RpcInflationReward, RpcLeaderSchedule, RpcVoteAccountStatus};
+use solana_sdk::{
+    account::Account, epoch_info::EpochInfo, epoch_schedule::EpochSchedule, pubkey::Pubkey,
+    sysvar::slot_history::SlotHistory,
+};
+use solana_transaction_status_client_types::UiConfirmedBlock;
+
+use crate::rewards::mev_rewards::ValidatorInfo;
+use crate::rpc_utils::{new_rpc_client, PriorityFeeKeeperError};
+use file::FileMevDataProvider;
+use kobe::{KobeMevDataProvider, KOBE_MAX_ATTEMPTS, KOBE_VALIDATORS_URL};
+use tip_distribution::{
+    CrossCheckedMevDataProvider, TipDistributionMevDataProvider, TIP_DISTRIBUTION_PROGRAM_ID,
+};
+
+#[cfg(test)]
+pub mod fake;
+pub mod file;
+pub mod kobe;
+pub mod rpc;
+pub mod shared;
+pub mod tip_distribution;
+
+/// Source of the on-chain data the excess reward calculations depend on.
+#[async_trait]
+pub trait ChainDataProvider: Send + Sync {
+    async fn get_epoch_info(&self) -> Result<EpochInfo>;
+
+    async fn get_epoch_schedule(&self) -> Result<EpochSchedule>;
+
+    /// Returns `None` if the account does not exist.
+    async fn get_account(&self, pubkey: &Pubkey) -> Result<Option<Account>>;
+
+    async fn get_inflation_reward(
+        &self,
+        addresses: &[Pubkey],
+        epoch: u64,
+    ) -> Result<Vec<Option<RpcInflationReward>>>;
+
+    async fn get_vote_accounts(&self) -> Result<RpcVoteAccountStatus>;
+
+    /// Returns every stake account currently delegated to `vote_pubkey`.
+    async fn get_stake_accounts(&self, vote_pubkey: &Pubkey) -> Result<Vec<(Pubkey, Account)>>;
+
+    /// Returns the leader schedule of `identity` for the epoch containing `slot`.
+    async fn get_leader_schedule(
+        &self,
+        slot: u64,
+        identity: &str,
+    ) -> Result<Option<RpcLeaderSchedule>>;
+
+    /// Fetches the rewards of the block at `slot`, using `slot_history` to tell skipped slots
+    /// apart from blocks missing on the RPC.
+    async fn get_block(
+        &self,
+        slot: u64,
+        slot_history: &SlotHistory,
+    ) -> std::result::Result<UiConfirmedBlock, PriorityFeeKeeperError>;
+
+    /// Fetches the blocks at `slots`, in as few requests as the provider allows. Results are in
+    /// the order of `slots`.
+    async fn get_blocks(
+        &self,
+        slots: &[u64],
+        slot_history: &SlotHistory,
+    ) -> Vec<std::result::Result<UiConfirmedBlock, PriorityFeeKeeperError>> {
+        let mut blocks = Vec::with_capacity(slots.len());
+        for slot in slots {
+            blocks.push(self.get_block(*slot, slot_history).await);
+        }
+        blocks
+    }
+
+    async fn get_block_time(&self, slot: u64) -> Result<i64>;
+
+    /// Returns the accounts of every transaction in the block at `slot`, as `(pubkey, writable)`
+    /// with the fee payer first.
+    async fn get_block_transaction_accounts(&self, slot: u64) -> Result<Vec<Vec<(String, bool)>>>;
+}
+
+/// Source of a validator's MEV rewards for an epoch.
+#[async_trait]
+pub trait MevDataProvider: Send + Sync {
+    async fn fetch_validator_info(
+        &self,
+        vote_pubkey: &Pubkey,
+        target_epoch: u64,
+    ) -> Result<ValidatorInfo>;
+}
+
+/// `--mev-source` value selecting the on-chain tip distribution accounts.
+pub const ONCHAIN_MEV_SOURCE: &str = "onchain";
+
+#[derive(Clone, Debug, Parser)]
+pub struct MevSourceArgs {
+    /// Source of Jito MEV data: `onchain` to decode the validator's tip distribution account, an
+    /// HTTP(S) URL serving the Kobe validators API (e.g. a mirror or a testnet instance), or a
+    /// path to a local JSON file with the same response. `{epoch}` in a file path is replaced
+    /// with the target epoch; a file path without it can only be used for a single epoch.
+    #[arg(long, env, default_value = KOBE_VALIDATORS_URL)]
+    pub mev_source: String,
+    /// Second MEV source, in the same format as `--mev-source`, to compare every epoch's MEV
+    /// data against. Differences are reported but payouts always use `--mev-source`.
+    #[arg(long, env)]
+    pub mev_cross_check: Option<String>,
+    /// Jito tip distribution program used by the `onchain` MEV source.
+    #[arg(long, env, default_value_t = TIP_DISTRIBUTION_PROGRAM_ID)]
+    pub tip_distribution_program_id: Pubkey,
+    /// With the `onchain` MEV source, treat a validator without a tip distribution account for
+    /// the epoch as not running Jito, with no MEV rewards. By default this is an error, as a
+    /// missing account can also mean the wrong program or an RPC that lost the account.
+    #[arg(long, env)]
+    pub allow_missing_tip_distribution_account: bool,
+}
+
+impl MevSourceArgs {
+    pub fn mev_data_provider(&self, rpc: &str) -> Box<dyn MevDataProvider> {
+        let primary = self.source_provider(&self.mev_source, rpc, KOBE_MAX_ATTEMPTS);
+        match &self.mev_cross_check {
+            // Kobe is only tried once, so a cross-check never delays a payout.
+            Some(cross_check) => Box::new(CrossCheckedMevDataProvider::new(
+                primary,
+                self.source_provider(cross_check, rpc, 1),
+            )),
+            None => primary,
+        }
+    }
+
+    fn source_provider(
+        &self,
+        source: &str,
+        rpc: &str,
+        kobe_max_attempts: u64,
+    ) -> Box<dyn MevDataProvider> {
+        if source == ONCHAIN_MEV_SOURCE {
+            let client = new_rpc_client(rpc.to_string());
+            Box::new(TipDistributionMevDataProvider::new(
+                client,
+                self.tip_distribution_program_id,
+                self.allow_missing_tip_distribution_account,
+            ))
+        } else if source.starts_with("http://") || source.starts_with("https://") {
+            Box::new(KobeMevDataProvider::new(source, kobe_max_attempts))
+        } else {
+            Box::new(FileMevDataProvider::new(source))
+        }
+    }
+}
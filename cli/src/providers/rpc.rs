@@ -0,0 +1,171 @@
+use std::sync::OnceLock;
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use solana_account_decoder_client_types::UiAccountEncoding;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_client::rpc_config::{
+    RpcAccountInfoConfig, RpcBlockConfig, RpcLeaderScheduleConfig, RpcProgramAccountsConfig,
+};
+use solana_client::rpc_filter::{Memcmp, MemcmpEncodedBytes, RpcFilterType};
+use solana_client::rpc_response::{RpcInflationReward, RpcLeaderSchedule, RpcVoteAccountStatus};
+use solana_sdk::{
+    account::Account, commitment_config::CommitmentConfig, epoch_info::EpochInfo,
+    epoch_schedule::EpochSchedule, pubkey, pubkey::Pubkey, sysvar::slot_history::SlotHistory,
+};
+use solana_transaction_status_client_types::{
+    EncodedTransaction, TransactionDetails, UiConfirmedBlock, UiTransactionEncoding,
+};
+
+use super::ChainDataProvider;
+use crate::rpc_utils::{self, PriorityFeeKeeperError, RPC_TIMEOUT};
+
+const STAKE_PROGRAM_ID: Pubkey = pubkey!("Stake11111111111111111111111111111111111111");
+/// HTTP client for batched JSON-RPC requests, which the RpcClient does not support. Requests
+/// are sent to the RpcClient's URL, with the RpcClient's [`RPC_TIMEOUT`].
+static BATCH_HTTP_CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
+/// Offset of `Delegation::voter_pubkey` in a serialized `StakeStateV2::Stake`.
+const STAKE_VOTER_PUBKEY_OFFSET: usize = 124;
+
+#[async_trait]
+impl ChainDataProvider for RpcClient {
+    async fn get_epoch_info(&self) -> Result<EpochInfo> {
+        RpcClient::get_epoch_info(self)
+            .await
+            .map_err(|e| anyhow!("Failed to fetch epoch info: {}", e))
+    }
+
+    async fn get_epoch_schedule(&self) -> Result<EpochSchedule> {
+        RpcClient::get_epoch_schedule(self)
+            .await
+            .map_err(|e| anyhow!("Failed to fetch epoch schedule: {}", e))
+    }
+
+    async fn get_account(&self, pubkey: &Pubkey) -> Result<Option<Account>> {
+        Ok(self
+            .get_account_with_commitment(pubkey, self.commitment())
+            .await
+            .map_err(|e| anyhow!("Failed to fetch account {}: {}", pubkey, e))?
+            .value)
+    }
+
+    async fn get_inflation_reward(
+        &self,
+        addresses: &[Pubkey],
+        epoch: u64,
+    ) -> Result<Vec<Option<RpcInflationReward>>> {
+        RpcClient::get_inflation_reward(self, addresses, Some(epoch))
+            .await
+            .map_err(|e| anyhow!("Failed to fetch inflation reward: {}", e))
+    }
+
+    async fn get_vote_accounts(&self) -> Result<RpcVoteAccountStatus> {
+        RpcClient::get_vote_accounts(self)
+            .await
+            .map_err(|e| anyhow!("Failed to fetch vote accounts: {}", e))
+    }
+
+    async fn get_stake_accounts(&self, vote_pubkey: &Pubkey) -> Result<Vec<(Pubkey, Account)>> {
+        let config = RpcProgramAccountsConfig {
+            filters: Some(vec![RpcFilterType::Memcmp(Memcmp::new(
+                STAKE_VOTER_PUBKEY_OFFSET,
+                MemcmpEncodedBytes::Base58(vote_pubkey.to_string()),
+            ))]),
+            account_config: RpcAccountInfoConfig {
+                encoding: Some(UiAccountEncoding::Base64Zstd),
+                data_slice: None,
+                commitment: None,
+                min_context_slot: None,
+            },
+            with_context: None,
+            sort_results: None,
+        };
+        self.get_program_accounts_with_config(&STAKE_PROGRAM_ID, config)
+            .await
+            .map_err(|e| {
+                anyhow!(
+                    "Failed to fetch stake accounts of vote account {}: {}",
+                    vote_pubkey,
+                    e
+                )
+            })
+    }
+
+    async fn get_leader_schedule(
+        &self,
+        slot: u64,
+        identity: &str,
+    ) -> Result<Option<RpcLeaderSchedule>> {
+        self.get_leader_schedule_with_config(
+            Some(slot),
+            RpcLeaderScheduleConfig {
+                identity: Some(identity.to_string()),
+                commitment: Some(CommitmentConfig::finalized()),
+            },
+        )
+        .await
+        .map_err(|e| anyhow!("Failed to fetch leader schedule: {}", e))
+    }
+
+    async fn get_block(
+        &self,
+        slot: u64,
+        slot_history: &SlotHistory,
+    ) -> std::result::Result<UiConfirmedBlock, PriorityFeeKeeperError> {
+        rpc_utils::get_block(self, slot, slot_history).await
+    }
+
+    async fn get_blocks(
+        &self,
+        slots: &[u64],
+        slot_history: &SlotHistory,
+    ) -> Vec<std::result::Result<UiConfirmedBlock, PriorityFeeKeeperError>> {
+        if let [slot] = slots {
+            return vec![rpc_utils::get_block(self, *slot, slot_history).await];
+        }
+        let http = BATCH_HTTP_CLIENT.get_or_init(|| {
+            reqwest::Client::builder()
+                .timeout(RPC_TIMEOUT)
+                .build()
+                .expect("building the batch HTTP client")
+        });
+        rpc_utils::get_blocks_batched(http, &self.url(), slots, slot_history).await
+    }
+
+    async fn get_block_time(&self, slot: u64) -> Result<i64> {
+        RpcClient::get_block_time(self, slot)
+            .await
+            .map_err(|e| anyhow!("Failed to fetch block time for slot {}: {}", slot, e))
+    }
+
+    async fn get_block_transaction_accounts(&self, slot: u64) -> Result<Vec<Vec<(String, bool)>>> {
+        let block = self
+            .get_block_with_config(
+                slot,
+                RpcBlockConfig {
+                    encoding: Some(UiTransactionEncoding::Json),
+                    transaction_details: Some(TransactionDetails::Accounts),
+                    rewards: Some(false),
+                    commitment: Some(CommitmentConfig::finalized()),
+                    max_supported_transaction_version: Some(0),
+                },
+            )
+            .await
+            .map_err(|e| anyhow!("Failed to fetch block {}: {}", slot, e))?;
+        Ok(block
+            .transactions
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|transaction| match transaction.transaction {
+                EncodedTransaction::Accounts(accounts) => Some(
+                    accounts
+                        .account_keys
+                        .into_iter()
+                        .map(|account| (account.pubkey, account.writable))
+                        .collect(),
+                ),
+                _ => None,
+            })
+            .collect())
+    }
+}
@@ -0,0 +1,235 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use solana_client::rpc_response::{RpcInflationReward, RpcLeaderSchedule, RpcVoteAccountStatus};
+use solana_sdk::{
+    account::Account,
+    epoch_info::EpochInfo,
+    epoch_schedule::EpochSchedule,
+    pubkey::Pubkey,
+    sysvar::{self, slot_history::SlotHistory},
+};
+use solana_transaction_status_client_types::UiConfirmedBlock;
+use tokio::sync::{Mutex, OnceCell};
+
+use super::ChainDataProvider;
+use crate::rpc_utils::PriorityFeeKeeperError;
+
+/// Sysvars every validator's calculations read.
+const SHARED_SYSVARS: [Pubkey; 2] = [sysvar::stake_history::ID, sysvar::slot_history::ID];
+
+/// Chain data shared by the validators processed together for an epoch. The epoch schedule,
+/// vote accounts, block times and the StakeHistory and SlotHistory sysvars are fetched once
+/// and reused; everything else is passed through. Meant to live for a single epoch's
+/// processing, since the cached data changes as the chain advances.
+pub struct SharedChainData<'a> {
+    inner: &'a dyn ChainDataProvider,
+    epoch_schedule: OnceCell<EpochSchedule>,
+    vote_accounts: OnceCell<RpcVoteAccountStatus>,
+    sysvars: Mutex<HashMap<Pubkey, Option<Account>>>,
+    block_times: Mutex<HashMap<u64, i64>>,
+}
+
+impl<'a> SharedChainData<'a> {
+    pub fn new(inner: &'a dyn ChainDataProvider) -> Self {
+        Self {
+            inner,
+            epoch_schedule: OnceCell::new(),
+            vote_accounts: OnceCell::new(),
+            sysvars: Mutex::new(HashMap::new()),
+            block_times: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+#[async_trait]
+impl ChainDataProvider for SharedChainData<'_> {
+    async fn get_epoch_info(&self) -> Result<EpochInfo> {
+        self.inner.get_epoch_info().await
+    }
+
+    async fn get_epoch_schedule(&self) -> Result<EpochSchedule> {
+        self.epoch_schedule
+            .get_or_try_init(|| self.inner.get_epoch_schedule())
+            .await
+            .cloned()
+    }
+
+    async fn get_account(&self, pubkey: &Pubkey) -> Result<Option<Account>> {
+        if !SHARED_SYSVARS.contains(pubkey) {
+            return self.inner.get_account(pubkey).await;
+        }
+        let mut sysvars = self.sysvars.lock().await;
+        if let Some(account) = sysvars.get(pubkey) {
+            return Ok(account.clone());
+        }
+        let account = self.inner.get_account(pubkey).await?;
+        sysvars.insert(*pubkey, account.clone());
+        Ok(account)
+    }
+
+    async fn get_inflation_reward(
+        &self,
+        addresses: &[Pubkey],
+        epoch: u64,
+    ) -> Result<Vec<Option<RpcInflationReward>>> {
+        self.inner.get_inflation_reward(addresses, epoch).await
+    }
+
+    async fn get_vote_accounts(&self) -> Result<RpcVoteAccountStatus> {
+        self.vote_accounts
+            .get_or_try_init(|| self.inner.get_vote_accounts())
+            .await
+            .cloned()
+    }
+
+    async fn get_stake_accounts(&self, vote_pubkey: &Pubkey) -> Result<Vec<(Pubkey, Account)>> {
+        self.inner.get_stake_accounts(vote_pubkey).await
+    }
+
+    async fn get_leader_schedule(
+        &self,
+        slot: u64,
+        identity: &str,
+    ) -> Result<Option<RpcLeaderSchedule>> {
+        self.inner.get_leader_schedule(slot, identity).await
+    }
+
+    async fn get_block(
+        &self,
+        slot: u64,
+        slot_history: &SlotHistory,
+    ) -> std::result::Result<UiConfirmedBlock, PriorityFeeKeeperError> {
+        self.inner.get_block(slot, slot_history).await
+    }
+
+    async fn get_blocks(
+        &self,
+        slots: &[u64],
+        slot_history: &SlotHistory,
+    ) -> Vec<std::result::Result<UiConfirmedBlock, PriorityFeeKeeperError>> {
+        self.inner.get_blocks(slots, slot_history).await
+    }
+
+    async fn get_block_time(&self, slot: u64) -> Result<i64> {
+        let mut block_times = self.block_times.lock().await;
+        if let Some(block_time) = block_times.get(&slot) {
+            return Ok(*block_time);
+        }
+        let block_time = self.inner.get_block_time(slot).await?;
+        block_times.insert(slot, block_time);
+        Ok(block_time)
+    }
+
+    async fn get_block_transaction_accounts(&self, slot: u64) -> Result<Vec<Vec<(String, bool)>>> {
+        self.inner.get_block_transaction_accounts(slot).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+    use crate::accounts::{fetch_slot_history, fetch_stake_history};
+    use crate::providers::fake::FakeChainData;
+
+    /// Counts the requests reaching the chain.
+    struct CountingChainData {
+        inner: FakeChainData,
+        requests: AtomicUsize,
+    }
+
+    impl CountingChainData {
+        fn count(&self) -> &FakeChainData {
+            self.requests.fetch_add(1, Ordering::SeqCst);
+            &self.inner
+        }
+    }
+
+    #[async_trait]
+    impl ChainDataProvider for CountingChainData {
+        async fn get_epoch_info(&self) -> Result<EpochInfo> {
+            self.count().get_epoch_info().await
+        }
+
+        async fn get_epoch_schedule(&self) -> Result<EpochSchedule> {
+            self.count().get_epoch_schedule().await
+        }
+
+        async fn get_account(&self, pubkey: &Pubkey) -> Result<Option<Account>> {
+            self.count().get_account(pubkey).await
+        }
+
+        async fn get_inflation_reward(
+            &self,
+            addresses: &[Pubkey],
+            epoch: u64,
+        ) -> Result<Vec<Option<RpcInflationReward>>> {
+            self.count().get_inflation_reward(addresses, epoch).await
+        }
+
+        async fn get_vote_accounts(&self) -> Result<RpcVoteAccountStatus> {
+            self.count().get_vote_accounts().await
+        }
+
+        async fn get_stake_accounts(&self, vote_pubkey: &Pubkey) -> Result<Vec<(Pubkey, Account)>> {
+            self.count().get_stake_accounts(vote_pubkey).await
+        }
+
+        async fn get_leader_schedule(
+            &self,
+            slot: u64,
+            identity: &str,
+        ) -> Result<Option<RpcLeaderSchedule>> {
+            self.count().get_leader_schedule(slot, identity).await
+        }
+
+        async fn get_block(
+            &self,
+            slot: u64,
+            slot_history: &SlotHistory,
+        ) -> std::result::Result<UiConfirmedBlock, PriorityFeeKeeperError> {
+            self.count().get_block(slot, slot_history).await
+        }
+
+        async fn get_block_time(&self, slot: u64) -> Result<i64> {
+            self.count().get_block_time(slot).await
+        }
+
+        async fn get_block_transaction_accounts(
+            &self,
+            slot: u64,
+        ) -> Result<Vec<Vec<(String, bool)>>> {
+            self.count().get_block_transaction_accounts(slot).await
+        }
+    }
+
+    #[tokio::test]
+    async fn test_shares_epoch_data_between_validators() {
+        let chain = CountingChainData {
+            inner: FakeChainData::new(800),
+            requests: AtomicUsize::new(0),
+        };
+        let shared = SharedChainData::new(&chain);
+        for _ in 0..3 {
+            shared.get_epoch_schedule().await.unwrap();
+            shared.get_vote_accounts().await.unwrap();
+            fetch_stake_history(&shared).await.unwrap();
+            fetch_slot_history(&shared).await.unwrap();
+        }
+        assert_eq!(chain.requests.load(Ordering::SeqCst), 4);
+
+        // Other accounts and per-validator data are not cached.
+        let bond = Pubkey::new_unique();
+        shared.get_account(&bond).await.unwrap();
+        shared.get_account(&bond).await.unwrap();
+        shared.get_stake_accounts(&bond).await.unwrap();
+        assert_eq!(chain.requests.load(Ordering::SeqCst), 7);
+        // Failures are not cached either.
+        assert!(shared.get_block_time(1).await.is_err());
+        assert!(shared.get_block_time(1).await.is_err());
+        assert_eq!(chain.requests.load(Ordering::SeqCst), 9);
+    }
+}
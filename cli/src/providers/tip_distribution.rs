@@ -0,0 +1,357 @@
+use anchor_lang::prelude::{borsh, AnchorDeserialize, AnchorSerialize};
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use log::{info, warn};
+use solana_metrics::datapoint_info;
+use solana_sdk::{account::Account, hash::hash, pubkey, pubkey::Pubkey, rent::Rent};
+
+use super::{ChainDataProvider, MevDataProvider};
+use crate::rewards::mev_rewards::ValidatorInfo;
+
+/// Jito's tip distribution program on mainnet.
+pub const TIP_DISTRIBUTION_PROGRAM_ID: Pubkey =
+    pubkey!("4R3gSG8BpU4t19KYj8CfnbtRpnT8gtk4dvTHxVRwc2r7");
+
+const TIP_DISTRIBUTION_ACCOUNT_SEED: &[u8] = b"TIP_DISTRIBUTION_ACCOUNT";
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq, Eq)]
+pub struct MerkleRoot {
+    pub root: [u8; 32],
+    /// Total lamports claimable from the account, validator commission included.
+    pub max_total_claim: u64,
+    pub max_num_nodes: u64,
+    pub total_funds_claimed: u64,
+    pub num_nodes_claimed: u64,
+}
+
+/// Layout of the tip distribution program's `TipDistributionAccount`, without the Anchor
+/// discriminator. One is created per vote account and epoch to collect that epoch's tips.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq, Eq)]
+pub struct TipDistributionAccount {
+    pub validator_vote_account: Pubkey,
+    pub merkle_root_upload_authority: Pubkey,
+    pub merkle_root: Option<MerkleRoot>,
+    pub epoch_created_at: u64,
+    pub validator_commission_bps: u16,
+    pub expires_at: u64,
+    pub bump: u8,
+}
+
+impl TipDistributionAccount {
+    pub fn discriminator() -> [u8; 8] {
+        let mut discriminator = [0u8; 8];
+        discriminator.copy_from_slice(&hash(b"account:TipDistributionAccount").to_bytes()[..8]);
+        discriminator
+    }
+
+    pub fn try_from_account(account: &Account, program_id: &Pubkey) -> Result<Self> {
+        if account.owner != *program_id {
+            return Err(anyhow!(
+                "Tip distribution account is owned by {}, expected {}",
+                account.owner,
+                program_id
+            ));
+        }
+        if account.data.len() < 8 || account.data[..8] != Self::discriminator() {
+            return Err(anyhow!("Account is not a TipDistributionAccount"));
+        }
+        Self::deserialize(&mut &account.data[8..])
+            .map_err(|e| anyhow!("Failed to decode TipDistributionAccount: {}", e))
+    }
+
+    /// Total tips collected for the epoch. Once the merkle root is uploaded this is the amount
+    /// distributed through claims; before that it is the account's balance above rent.
+    pub fn total_tips(&self, account: &Account) -> u64 {
+        match &self.merkle_root {
+            Some(merkle_root) => merkle_root.max_total_claim,
+            None => account
+                .lamports
+                .saturating_sub(Rent::default().minimum_balance(account.data.len())),
+        }
+    }
+}
+
+pub fn derive_tip_distribution_account_address(
+    program_id: &Pubkey,
+    vote_pubkey: &Pubkey,
+    epoch: u64,
+) -> Pubkey {
+    Pubkey::find_program_address(
+        &[
+            TIP_DISTRIBUTION_ACCOUNT_SEED,
+            vote_pubkey.as_ref(),
+            &epoch.to_le_bytes(),
+        ],
+        program_id,
+    )
+    .0
+}
+
+/// Derives a validator's MEV rewards and commission for an epoch from its on-chain tip
+/// distribution account, so they are available as soon as the epoch ends.
+///
+/// The validator's active stake is not stored on-chain per epoch, so it is reported as 0 and
+/// only taken from the network, with `--stake-denominator network`, which this source requires.
+pub struct TipDistributionMevDataProvider<C: ChainDataProvider> {
+    chain: C,
+    program_id: Pubkey,
+    allow_missing_account: bool,
+}
+
+impl<C: ChainDataProvider> TipDistributionMevDataProvider<C> {
+    /// With `allow_missing_account`, a validator without a tip distribution account for the
+    /// epoch is reported as not running Jito, with no MEV rewards, instead of as an error.
+    pub fn new(chain: C, program_id: Pubkey, allow_missing_account: bool) -> Self {
+        Self {
+            chain,
+            program_id,
+            allow_missing_account,
+        }
+    }
+}
+
+#[async_trait]
+impl<C: ChainDataProvider> MevDataProvider for TipDistributionMevDataProvider<C> {
+    async fn fetch_validator_info(
+        &self,
+        vote_pubkey: &Pubkey,
+        target_epoch: u64,
+    ) -> Result<ValidatorInfo> {
+        let address =
+            derive_tip_distribution_account_address(&self.program_id, vote_pubkey, target_epoch);
+        let account = self.chain.get_account(&address).await?;
+        if account.is_none() && !self.allow_missing_account {
+            return Err(anyhow!(
+                "No tip distribution account {} for vote account {} in epoch {}",
+                address,
+                vote_pubkey,
+                target_epoch
+            ));
+        }
+        let Some(account) = account else {
+            info!(
+                "No tip distribution account {} for vote account {} in epoch {}. Assuming that validator does not have MEV.",
+                address, vote_pubkey, target_epoch
+            );
+            return Ok(ValidatorInfo {
+                vote_account: vote_pubkey.to_string(),
+                mev_commission_bps: 0,
+                mev_rewards: 0,
+                running_jito: false,
+                active_stake: 0,
+            });
+        };
+        let tip_distribution_account =
+            TipDistributionAccount::try_from_account(&account, &self.program_id)?;
+        if tip_distribution_account.validator_vote_account != *vote_pubkey {
+            return Err(anyhow!(
+                "Tip distribution account {} belongs to vote account {}, expected {}",
+                address,
+                tip_distribution_account.validator_vote_account,
+                vote_pubkey
+            ));
+        }
+        Ok(ValidatorInfo {
+            vote_account: vote_pubkey.to_string(),
+            mev_commission_bps: u64::from(tip_distribution_account.validator_commission_bps),
+            mev_rewards: tip_distribution_account.total_tips(&account),
+            running_jito: true,
+            active_stake: 0,
+        })
+    }
+}
+
+/// Uses `primary` for payouts and compares every result with `secondary`, reporting
+/// differences. Failures of the secondary source are logged and never block a payout.
+pub struct CrossCheckedMevDataProvider {
+    primary: Box<dyn MevDataProvider>,
+    secondary: Box<dyn MevDataProvider>,
+}
+
+impl CrossCheckedMevDataProvider {
+    pub fn new(primary: Box<dyn MevDataProvider>, secondary: Box<dyn MevDataProvider>) -> Self {
+        Self { primary, secondary }
+    }
+}
+
+#[async_trait]
+impl MevDataProvider for CrossCheckedMevDataProvider {
+    async fn fetch_validator_info(
+        &self,
+        vote_pubkey: &Pubkey,
+        target_epoch: u64,
+    ) -> Result<ValidatorInfo> {
+        let info = self
+            .primary
+            .fetch_validator_info(vote_pubkey, target_epoch)
+            .await?;
+        match self
+            .secondary
+            .fetch_validator_info(vote_pubkey, target_epoch)
+            .await
+        {
+            Ok(other) => {
+                let matches = info.mev_rewards == other.mev_rewards
+                    && info.mev_commission_bps == other.mev_commission_bps;
+                if !matches {
+                    warn!(
+                        "MEV data mismatch for vote account {} in epoch {}: {:?} vs cross-check {:?}",
+                        vote_pubkey, target_epoch, info, other
+                    );
+                }
+                datapoint_info!(
+                    "mev_cross_check",
+                    ("vote_account", vote_pubkey.to_string(), String),
+                    ("epoch", target_epoch.to_string(), String),
+                    ("mev_rewards", info.mev_rewards as i64, i64),
+                    ("cross_check_mev_rewards", other.mev_rewards as i64, i64),
+                    ("mev_commission_bps", info.mev_commission_bps as i64, i64),
+                    (
+                        "cross_check_mev_commission_bps",
+                        other.mev_commission_bps as i64,
+                        i64
+                    ),
+                    ("active_stake", info.active_stake as i64, i64),
+                    ("cross_check_active_stake", other.active_stake as i64, i64),
+                    ("matches", matches, bool),
+                );
+            }
+            Err(err) => warn!(
+                "Failed to cross-check MEV data for vote account {} in epoch {}: {}",
+                vote_pubkey, target_epoch, err
+            ),
+        }
+        Ok(info)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::fake::{FakeChainData, FakeMevData};
+
+    fn tip_distribution_account(
+        vote_pubkey: Pubkey,
+        merkle_root: Option<MerkleRoot>,
+        lamports: u64,
+    ) -> Account {
+        let mut data = TipDistributionAccount::discriminator().to_vec();
+        TipDistributionAccount {
+            validator_vote_account: vote_pubkey,
+            merkle_root_upload_authority: Pubkey::new_unique(),
+            merkle_root,
+            epoch_created_at: 700,
+            validator_commission_bps: 800,
+            expires_at: 703,
+            bump: 255,
+        }
+        .serialize(&mut data)
+        .unwrap();
+        Account {
+            lamports,
+            data,
+            owner: TIP_DISTRIBUTION_PROGRAM_ID,
+            executable: false,
+            rent_epoch: 0,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_decodes_tips_before_and_after_merkle_root_upload() {
+        let vote_pubkey = Pubkey::new_unique();
+        let address = derive_tip_distribution_account_address(
+            &TIP_DISTRIBUTION_PROGRAM_ID,
+            &vote_pubkey,
+            700,
+        );
+        let account = tip_distribution_account(vote_pubkey, None, 0);
+        let rent = Rent::default().minimum_balance(account.data.len());
+        let chain = FakeChainData::new(701).with_account(
+            address,
+            tip_distribution_account(vote_pubkey, None, rent + 5_000_000_000),
+        );
+        let provider =
+            TipDistributionMevDataProvider::new(chain, TIP_DISTRIBUTION_PROGRAM_ID, false);
+        let info = provider
+            .fetch_validator_info(&vote_pubkey, 700)
+            .await
+            .unwrap();
+        assert!(info.running_jito);
+        assert_eq!(info.mev_commission_bps, 800);
+        assert_eq!(info.mev_rewards, 5_000_000_000);
+        // The active stake is left to the network stake denominator.
+        assert_eq!(info.active_stake, 0);
+
+        // After the merkle root upload, claims drain the account, so the root's total is used.
+        let merkle_root = MerkleRoot {
+            root: [1; 32],
+            max_total_claim: 4_900_000_000,
+            max_num_nodes: 10,
+            total_funds_claimed: 1_000_000_000,
+            num_nodes_claimed: 2,
+        };
+        let chain = |merkle_root| {
+            FakeChainData::new(701).with_account(
+                address,
+                tip_distribution_account(vote_pubkey, merkle_root, rent + 3_900_000_000),
+            )
+        };
+        let provider = TipDistributionMevDataProvider::new(
+            chain(Some(merkle_root.clone())),
+            TIP_DISTRIBUTION_PROGRAM_ID,
+            false,
+        );
+        let info = provider
+            .fetch_validator_info(&vote_pubkey, 700)
+            .await
+            .unwrap();
+        assert_eq!(info.mev_rewards, 4_900_000_000);
+
+        // No tip distribution account was created for the epoch.
+        assert!(provider
+            .fetch_validator_info(&vote_pubkey, 699)
+            .await
+            .is_err());
+        let provider = TipDistributionMevDataProvider::new(
+            chain(Some(merkle_root)),
+            TIP_DISTRIBUTION_PROGRAM_ID,
+            true,
+        );
+        let info = provider
+            .fetch_validator_info(&vote_pubkey, 699)
+            .await
+            .unwrap();
+        assert!(!info.running_jito);
+        assert_eq!(info.mev_rewards, 0);
+    }
+
+    #[tokio::test]
+    async fn test_cross_check_returns_primary_data() {
+        let vote_pubkey = Pubkey::new_unique();
+        let info = |mev_rewards| ValidatorInfo {
+            vote_account: vote_pubkey.to_string(),
+            mev_commission_bps: 800,
+            mev_rewards,
+            running_jito: true,
+            active_stake: 1_000,
+        };
+        let provider = CrossCheckedMevDataProvider::new(
+            Box::new(FakeMevData::default().with_validator(700, info(5_000))),
+            Box::new(FakeMevData::default().with_validator(700, info(4_000))),
+        );
+        let result = provider
+            .fetch_validator_info(&vote_pubkey, 700)
+            .await
+            .unwrap();
+        assert_eq!(result.mev_rewards, 5_000);
+        // A missing cross-check is not an error.
+        let provider = CrossCheckedMevDataProvider::new(
+            Box::new(FakeMevData::default().with_validator(700, info(5_000))),
+            Box::new(FakeMevData::default()),
+        );
+        assert!(provider
+            .fetch_validator_info(&vote_pubkey, 700)
+            .await
+            .is_ok());
+    }
+}
@@ -1,17 +1,20 @@
-use crate::rpc_utils::{self, PriorityFeeKeeperError};
+use crate::providers::ChainDataProvider;
+use crate::rpc_utils::PriorityFeeKeeperError;
 use anyhow::{anyhow, Result};
 use futures::stream::{self, StreamExt};
 use log::{info, warn};
-use pye_core_cpi::pye_core::types::RewardCommissions;
-use solana_client::nonblocking::rpc_client::RpcClient;
-use solana_client::rpc_config::RpcLeaderScheduleConfig;
-use solana_sdk::commitment_config::CommitmentConfig;
-use solana_sdk::epoch_info::EpochInfo;
+use solana_sdk::epoch_schedule::EpochSchedule;
+use solana_sdk::pubkey;
 use solana_sdk::pubkey::Pubkey;
 use solana_sdk::reward_type::RewardType;
+use solana_transaction_status_client_types::UiConfirmedBlock;
 use std::sync::Arc;
 use std::time::Duration;
 
+const VOTE_PROGRAM_ID: Pubkey = pubkey!("Vote111111111111111111111111111111111111111");
+/// Number of slots at the start of an epoch searched for a vote of the validator.
+const IDENTITY_SEARCH_SLOTS: u64 = 32;
+
 /// Computes the excess block commission owed to bond holders.
 ///
 /// # Arguments
@@ -38,44 +41,26 @@ pub fn compute_excess_block_commission(
     excess_block_commission
 }
 
-/// Uses and RPC client to fetch the block rewards for a given validator
+/// Uses and RPC client to fetch the block rewards for a given validator in `target_epoch`
 pub async fn calculate_block_rewards(
-    rpc: &RpcClient,
+    rpc: &dyn ChainDataProvider,
     vote_pubkey: &Pubkey,
-    epoch_info: &EpochInfo,
+    epoch_schedule: &EpochSchedule,
+    target_epoch: u64,
     concurrency: usize,
+    block_batch_size: usize,
     block_retry_delay: u64,
 ) -> Result<u64> {
-    let vote_str = vote_pubkey.to_string();
-    let vote_accounts = rpc
-        .get_vote_accounts()
-        .await
-        .map_err(|e| anyhow!("Failed to fetch vote accounts: {}", e))?;
-
-    let node_identity = vote_accounts
-        .current
-        .into_iter()
-        .find(|va| va.vote_pubkey == vote_str)
-        .ok_or_else(|| anyhow!("Validator with vote pubkey {} not found", vote_str))?
-        .node_pubkey;
+    let node_identity =
+        resolve_node_identity(rpc, vote_pubkey, epoch_schedule, target_epoch).await?;
 
-    // 1) Get slot of first block in previous epoch
-    let first = epoch_info
-        .absolute_slot
-        .saturating_sub(epoch_info.slot_index)
-        .saturating_sub(epoch_info.slots_in_epoch);
+    // 1) Get slot of first block in target epoch
+    let first = epoch_schedule.get_first_slot_in_epoch(target_epoch);
 
     // 2) Fetch the leader schedule for specified node.
     let schedule = rpc
-        .get_leader_schedule_with_config(
-            Some(first),
-            RpcLeaderScheduleConfig {
-                identity: Some(node_identity.clone()),
-                commitment: Some(CommitmentConfig::finalized()),
-            },
-        )
-        .await
-        .map_err(|e| anyhow!("Failed to fetch leader schedule: {}", e))?
+        .get_leader_schedule(first, &node_identity)
+        .await?
         .ok_or_else(|| anyhow!("Leader schedule not found for node {}", node_identity))?;
 
     let indices = schedule
@@ -87,125 +72,160 @@ pub async fn calculate_block_rewards(
     // 3) Fetch each block that the leader produced to calculate total block rewards earned.
     let slot_history = Arc::new(crate::accounts::fetch_slot_history(rpc).await?);
 
-    // TODO: Replace with a batched JSON-RPC call to reduce HTTP overhead.
     info!(
         "Fetching {} Blocks Produced in Epoch {}",
         slots.len(),
-        epoch_info.epoch - 1,
+        target_epoch,
     );
-    let total_fees: u64 = stream::iter(slots)
-        .map(|slot| {
-            let node_identity = node_identity.clone();
-            let slot_history = Arc::clone(&slot_history);
-            async move {
-                let mut attempts: u8 = 0;
-                loop {
-                    attempts += 1;
-                    match rpc_utils::get_block(rpc, slot, &slot_history).await {
-                        Ok(block) => {
-                            let mut total = 0;
-                            if let Some(rewards) = block.rewards {
-                                for r in rewards {
-                                    if r.pubkey == node_identity {
-                                        if let Some(RewardType::Fee) = r.reward_type {
-                                            total += r.lamports as u64;
-                                        }
-                                    }
-                                }
-                            }
-                            return Ok(Some(total));
-                        }
-                        Err(e) => {
-                            match e {
-                                PriorityFeeKeeperError::SkippedBlock => {
-                                    warn!(
-                                        "PriorityFeeKeeperError::SkippedBlock at slot {}: {}",
-                                        slot, e
-                                    );
-                                    return Ok(None);
-                                }
-                                _ => {
-                                    if attempts >= 5 {
-                                        return Err(anyhow!(
-                                            "Failed to fetch block at slot {}: {}",
-                                            slot,
-                                            e
-                                        ));
-                                    } else {
-                                        // sleep for 30min before trying this block again. Max wait time is currently 2.5 hours
-                                        tokio::time::sleep(Duration::from_secs(block_retry_delay))
-                                            .await;
-                                    }
-                                }
-                            }
-                        }
-                    }
+    let mut total_fees = 0u64;
+    let mut pending = slots;
+    let mut attempts: u8 = 0;
+    while !pending.is_empty() {
+        attempts += 1;
+        let batches: Vec<Vec<u64>> = pending
+            .chunks(block_batch_size.max(1))
+            .map(<[u64]>::to_vec)
+            .collect();
+        let results: Vec<_> = stream::iter(batches)
+            .map(|batch| {
+                let slot_history = Arc::clone(&slot_history);
+                async move {
+                    let blocks = rpc.get_blocks(&batch, &slot_history).await;
+                    batch.into_iter().zip(blocks).collect::<Vec<_>>()
+                }
+            })
+            .buffer_unordered(concurrency)
+            .collect()
+            .await;
+
+        let mut failed = vec![];
+        for (slot, result) in results.into_iter().flatten() {
+            match result {
+                Ok(block) => total_fees += leader_fees(&block, &node_identity),
+                Err(PriorityFeeKeeperError::SkippedBlock) => {
+                    warn!("PriorityFeeKeeperError::SkippedBlock at slot {}", slot);
                 }
+                Err(e) => failed.push((slot, e)),
             }
-        })
-        .buffer_unordered(concurrency)
-        .fold(Ok(0u64), |acc, fee_result| async move {
-            match (acc, fee_result) {
-                (Ok(acc), Ok(fee)) => Ok(acc + fee.unwrap_or(0)),
-                (Err(e), _) | (_, Err(e)) => Err(e),
+        }
+        if let Some((slot, e)) = failed.first() {
+            if attempts >= 5 {
+                return Err(anyhow!(
+                    "Failed to fetch {} blocks, e.g. at slot {}: {}",
+                    failed.len(),
+                    slot,
+                    e
+                ));
             }
-        })
-        .await?;
+            warn!(
+                "Failed to fetch {} blocks, e.g. at slot {}: {}. Retrying in {}s",
+                failed.len(),
+                slot,
+                e,
+                block_retry_delay
+            );
+            // sleep for 30min before trying these blocks again. Max wait time is currently 2 hours
+            tokio::time::sleep(Duration::from_secs(block_retry_delay)).await;
+        }
+        pending = failed.into_iter().map(|(slot, _)| slot).collect();
+    }
 
     Ok(total_fees)
 }
 
-pub async fn calculate_excess_block_reward(
-    client: &RpcClient,
+/// Returns the node identity `vote_pubkey` ran with in `target_epoch`, which its leader slots and
+/// block rewards for the epoch are keyed by: the fee payer of its votes in the first blocks of
+/// the epoch. The identity can have changed since, so its current one is only used when none of
+/// those blocks hold a vote from it.
+async fn resolve_node_identity(
+    rpc: &dyn ChainDataProvider,
     vote_pubkey: &Pubkey,
-    epoch_info: &EpochInfo,
-    bond_active_stake: u64,
-    validator_active_stake: u64,
-    reward_commissions: &RewardCommissions,
-    concurrency: usize,
-    block_retry_delay: u64,
-) -> Result<i64> {
-    let total_block_reward: std::result::Result<u64, anyhow::Error> = calculate_block_rewards(
-        client,
-        vote_pubkey,
-        epoch_info,
-        concurrency,
-        block_retry_delay,
-    )
-    .await;
-
-    if validator_active_stake == 0 {
-        info!("No excess block reward when validator active stake is 0");
-        return Ok(0);
-    }
-
-    match total_block_reward {
-        Ok(amount) => {
-            let excess_block_commission = compute_excess_block_commission(
-                amount,
-                bond_active_stake,
-                validator_active_stake,
-                reward_commissions.block_rewards_bps,
-            );
-            info!(
-                "Total Block Reward: {}, Excess Block Commission: {}\n",
-                amount, excess_block_commission
-            );
-            Ok(excess_block_commission)
-        }
-        Err(e) => {
-            info!(
-                "Error fetching block reward: {}. Assuming no block reward earned.\n",
-                e
-            );
-            Ok(0)
+    epoch_schedule: &EpochSchedule,
+    target_epoch: u64,
+) -> Result<String> {
+    let vote_str = vote_pubkey.to_string();
+    let vote_program = VOTE_PROGRAM_ID.to_string();
+    let first = epoch_schedule.get_first_slot_in_epoch(target_epoch);
+    for slot in first..first + IDENTITY_SEARCH_SLOTS {
+        // Skipped slots have no block.
+        let Ok(transactions) = rpc.get_block_transaction_accounts(slot).await else {
+            continue;
+        };
+        let vote = transactions.into_iter().find(|accounts| {
+            accounts.iter().any(|(pubkey, _)| *pubkey == vote_program)
+                && accounts
+                    .iter()
+                    .any(|(pubkey, writable)| *pubkey == vote_str && *writable)
+        });
+        if let Some((fee_payer, _)) = vote.and_then(|accounts| accounts.into_iter().next()) {
+            return Ok(fee_payer);
         }
     }
+
+    let node_identity = rpc
+        .get_vote_accounts()
+        .await?
+        .current
+        .into_iter()
+        .find(|va| va.vote_pubkey == vote_str)
+        .ok_or_else(|| anyhow!("Validator with vote pubkey {} not found", vote_str))?
+        .node_pubkey;
+    warn!(
+        "No vote of {} found at the start of epoch {}, using its current identity {}",
+        vote_str, target_epoch, node_identity
+    );
+    Ok(node_identity)
+}
+
+/// Sums the fee rewards `node_identity` earned as the leader of `block`.
+fn leader_fees(block: &UiConfirmedBlock, node_identity: &str) -> u64 {
+    block
+        .rewards
+        .iter()
+        .flatten()
+        .filter(|r| r.pubkey == node_identity && r.reward_type == Some(RewardType::Fee))
+        .map(|r| r.lamports as u64)
+        .sum()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::providers::fake::FakeChainData;
+
+    #[tokio::test]
+    async fn test_resolves_node_identity_of_target_epoch() {
+        let vote_pubkey = Pubkey::new_unique();
+        let (old_identity, new_identity) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let chain = FakeChainData::new(701).with_validator(&vote_pubkey, &new_identity);
+        let epoch_schedule = chain.epoch_schedule().clone();
+        let first = epoch_schedule.get_first_slot_in_epoch(700);
+        // The validator switched to its new identity after epoch 700.
+        let chain = chain
+            .with_vote(first + 1, &Pubkey::new_unique(), &Pubkey::new_unique())
+            .with_vote(first + 2, &old_identity, &vote_pubkey)
+            .with_leader_slots(&old_identity, 700, vec![3])
+            .with_block(first + 3, &old_identity, 1_000);
+        assert_eq!(
+            resolve_node_identity(&chain, &vote_pubkey, &epoch_schedule, 700)
+                .await
+                .unwrap(),
+            old_identity.to_string()
+        );
+        assert_eq!(
+            calculate_block_rewards(&chain, &vote_pubkey, &epoch_schedule, 700, 1, 1, 0)
+                .await
+                .unwrap(),
+            1_000
+        );
+        // Without a vote at the start of the epoch, the current identity is used.
+        assert_eq!(
+            resolve_node_identity(&chain, &vote_pubkey, &epoch_schedule, 699)
+                .await
+                .unwrap(),
+            new_identity.to_string()
+        );
+    }
 
     #[test]
     fn test_partial_bond_stake() {
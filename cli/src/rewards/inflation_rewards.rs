@@ -1,9 +1,12 @@
 use anyhow::{anyhow, Result};
-use log::{error, info};
+use log::info;
 use pye_core_cpi::pye_core::types::RewardCommissions;
-use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_client::rpc_response::RpcInflationReward;
 use solana_sdk::pubkey::Pubkey;
 
+use crate::providers::ChainDataProvider;
+use crate::rewards::or_zero_if_permissive;
+
 /// Computes the excess inflation commission owed to bond holders.
 ///
 /// # Arguments
@@ -21,88 +24,83 @@ pub fn compute_excess_inflation_commission(
     actual_commission - expected_commission
 }
 
-async fn get_excess_inflation_reward(
-    client: &RpcClient,
+fn excess_inflation_reward(
     address: &Pubkey,
-    target_epoch: u64,
+    reward: Option<&RpcInflationReward>,
     reward_commissions: &RewardCommissions,
 ) -> Result<i64> {
-    let inflation_rewards = client
-        .get_inflation_reward(&[*address], Some(target_epoch))
-        .await
-        .map_err(|e| anyhow!("Failed to fetch inflation reward: {}", e))?;
-
-    if inflation_rewards.is_empty() {
-        return Err(anyhow!("No inflation rewards found for {}", address));
-    }
-
-    if let Some(reward) = &inflation_rewards[0] {
-        let commission_rate = u64::from(
-            reward
-                .commission
-                .ok_or_else(|| anyhow!("Commission data missing for {}", address))?,
-        );
-        let excess = compute_excess_inflation_commission(
-            reward.amount,
-            commission_rate,
-            reward_commissions.inflation_bps,
-        );
-        Ok(excess)
-    } else {
+    match reward {
+        Some(reward) => {
+            let commission_rate = u64::from(
+                reward
+                    .commission
+                    .ok_or_else(|| anyhow!("Commission data missing for {}", address))?,
+            );
+            Ok(compute_excess_inflation_commission(
+                reward.amount,
+                commission_rate,
+                reward_commissions.inflation_bps,
+            ))
+        }
         // This is the case for stake accounts that are activating
-        return Ok(0);
+        None => Ok(0),
     }
 }
 
+/// Calculates the excess inflation commission of the bond's stake and transient stake accounts,
+/// whose rewards are fetched in a single request. In permissive mode an account whose reward
+/// cannot be fetched counts as 0.
 pub async fn calculate_excess_inflation_reward(
-    client: &RpcClient,
+    client: &dyn ChainDataProvider,
     stake_pubkey: &Pubkey,
     transient_pubkey: &Pubkey,
     target_epoch: u64,
     reward_commissions: &RewardCommissions,
-) -> i64 {
-    let excess_stake_inflation_commission =
-        match get_excess_inflation_reward(client, stake_pubkey, target_epoch, reward_commissions)
-            .await
-        {
-            Ok(amount) => {
-                info!("Excess Stake Account Inflation Commission: {:?}", amount);
-                amount
-            }
-            Err(e) => {
-                error!("Error for stake account: {}", e);
-                0 // Return 0 for stake account on error.
-            }
-        };
+    permissive: bool,
+) -> Result<i64> {
+    let has_transient = !transient_pubkey.eq(&Pubkey::default());
+    let addresses = if has_transient {
+        vec![*stake_pubkey, *transient_pubkey]
+    } else {
+        vec![*stake_pubkey]
+    };
+    let inflation_rewards = match client.get_inflation_reward(&addresses, target_epoch).await {
+        Ok(rewards) if rewards.len() == addresses.len() => Ok(rewards),
+        Ok(_) => Err(anyhow!("No inflation rewards found for {:?}", addresses)),
+        Err(e) => Err(e),
+    };
+    let inflation_rewards =
+        or_zero_if_permissive(inflation_rewards, "inflation rewards", permissive)?;
+    let reward = |index: usize| inflation_rewards.get(index).and_then(Option::as_ref);
 
-    let excess_transient_inflation_commission = if !transient_pubkey.eq(&Pubkey::default()) {
-        match get_excess_inflation_reward(
-            client,
-            transient_pubkey,
-            target_epoch,
-            reward_commissions,
-        )
-        .await
-        {
-            Ok(amount) => {
-                info!(
-                    "Excess Transient Account Inflation Commission: {:?}\n",
-                    amount
-                );
-                amount
-            }
-            Err(e) => {
-                error!("Error for transient account: {}\n", e);
-                0 // Return 0 for transient account on error
-            }
-        }
+    let excess_stake_inflation_commission = or_zero_if_permissive(
+        excess_inflation_reward(stake_pubkey, reward(0), reward_commissions),
+        "stake account inflation reward",
+        permissive,
+    )?;
+    info!(
+        "Excess Stake Account Inflation Commission: {:?}",
+        excess_stake_inflation_commission
+    );
+
+    let excess_transient_inflation_commission = if has_transient {
+        let amount = or_zero_if_permissive(
+            excess_inflation_reward(transient_pubkey, reward(1), reward_commissions),
+            "transient account inflation reward",
+            permissive,
+        )?;
+        info!(
+            "Excess Transient Account Inflation Commission: {:?}\n",
+            amount
+        );
+        amount
     } else {
         0 // No transient account specified
     };
 
     // Commissions in excess of stated rate taken by validator. If negative,
     // this is the amount of commission owned to validator.
-    excess_stake_inflation_commission + excess_transient_inflation_commission
+    Ok(excess_stake_inflation_commission + excess_transient_inflation_commission)
 }
 
 #[cfg(test)]
@@ -1,10 +1,5 @@
-use std::time::Duration;
-
-use anyhow::{anyhow, Result};
 use pye_core_cpi::pye_core::types::RewardCommissions;
-use reqwest::Client;
 use serde::Deserialize;
-use solana_sdk::pubkey::Pubkey;
 
 #[derive(Clone, Deserialize, Debug)]
 pub struct ValidatorInfo {
@@ -15,11 +10,6 @@ pub struct ValidatorInfo {
     pub active_stake: u64,
 }
 
-#[derive(Deserialize, Debug)]
-pub struct ValidatorsResponse {
-    pub validators: Vec<ValidatorInfo>,
-}
-
 /// Computes the excess MEV commission owed to bond holders.
 ///
 /// # Arguments
@@ -63,86 +53,6 @@ pub fn compute_excess_mev_commission(
     mev_commission_taken - expected_mev_commission
 }
 
-pub async fn fetch_and_filter_mev_data(
-    vote_pubkey: &Pubkey,
-    target_epoch: u64,
-) -> Result<ValidatorInfo> {
-    let response = fetch_mev_with_retry(target_epoch, 12, Duration::from_secs(3600)).await?;
-    filter_mev_data(response, vote_pubkey)
-}
-
-// REVIEW: When does MEV epoch data get uploaded to the API? If operators are waiting for epoch
-// transition, there could be a race condition for MEV epoch data
-pub async fn fetch_mev_data(target_epoch: u64) -> Result<ValidatorsResponse> {
-    let http = Client::new();
-
-    http.post("https://kobe.mainnet.jito.network/api/v1/validators")
-        .header("Content-Type", "application/json")
-        .json(&serde_json::json!({ "epoch": target_epoch }))
-        .send()
-        .await
-        .map_err(|e| anyhow!("Failed to send request: {}", e))?
-        .error_for_status()
-        .map_err(|e| anyhow!("Server returned error status: {}", e))?
-        .json::<ValidatorsResponse>()
-        .await
-        .map_err(|e| anyhow!("Failed to deserialize response: {}", e))
-}
-
-pub async fn fetch_mev_with_retry(
-    target_epoch: u64,
-    max_attempts: u64,
-    duration: Duration,
-) -> Result<ValidatorsResponse> {
-    let mut attempt: u64 = 0;
-    loop {
-        match fetch_mev_data(target_epoch).await {
-            Ok(res) => {
-                // We check the sum of rewards. If it's 0, then we know the Jito API hasn't been properly updated so we should wait
-                let total_mev_rewards = res
-                    .validators
-                    .iter()
-                    .fold(0u64, |accum, x| accum + x.mev_rewards);
-                if total_mev_rewards == 0 {
-                    attempt += 1;
-                    if attempt >= max_attempts {
-                        return Err(anyhow!("jito mev: Max attempts reached"));
-                    } else {
-                        tokio::time::sleep(duration).await;
-                    }
-                } else {
-                    return Ok(res);
-                }
-            }
-            Err(err) => {
-                attempt += 1;
-                if attempt >= max_attempts {
-                    return Err(err.into());
-                } else {
-                    tokio::time::sleep(duration).await;
-                }
-            }
-        }
-    }
-}
-
-fn filter_mev_data(response: ValidatorsResponse, vote_pubkey: &Pubkey) -> Result<ValidatorInfo> {
-    let vote_str = vote_pubkey.to_string();
-    let validator = response
-        .validators
-        .into_iter()
-        .find(|v| v.vote_account == vote_str);
-
-    if let Some(info) = validator {
-        Ok(info.clone())
-    } else {
-        Err(anyhow!(
-            "Validator with vote account {} not found with Jito MEV API. Assuming that validator does not have MEV.\n",
-            vote_str
-        ))
-    }
-}
-
 pub fn calculate_excess_mev_reward(
     mev_data: &ValidatorInfo,
     bond_active_stake: u64,
@@ -1,3 +1,224 @@
+use anyhow::{anyhow, Result};
+use clap::{Parser, ValueEnum};
+use log::{info, warn};
+use pye_core_cpi::pye_core::accounts::SoloValidatorBond;
+use serde::{Deserialize, Serialize};
+use solana_metrics::datapoint_info;
+use solana_sdk::pubkey::Pubkey;
+
+use crate::active_stake::{fetch_bond_active_stake, fetch_validator_active_stake};
+use crate::providers::{ChainDataProvider, MevSourceArgs, ONCHAIN_MEV_SOURCE};
+use block_rewards::compute_excess_block_commission;
+use inflation_rewards::calculate_excess_inflation_reward;
+use mev_rewards::{calculate_excess_mev_reward, ValidatorInfo};
+
 pub mod block_rewards;
 pub mod inflation_rewards;
 pub mod mev_rewards;
+
+/// Excess reward components owed by a validator to a single bond for one epoch.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExcessRewards {
+    pub bond_active_stake: u64,
+    pub inflation: i64,
+    pub mev: i64,
+    pub block: i64,
+}
+
+impl ExcessRewards {
+    pub fn total(&self) -> i64 {
+        self.inflation + self.mev + self.block
+    }
+}
+
+/// Validator active stake that bond shares of MEV and block rewards are pro-rated against.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum StakeDenominator {
+    /// The `active_stake` reported by the MEV data source.
+    #[default]
+    Mev,
+    /// The stake delegated to the vote account during the epoch, reconstructed from stake
+    /// accounts and StakeHistory.
+    Network,
+}
+
+#[derive(Clone, Debug, Parser)]
+pub struct StakeDenominatorArgs {
+    /// Validator active stake that bond shares of MEV and block rewards are pro-rated against:
+    /// `mev` uses the MEV data source's `active_stake`, `network` the stake reconstructed from
+    /// stake accounts and StakeHistory. With `network`, both are reported.
+    #[arg(long, env, value_enum, default_value_t = StakeDenominator::Mev)]
+    pub stake_denominator: StakeDenominator,
+}
+
+impl StakeDenominatorArgs {
+    /// Fails if the MEV data source has no active stake to pro-rate against.
+    pub fn check(&self, mev: &MevSourceArgs) -> Result<()> {
+        if mev.mev_source == ONCHAIN_MEV_SOURCE && self.stake_denominator == StakeDenominator::Mev {
+            return Err(anyhow!(
+                "The onchain MEV source has no active stake: pass --stake-denominator network"
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Divergence of `network_stake` from `mev_stake`, in basis points of `mev_stake`.
+pub fn stake_divergence_bps(mev_stake: u64, network_stake: u64) -> i64 {
+    if mev_stake == 0 {
+        return 0;
+    }
+    ((i128::from(network_stake) - i128::from(mev_stake)) * 10_000 / i128::from(mev_stake)) as i64
+}
+
+/// Returns `mev_data` with the `denominator` active stake for `target_epoch`. With
+/// `StakeDenominator::Network`, the validator's stake is reconstructed from the network, once per
+/// validator and epoch, and reported against the MEV data source's.
+pub async fn resolve_validator_active_stake(
+    client: &dyn ChainDataProvider,
+    vote_pubkey: &Pubkey,
+    mut mev_data: ValidatorInfo,
+    denominator: StakeDenominator,
+    target_epoch: u64,
+    current_epoch: u64,
+) -> Result<ValidatorInfo> {
+    if denominator == StakeDenominator::Mev {
+        return Ok(mev_data);
+    }
+    let network_stake =
+        fetch_validator_active_stake(client, vote_pubkey, target_epoch, current_epoch)
+            .await
+            .map_err(|err| {
+                anyhow!(
+                    "Failed to reconstruct the active stake of {} for epoch {}: {}",
+                    vote_pubkey,
+                    target_epoch,
+                    err
+                )
+            })?;
+    let mev_stake = mev_data.active_stake;
+    let divergence_bps = stake_divergence_bps(mev_stake, network_stake);
+    info!(
+        "Validator {} active stake for epoch {}: MEV data {}, network {} ({} bps), using {:?}",
+        vote_pubkey, target_epoch, mev_stake, network_stake, divergence_bps, denominator
+    );
+    datapoint_info!(
+        "validator_stake_denominator",
+        ("vote_account", vote_pubkey.to_string(), String),
+        ("epoch", target_epoch.to_string(), String),
+        ("mev_active_stake", mev_stake as i64, i64),
+        ("network_active_stake", network_stake as i64, i64),
+        ("divergence", network_stake as i64 - mev_stake as i64, i64),
+        ("divergence_bps", divergence_bps, i64),
+        ("denominator", format!("{:?}", denominator), String),
+    );
+    mev_data.active_stake = network_stake;
+    Ok(mev_data)
+}
+
+/// Resolves a reward component that may have failed to compute. By default the error is
+/// returned so the payout is aborted; in permissive mode it is logged and the component counts as
+/// 0, which can underpay the bond.
+pub fn or_zero_if_permissive<T: Default>(
+    result: Result<T>,
+    component: &str,
+    permissive: bool,
+) -> Result<T> {
+    match result {
+        Ok(value) => Ok(value),
+        Err(e) if permissive => {
+            warn!(
+                "Failed to compute {}: {}. Assuming 0 (permissive mode).",
+                component, e
+            );
+            Ok(T::default())
+        }
+        Err(e) => Err(anyhow!("Failed to compute {}: {}", component, e)),
+    }
+}
+
+/// Calculates the excess rewards owed to a bond for `target_epoch`, given the validator's MEV
+/// data and total block rewards for that epoch.
+pub async fn calculate_bond_excess_rewards(
+    client: &dyn ChainDataProvider,
+    bond: &SoloValidatorBond,
+    mev_data: &ValidatorInfo,
+    validator_block_rewards: u64,
+    target_epoch: u64,
+    current_epoch: u64,
+    permissive: bool,
+) -> Result<ExcessRewards> {
+    // Fetch the SoloValidatorBond's active stake during target epoch.
+    let bond_active_stake = fetch_bond_active_stake(
+        client,
+        &bond.stake_account,
+        &bond.transient_stake_account,
+        target_epoch,
+        current_epoch,
+    )
+    .await?;
+
+    // Calculate the excess inflation reward to be refunded by validator to SoloValidatorBond.
+    let inflation = calculate_excess_inflation_reward(
+        client,
+        &bond.stake_account,
+        &bond.transient_stake_account,
+        target_epoch,
+        &bond.reward_commissions,
+        permissive,
+    )
+    .await?;
+
+    // Calculate the excess MEV reward to be refunded by validator to SoloValidatorBond.
+    let mev = calculate_excess_mev_reward(mev_data, bond_active_stake, &bond.reward_commissions);
+
+    // Calculate the excess block reward to be funded by validator to SoloValidatorBond.
+    let block = compute_excess_block_commission(
+        validator_block_rewards,
+        bond_active_stake,
+        mev_data.active_stake,
+        bond.reward_commissions.block_rewards_bps,
+    );
+
+    Ok(ExcessRewards {
+        bond_active_stake,
+        inflation,
+        mev,
+        block,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::mev_source_args;
+
+    #[test]
+    fn test_onchain_mev_source_needs_network_stake() {
+        let mut mev = mev_source_args();
+        mev.mev_source = ONCHAIN_MEV_SOURCE.to_string();
+        let stake = |stake_denominator| StakeDenominatorArgs { stake_denominator };
+        assert!(stake(StakeDenominator::Mev).check(&mev).is_err());
+        assert!(stake(StakeDenominator::Network).check(&mev).is_ok());
+        mev.mev_source = "./mev/{epoch}.json".to_string();
+        assert!(stake(StakeDenominator::Mev).check(&mev).is_ok());
+    }
+
+    #[test]
+    fn test_or_zero_if_permissive() {
+        assert_eq!(or_zero_if_permissive(Ok(5u64), "x", false).unwrap(), 5);
+        assert!(or_zero_if_permissive::<u64>(Err(anyhow!("rpc down")), "x", false).is_err());
+        assert_eq!(
+            or_zero_if_permissive::<u64>(Err(anyhow!("rpc down")), "x", true).unwrap(),
+            0
+        );
+    }
+
+    #[test]
+    fn test_stake_divergence_bps() {
+        assert_eq!(stake_divergence_bps(1_000, 1_000), 0);
+        assert_eq!(stake_divergence_bps(1_000, 1_010), 100);
+        assert_eq!(stake_divergence_bps(1_000, 900), -1_000);
+        assert_eq!(stake_divergence_bps(0, 900), 0);
+    }
+}
@@ -2,10 +2,14 @@ use std::time::Duration;
 
 use log::{error, info};
 use regex::Regex;
+use serde_json::{json, Value};
 use solana_client::client_error::ClientErrorKind;
 use solana_client::nonblocking::rpc_client::RpcClient;
 use solana_client::rpc_config::RpcBlockConfig;
-use solana_client::{client_error::ClientError, rpc_request::RpcError};
+use solana_client::{
+    client_error::ClientError,
+    rpc_request::{RpcError, RpcResponseErrorData},
+};
 use solana_commitment_config::CommitmentConfig;
 use solana_sdk::epoch_info::EpochInfo;
 use solana_sdk::slot_history;
@@ -33,69 +37,171 @@ pub enum PriorityFeeKeeperError {
     InSlotHistoryNotOnRpc(u64),
 }
 
+/// Timeout of every RPC request, shared by the RpcClients and the batched requests made
+/// alongside them.
+pub const RPC_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Creates an RpcClient for `url`, at confirmed commitment and with [`RPC_TIMEOUT`].
+pub fn new_rpc_client(url: String) -> RpcClient {
+    RpcClient::new_with_timeout_and_commitment(url, RPC_TIMEOUT, CommitmentConfig::confirmed())
+}
+
 // rpc_utils.rs
+fn block_config() -> RpcBlockConfig {
+    RpcBlockConfig {
+        encoding: Some(UiTransactionEncoding::Json),
+        transaction_details: Some(TransactionDetails::None),
+        rewards: Some(true),
+        commitment: Some(CommitmentConfig::finalized()),
+        max_supported_transaction_version: Some(0),
+    }
+}
+
 /// Wrapper on Solana RPC get_block, but propagates skipped blocks as PriorityFeeKeeperError
 pub async fn get_block(
     client: &RpcClient,
     slot: u64,
     slot_history: &SlotHistory,
 ) -> Result<UiConfirmedBlock, PriorityFeeKeeperError> {
-    let block_res = client
-        .get_block_with_config(
-            slot,
-            RpcBlockConfig {
-                encoding: Some(UiTransactionEncoding::Json),
-                transaction_details: Some(TransactionDetails::None),
-                rewards: Some(true),
-                commitment: Some(CommitmentConfig::finalized()),
-                max_supported_transaction_version: Some(0),
-            },
-        )
-        .await;
-    match block_res {
-        Ok(block) => return Ok(block),
+    match client.get_block_with_config(slot, block_config()).await {
+        Ok(block) => Ok(block),
         Err(err) => match err.kind {
-            ClientErrorKind::RpcError(client_rpc_err) => match client_rpc_err {
-                RpcError::RpcResponseError {
-                    code,
-                    message,
-                    data,
-                } => {
-                    // These slot skipped errors come from RpcCustomError::SlotSkipped or
-                    //  RpcCustomError::LongTermStorageSlotSkipped and may not always mean
-                    //  there is no block for a given slot. The additional context are:
-                    //  "...or missing due to ledger jump to recent snapshot"
-                    //  "...or missing in long-term storage"
-                    // Meaning they can arise from RPC issues or lack of history (limit ledger
-                    //  space, no big table) accesible  by an RPC. This is why we check
-                    // SlotHistory and then follow up with redundant RPC checks.
-                    let slot_skipped_regex = Regex::new(r"^Slot [\d]+ was skipped").unwrap();
-                    if slot_skipped_regex.is_match(&message) {
-                        match slot_history.check(slot) {
-                            slot_history::Check::Future => {
-                                return Err(PriorityFeeKeeperError::SlotInFuture(slot));
-                            }
-                            slot_history::Check::NotFound => {
-                                return Err(PriorityFeeKeeperError::SkippedBlock);
-                            }
-                            slot_history::Check::TooOld | slot_history::Check::Found => {
-                                return Err(PriorityFeeKeeperError::InSlotHistoryNotOnRpc(slot));
-                            }
-                        }
-                    }
-                    return Err(PriorityFeeKeeperError::RpcError(
-                        RpcError::RpcResponseError {
-                            code,
-                            message,
-                            data,
-                        },
-                    ));
-                }
-                _ => return Err(PriorityFeeKeeperError::RpcError(client_rpc_err)),
-            },
-            _ => return Err(PriorityFeeKeeperError::SolanaClientError(err)),
+            ClientErrorKind::RpcError(client_rpc_err) => {
+                Err(classify_rpc_error(slot, client_rpc_err, slot_history))
+            }
+            _ => Err(PriorityFeeKeeperError::SolanaClientError(err)),
         },
-    };
+    }
+}
+
+/// Classifies a getBlock RPC error, telling skipped slots apart from blocks the RPC is missing.
+fn classify_rpc_error(
+    slot: u64,
+    err: RpcError,
+    slot_history: &SlotHistory,
+) -> PriorityFeeKeeperError {
+    if let RpcError::RpcResponseError { message, .. } = &err {
+        // These slot skipped errors come from RpcCustomError::SlotSkipped or
+        //  RpcCustomError::LongTermStorageSlotSkipped and may not always mean
+        //  there is no block for a given slot. The additional context are:
+        //  "...or missing due to ledger jump to recent snapshot"
+        //  "...or missing in long-term storage"
+        // Meaning they can arise from RPC issues or lack of history (limit ledger
+        //  space, no big table) accesible  by an RPC. This is why we check
+        // SlotHistory and then follow up with redundant RPC checks.
+        let slot_skipped_regex = Regex::new(r"^Slot [\d]+ was skipped").unwrap();
+        if slot_skipped_regex.is_match(message) {
+            return match slot_history.check(slot) {
+                slot_history::Check::Future => PriorityFeeKeeperError::SlotInFuture(slot),
+                slot_history::Check::NotFound => PriorityFeeKeeperError::SkippedBlock,
+                slot_history::Check::TooOld | slot_history::Check::Found => {
+                    PriorityFeeKeeperError::InSlotHistoryNotOnRpc(slot)
+                }
+            };
+        }
+    }
+    PriorityFeeKeeperError::RpcError(err)
+}
+
+/// Fetches the blocks at `slots` with a single batched JSON-RPC request. Results are returned in
+/// the order of `slots`; each failed call is classified like [`get_block`], and a failure of the
+/// whole request is reported for every slot.
+pub async fn get_blocks_batched(
+    http: &reqwest::Client,
+    url: &str,
+    slots: &[u64],
+    slot_history: &SlotHistory,
+) -> Vec<Result<UiConfirmedBlock, PriorityFeeKeeperError>> {
+    let mut results: Vec<Option<Result<UiConfirmedBlock, PriorityFeeKeeperError>>> =
+        slots.iter().map(|_| None).collect();
+    match send_batch(http, url, slots).await {
+        Ok(responses) => {
+            for response in responses {
+                let Some(id) = response["id"].as_u64().map(|id| id as usize) else {
+                    continue;
+                };
+                let Some(slot) = slots.get(id) else {
+                    continue;
+                };
+                let result = if let Some(error) = response.get("error") {
+                    let err = RpcError::RpcResponseError {
+                        code: error["code"].as_i64().unwrap_or_default(),
+                        message: error["message"].as_str().unwrap_or_default().to_string(),
+                        data: RpcResponseErrorData::Empty,
+                    };
+                    Err(classify_rpc_error(*slot, err, slot_history))
+                } else {
+                    match serde_json::from_value::<Option<UiConfirmedBlock>>(
+                        response["result"].clone(),
+                    ) {
+                        Ok(Some(block)) => Ok(block),
+                        Ok(None) => Err(PriorityFeeKeeperError::RpcError(RpcError::ForUser(
+                            format!("No block for slot {}", slot),
+                        ))),
+                        Err(e) => Err(PriorityFeeKeeperError::RpcError(RpcError::ParseError(
+                            format!("UiConfirmedBlock: {}", e),
+                        ))),
+                    }
+                };
+                results[id] = Some(result);
+            }
+        }
+        Err(message) => {
+            for result in results.iter_mut() {
+                *result = Some(Err(batch_request_error(message.clone())));
+            }
+        }
+    }
+    let mut blocks = Vec::with_capacity(slots.len());
+    for (result, slot) in results.into_iter().zip(slots) {
+        blocks.push(match result {
+            Some(result) => result,
+            None => Err(batch_request_error(format!(
+                "No response for slot {} in batch",
+                slot
+            ))),
+        });
+    }
+    blocks
+}
+
+fn batch_request_error(message: String) -> PriorityFeeKeeperError {
+    PriorityFeeKeeperError::RpcError(RpcError::RpcRequestError(message))
+}
+
+async fn send_batch(
+    http: &reqwest::Client,
+    url: &str,
+    slots: &[u64],
+) -> Result<Vec<Value>, String> {
+    let config = serde_json::to_value(block_config()).map_err(|e| e.to_string())?;
+    let requests: Vec<Value> = slots
+        .iter()
+        .enumerate()
+        .map(|(id, slot)| {
+            json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "method": "getBlock",
+                "params": [slot, config],
+            })
+        })
+        .collect();
+    http.post(url)
+        .json(&requests)
+        .send()
+        .await
+        .map_err(|e| format!("Failed to send batched getBlock request: {}", e))?
+        .error_for_status()
+        .map_err(|e| format!("Batched getBlock request returned error status: {}", e))?
+        .json::<Vec<Value>>()
+        .await
+        .map_err(|e| {
+            format!(
+                "Failed to deserialize batched getBlock response (the RPC may not support batch requests): {}",
+                e
+            )
+        })
 }
 
 pub async fn wait_for_next_epoch(
@@ -127,3 +233,73 @@ pub async fn wait_for_next_epoch(
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// Serves a single HTTP request with `body` and returns the server's URL.
+    async fn serve_once(body: String) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut request = vec![0u8; 64 * 1024];
+            let _ = socket.read(&mut request).await.unwrap();
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            );
+            socket.write_all(response.as_bytes()).await.unwrap();
+        });
+        url
+    }
+
+    #[tokio::test]
+    async fn test_batched_get_block_classifies_each_slot() {
+        let body = json!([
+            {
+                "jsonrpc": "2.0",
+                "id": 1,
+                "result": {
+                    "previousBlockhash": "11111111111111111111111111111111",
+                    "blockhash": "11111111111111111111111111111111",
+                    "parentSlot": 5,
+                    "rewards": [],
+                    "blockTime": null,
+                    "blockHeight": null,
+                },
+            },
+            {
+                "jsonrpc": "2.0",
+                "id": 0,
+                "error": {
+                    "code": -32007,
+                    "message": "Slot 5 was skipped, or missing due to ledger jump to recent snapshot",
+                },
+            },
+        ]);
+        let url = serve_once(body.to_string()).await;
+        let mut slot_history = SlotHistory::default();
+        slot_history.add(6);
+        slot_history.add(10);
+
+        let results =
+            get_blocks_batched(&reqwest::Client::new(), &url, &[5, 6, 7], &slot_history).await;
+        assert!(matches!(
+            results[0],
+            Err(PriorityFeeKeeperError::SkippedBlock)
+        ));
+        assert!(results[1].is_ok());
+        // No response was returned for slot 7.
+        assert!(matches!(
+            results[2],
+            Err(PriorityFeeKeeperError::RpcError(RpcError::RpcRequestError(
+                _
+            )))
+        ));
+    }
+}
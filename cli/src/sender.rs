@@ -0,0 +1,580 @@
+use std::str::FromStr;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use clap::Parser;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_client::rpc_config::RpcSendTransactionConfig;
+use solana_commitment_config::CommitmentConfig;
+use solana_sdk::{hash::Hash, pubkey::Pubkey, signature::Signature, transaction::Transaction};
+use solana_transaction_status_client_types::{TransactionConfirmationStatus, TransactionStatus};
+
+use crate::nonce::parse_nonce_account;
+
+/// Consecutive RPC errors after which the sender gives up on finding out whether a transaction
+/// landed.
+const MAX_CONSECUTIVE_RPC_ERRORS: usize = 10;
+
+/// Rebroadcast and re-sign policy for the transactions the CLI sends.
+#[derive(Parser, Debug, Clone)]
+pub struct SenderArgs {
+    /// Interval (in millis) between re-broadcasts of a transaction that has not been confirmed.
+    #[arg(long, env, default_value = "2000")]
+    pub rebroadcast_interval_ms: u64,
+    /// Number of times a transaction whose blockhash expired without it landing is re-signed
+    /// with a fresh blockhash.
+    #[arg(long, env, default_value = "3")]
+    pub max_resigns: usize,
+    /// Number of re-broadcasts after which the sender stops waiting for a durable nonce
+    /// transaction. It only expires once its nonce is advanced, which never happens if it cannot
+    /// land, e.g. when the payer cannot cover its fee. Its outcome is then left unknown, to be
+    /// checked on a later run.
+    #[arg(long, env, default_value = "150")]
+    pub max_nonce_rebroadcasts: usize,
+}
+
+/// What became of a transaction handed to the sender.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SendOutcome {
+    /// The transaction landed and succeeded.
+    Confirmed { signature: Signature },
+    /// The transaction landed but failed on-chain.
+    Failed { signature: Signature, error: String },
+    /// None of the signed transactions landed, and none can land anymore: it is safe to send
+    /// the payment again.
+    Dropped {
+        signatures: Vec<Signature>,
+        error: String,
+    },
+    /// The sender could not tell whether the last transaction landed. It must be checked before
+    /// the payment is sent again.
+    Unknown {
+        signature: Signature,
+        expiry: TransactionExpiry,
+        error: String,
+    },
+}
+
+/// When a signed transaction can no longer land, so that a payment whose outcome was unknown can
+/// be checked later.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TransactionExpiry {
+    /// Once the finalized block height is past the blockhash's last valid block height.
+    BlockHeight(u64),
+    /// Once the durable nonce account no longer holds the nonce the transaction was signed with.
+    Nonce { account: String, nonce: String },
+}
+
+impl SendOutcome {
+    /// Signature of the transaction that landed, or of the last one sent.
+    pub fn signature(&self) -> Option<&Signature> {
+        match self {
+            SendOutcome::Confirmed { signature }
+            | SendOutcome::Failed { signature, .. }
+            | SendOutcome::Unknown { signature, .. } => Some(signature),
+            SendOutcome::Dropped { signatures, .. } => signatures.last(),
+        }
+    }
+}
+
+/// RPC calls the sender relies on.
+#[async_trait]
+pub trait TransactionRpc: Send + Sync {
+    /// Latest blockhash and the last block height at which it is valid.
+    async fn get_latest_blockhash(&self) -> Result<(Hash, u64)>;
+
+    async fn send_transaction(&self, transaction: &Transaction) -> Result<Signature>;
+
+    /// Status of `signature`, searching the full transaction history.
+    async fn get_signature_status(
+        &self,
+        signature: &Signature,
+    ) -> Result<Option<TransactionStatus>>;
+
+    async fn get_finalized_block_height(&self) -> Result<u64>;
+
+    /// Current (finalized) nonce of a durable nonce account.
+    async fn get_nonce(&self, nonce_account: &Pubkey) -> Result<Hash>;
+}
+
+#[async_trait]
+impl TransactionRpc for RpcClient {
+    async fn get_latest_blockhash(&self) -> Result<(Hash, u64)> {
+        Ok(self
+            .get_latest_blockhash_with_commitment(CommitmentConfig::confirmed())
+            .await?)
+    }
+
+    async fn send_transaction(&self, transaction: &Transaction) -> Result<Signature> {
+        // The sender rebroadcasts itself, so the RPC node should not queue retries.
+        Ok(self
+            .send_transaction_with_config(
+                transaction,
+                RpcSendTransactionConfig {
+                    skip_preflight: true,
+                    max_retries: Some(0),
+                    ..RpcSendTransactionConfig::default()
+                },
+            )
+            .await?)
+    }
+
+    async fn get_signature_status(
+        &self,
+        signature: &Signature,
+    ) -> Result<Option<TransactionStatus>> {
+        let statuses = self
+            .get_signature_statuses_with_history(&[*signature])
+            .await?
+            .value;
+        Ok(statuses.into_iter().next().flatten())
+    }
+
+    async fn get_finalized_block_height(&self) -> Result<u64> {
+        Ok(self
+            .get_block_height_with_commitment(CommitmentConfig::finalized())
+            .await?)
+    }
+
+    async fn get_nonce(&self, nonce_account: &Pubkey) -> Result<Hash> {
+        let account = self
+            .get_account_with_commitment(nonce_account, CommitmentConfig::finalized())
+            .await?
+            .value
+            .ok_or_else(|| anyhow!("Nonce account {} not found", nonce_account))?;
+        Ok(parse_nonce_account(nonce_account, &account)?.blockhash)
+    }
+}
+
+/// Sends the transaction built by `sign` and rebroadcasts it every `rebroadcast_interval_ms`
+/// until it is confirmed or its blockhash expires. An expired transaction is only re-signed with
+/// a fresh blockhash once its signature is provably not landed, i.e. the finalized block height
+/// is past its `last_valid_block_height` and the signature is unknown to the cluster.
+///
+/// With `nonce_account`, `sign` is given the account's current nonce instead of a blockhash, and
+/// the transaction only expires once the nonce has been advanced.
+pub async fn send_with_rebroadcast(
+    rpc: &dyn TransactionRpc,
+    args: &SenderArgs,
+    nonce_account: Option<&Pubkey>,
+    sign: &(dyn Fn(Hash) -> Result<Transaction> + Send + Sync),
+) -> SendOutcome {
+    let rebroadcast_interval = Duration::from_millis(args.rebroadcast_interval_ms);
+    let mut signatures = vec![];
+    for attempt in 0..=args.max_resigns {
+        let latest = match nonce_account {
+            Some(nonce_account) => rpc.get_nonce(nonce_account).await.map(|nonce| (nonce, 0)),
+            None => rpc.get_latest_blockhash().await,
+        };
+        let (blockhash, last_valid_block_height) = match latest {
+            Ok(latest) => latest,
+            Err(e) => {
+                return SendOutcome::Dropped {
+                    signatures,
+                    error: format!("Failed to fetch latest blockhash: {}", e),
+                }
+            }
+        };
+        let transaction = match sign(blockhash) {
+            Ok(transaction) => transaction,
+            Err(e) => {
+                return SendOutcome::Dropped {
+                    signatures,
+                    error: format!("Failed to sign transaction: {}", e),
+                }
+            }
+        };
+        let signature = transaction.signatures[0];
+        signatures.push(signature);
+        let expiry = match nonce_account {
+            Some(nonce_account) => TransactionExpiry::Nonce {
+                account: nonce_account.to_string(),
+                nonce: blockhash.to_string(),
+            },
+            None => TransactionExpiry::BlockHeight(last_valid_block_height),
+        };
+        if attempt > 0 {
+            info!(
+                "Re-signed transaction {} with a fresh blockhash (attempt {})",
+                signature,
+                attempt + 1
+            );
+        }
+
+        let mut consecutive_errors = 0;
+        let mut rebroadcasts = 0;
+        loop {
+            if let Err(e) = rpc.send_transaction(&transaction).await {
+                warn!("Failed to broadcast transaction {}: {}", signature, e);
+            }
+            tokio::time::sleep(rebroadcast_interval).await;
+
+            let expired = match rpc.get_signature_status(&signature).await {
+                Ok(Some(status)) => {
+                    if let Some(outcome) = landed_outcome(&signature, &status) {
+                        return outcome;
+                    }
+                    // Processed but not confirmed yet.
+                    consecutive_errors = 0;
+                    continue;
+                }
+                Ok(None) => is_expired(rpc, &expiry).await,
+                Err(e) => Err(e),
+            };
+            match expired {
+                Ok(false) => {
+                    consecutive_errors = 0;
+                    rebroadcasts += 1;
+                    if nonce_account.is_some() && rebroadcasts >= args.max_nonce_rebroadcasts {
+                        return SendOutcome::Unknown {
+                            signature,
+                            expiry,
+                            error: format!(
+                                "Not confirmed after {} re-broadcasts while its nonce was not advanced",
+                                rebroadcasts
+                            ),
+                        };
+                    }
+                }
+                Ok(true) => {
+                    // Check once more to rule out a landing between the two calls.
+                    match rpc.get_signature_status(&signature).await {
+                        Ok(None) => break,
+                        Ok(Some(status)) => {
+                            if let Some(outcome) = landed_outcome(&signature, &status) {
+                                return outcome;
+                            }
+                        }
+                        Err(e) => {
+                            return SendOutcome::Unknown {
+                                signature,
+                                expiry,
+                                error: e.to_string(),
+                            }
+                        }
+                    }
+                }
+                Err(e) => {
+                    consecutive_errors += 1;
+                    warn!("Failed to check transaction {}: {}", signature, e);
+                    if consecutive_errors >= MAX_CONSECUTIVE_RPC_ERRORS {
+                        return SendOutcome::Unknown {
+                            signature,
+                            expiry,
+                            error: e.to_string(),
+                        };
+                    }
+                }
+            }
+        }
+        warn!("Transaction {} expired without landing", signature);
+    }
+    SendOutcome::Dropped {
+        error: format!(
+            "Transaction did not land after {} attempts",
+            signatures.len()
+        ),
+        signatures,
+    }
+}
+
+/// Finds out what became of a transaction whose outcome was unknown when it was sent. Returns
+/// `None` while it may still land.
+pub async fn check_outcome(
+    rpc: &dyn TransactionRpc,
+    signature: &Signature,
+    expiry: &TransactionExpiry,
+) -> Result<Option<SendOutcome>> {
+    if let Some(status) = rpc.get_signature_status(signature).await? {
+        return Ok(landed_outcome(signature, &status));
+    }
+    if !is_expired(rpc, expiry).await? {
+        return Ok(None);
+    }
+    // Check once more to rule out a landing between the two calls.
+    Ok(match rpc.get_signature_status(signature).await? {
+        Some(status) => landed_outcome(signature, &status),
+        None => Some(SendOutcome::Dropped {
+            signatures: vec![*signature],
+            error: "Transaction expired without landing".to_string(),
+        }),
+    })
+}
+
+/// Whether a transaction with `expiry` can no longer land.
+pub async fn is_expired(rpc: &dyn TransactionRpc, expiry: &TransactionExpiry) -> Result<bool> {
+    match expiry {
+        TransactionExpiry::BlockHeight(last_valid_block_height) => {
+            Ok(rpc.get_finalized_block_height().await? > *last_valid_block_height)
+        }
+        TransactionExpiry::Nonce { account, nonce } => {
+            let account = Pubkey::from_str(account)
+                .map_err(|e| anyhow!("Invalid nonce account {}: {}", account, e))?;
+            Ok(rpc.get_nonce(&account).await?.to_string() != *nonce)
+        }
+    }
+}
+
+/// Outcome of a transaction that is known to the cluster, unless it is only processed so far.
+fn landed_outcome(signature: &Signature, status: &TransactionStatus) -> Option<SendOutcome> {
+    if let Some(error) = &status.err {
+        return Some(SendOutcome::Failed {
+            signature: *signature,
+            error: error.to_string(),
+        });
+    }
+    let confirmed = match &status.confirmation_status {
+        Some(TransactionConfirmationStatus::Processed) => false,
+        Some(_) => true,
+        // Nodes that don't report a confirmation status leave `confirmations` empty once rooted.
+        None => status.confirmations.is_none(),
+    };
+    confirmed.then_some(SendOutcome::Confirmed {
+        signature: *signature,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+
+    use solana_sdk::{
+        message::Message, signature::Keypair, signer::Signer, system_instruction::transfer,
+        transaction::TransactionError,
+    };
+
+    use super::*;
+
+    /// Cluster whose block height advances by one on every height query, and where a
+    /// transaction lands only if its blockhash is in `landing_blockhashes`.
+    struct FakeTransactionRpc {
+        landing_blockhashes: HashMap<Hash, Option<TransactionError>>,
+        state: Mutex<FakeState>,
+    }
+
+    #[derive(Default)]
+    struct FakeState {
+        block_height: u64,
+        blockhashes_issued: u64,
+        nonce_queries: u64,
+        landed: HashMap<Signature, Option<TransactionError>>,
+    }
+
+    const BLOCKHASH_LIFETIME: u64 = 3;
+
+    fn blockhash(index: u64) -> Hash {
+        Hash::new_from_array([index as u8 + 1; 32])
+    }
+
+    #[async_trait]
+    impl TransactionRpc for FakeTransactionRpc {
+        async fn get_latest_blockhash(&self) -> Result<(Hash, u64)> {
+            let mut state = self.state.lock().unwrap();
+            let hash = blockhash(state.blockhashes_issued);
+            state.blockhashes_issued += 1;
+            Ok((hash, state.block_height + BLOCKHASH_LIFETIME))
+        }
+
+        async fn send_transaction(&self, transaction: &Transaction) -> Result<Signature> {
+            let signature = transaction.signatures[0];
+            if let Some(err) = self
+                .landing_blockhashes
+                .get(&transaction.message.recent_blockhash)
+            {
+                self.state
+                    .lock()
+                    .unwrap()
+                    .landed
+                    .insert(signature, err.clone());
+            }
+            Ok(signature)
+        }
+
+        async fn get_signature_status(
+            &self,
+            signature: &Signature,
+        ) -> Result<Option<TransactionStatus>> {
+            Ok(self
+                .state
+                .lock()
+                .unwrap()
+                .landed
+                .get(signature)
+                .map(|err| TransactionStatus {
+                    slot: 1,
+                    confirmations: Some(1),
+                    status: err.clone().map_or(Ok(()), Err),
+                    err: err.clone(),
+                    confirmation_status: Some(TransactionConfirmationStatus::Confirmed),
+                }))
+        }
+
+        async fn get_finalized_block_height(&self) -> Result<u64> {
+            let mut state = self.state.lock().unwrap();
+            state.block_height += 1;
+            Ok(state.block_height)
+        }
+
+        /// The nonce is advanced by someone else after `NONCE_ADVANCED_AFTER` queries.
+        async fn get_nonce(&self, _nonce_account: &Pubkey) -> Result<Hash> {
+            let mut state = self.state.lock().unwrap();
+            state.nonce_queries += 1;
+            Ok(blockhash(
+                100 + (state.nonce_queries > NONCE_ADVANCED_AFTER) as u64,
+            ))
+        }
+    }
+
+    const NONCE_ADVANCED_AFTER: u64 = 2 * BLOCKHASH_LIFETIME;
+
+    async fn send(
+        rpc: &FakeTransactionRpc,
+        max_resigns: usize,
+        nonce_account: Option<&Pubkey>,
+    ) -> (SendOutcome, Vec<Signature>) {
+        let payer = Keypair::new();
+        let signed = Mutex::new(vec![]);
+        let sign = |blockhash: Hash| {
+            let ix = transfer(&payer.pubkey(), &Pubkey::new_unique(), 1);
+            let message = Message::new(&[ix], Some(&payer.pubkey()));
+            let tx = Transaction::new(&[&payer], message, blockhash);
+            signed.lock().unwrap().push(tx.signatures[0]);
+            Ok(tx)
+        };
+        let args = SenderArgs {
+            rebroadcast_interval_ms: 0,
+            max_resigns,
+            max_nonce_rebroadcasts: 100,
+        };
+        let outcome = send_with_rebroadcast(rpc, &args, nonce_account, &sign).await;
+        (outcome, signed.into_inner().unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_resigns_only_after_expiry_and_confirms() {
+        let rpc = FakeTransactionRpc {
+            landing_blockhashes: HashMap::from([(blockhash(1), None)]),
+            state: Mutex::default(),
+        };
+        let (outcome, signed) = send(&rpc, 3, None).await;
+        assert_eq!(signed.len(), 2);
+        assert_eq!(
+            outcome,
+            SendOutcome::Confirmed {
+                signature: signed[1]
+            }
+        );
+        // The first blockhash was only abandoned once the block height passed its expiry.
+        assert!(rpc.state.lock().unwrap().block_height > BLOCKHASH_LIFETIME);
+    }
+
+    #[tokio::test]
+    async fn test_reports_on_chain_failure_without_resigning() {
+        let rpc = FakeTransactionRpc {
+            landing_blockhashes: HashMap::from([(
+                blockhash(0),
+                Some(TransactionError::InsufficientFundsForFee),
+            )]),
+            state: Mutex::default(),
+        };
+        let (outcome, signed) = send(&rpc, 3, None).await;
+        assert_eq!(signed.len(), 1);
+        assert!(matches!(outcome, SendOutcome::Failed { signature, .. } if signature == signed[0]));
+    }
+
+    #[tokio::test]
+    async fn test_drops_after_max_resigns() {
+        let rpc = FakeTransactionRpc {
+            landing_blockhashes: HashMap::new(),
+            state: Mutex::default(),
+        };
+        let (outcome, signed) = send(&rpc, 2, None).await;
+        assert_eq!(signed.len(), 3);
+        assert!(matches!(outcome, SendOutcome::Dropped { signatures, .. } if signatures == signed));
+    }
+
+    #[tokio::test]
+    async fn test_nonce_transaction_expires_when_nonce_advances() {
+        let rpc = FakeTransactionRpc {
+            landing_blockhashes: HashMap::new(),
+            state: Mutex::default(),
+        };
+        let (outcome, signed) = send(&rpc, 0, Some(&Pubkey::new_unique())).await;
+        assert_eq!(signed.len(), 1);
+        assert!(matches!(outcome, SendOutcome::Dropped { signatures, .. } if signatures == signed));
+        // Rebroadcast well past the lifetime of a blockhash, until the nonce was advanced.
+        let state = rpc.state.lock().unwrap();
+        assert!(state.nonce_queries > NONCE_ADVANCED_AFTER);
+        assert_eq!(state.block_height, 0);
+    }
+
+    #[tokio::test]
+    async fn test_nonce_transaction_is_left_unknown_after_max_rebroadcasts() {
+        let rpc = FakeTransactionRpc {
+            landing_blockhashes: HashMap::new(),
+            state: Mutex::default(),
+        };
+        let nonce_account = Pubkey::new_unique();
+        let payer = Keypair::new();
+        let sign = |blockhash: Hash| {
+            let ix = transfer(&payer.pubkey(), &Pubkey::new_unique(), 1);
+            let message = Message::new(&[ix], Some(&payer.pubkey()));
+            Ok(Transaction::new(&[&payer], message, blockhash))
+        };
+        let args = SenderArgs {
+            rebroadcast_interval_ms: 0,
+            max_resigns: 3,
+            max_nonce_rebroadcasts: 2,
+        };
+        let outcome = send_with_rebroadcast(&rpc, &args, Some(&nonce_account), &sign).await;
+        // Given up on before the nonce was advanced, to be checked later against the nonce.
+        assert!(rpc.state.lock().unwrap().nonce_queries <= NONCE_ADVANCED_AFTER);
+        assert!(matches!(
+            outcome,
+            SendOutcome::Unknown {
+                expiry: TransactionExpiry::Nonce { account, nonce },
+                ..
+            } if account == nonce_account.to_string() && nonce == blockhash(100).to_string()
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_checks_outcome_of_unknown_transaction() {
+        let rpc = FakeTransactionRpc {
+            landing_blockhashes: HashMap::from([(blockhash(0), None)]),
+            state: Mutex::default(),
+        };
+        let (outcome, signed) = send(&rpc, 0, None).await;
+        let landed = check_outcome(&rpc, &signed[0], &TransactionExpiry::BlockHeight(0))
+            .await
+            .unwrap();
+        assert_eq!(landed, Some(outcome));
+
+        // Unknown to the cluster: pending until the blockhash expires, dropped after.
+        let signature = Signature::new_unique();
+        let expiry = TransactionExpiry::BlockHeight(rpc.state.lock().unwrap().block_height + 1);
+        assert_eq!(
+            check_outcome(&rpc, &signature, &expiry).await.unwrap(),
+            None
+        );
+        assert!(matches!(
+            check_outcome(&rpc, &signature, &expiry).await.unwrap(),
+            Some(SendOutcome::Dropped { signatures, .. }) if signatures == vec![signature]
+        ));
+
+        // Unknown to the cluster, with a nonce that was not advanced yet.
+        let expiry = TransactionExpiry::Nonce {
+            account: Pubkey::new_unique().to_string(),
+            nonce: blockhash(100).to_string(),
+        };
+        assert_eq!(
+            check_outcome(&rpc, &signature, &expiry).await.unwrap(),
+            None
+        );
+    }
+}
@@ -0,0 +1,383 @@
+use std::str::FromStr;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use clap::Parser;
+use dialoguer::Password;
+use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION};
+use serde::{Deserialize, Serialize};
+use solana_sdk::{
+    pubkey::Pubkey,
+    signature::Signature,
+    signer::{
+        keypair::{keypair_from_seed_phrase_and_passphrase, read_keypair, read_keypair_file},
+        Signer, SignerError,
+    },
+};
+use tokio::runtime::Handle;
+
+/// A signer resolved from a signer URI.
+pub type BoxedSigner = Box<dyn Signer + Send + Sync>;
+
+/// Where a signer's key comes from, following the Solana CLI's signer URIs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SignerSource {
+    /// A keypair file, given as a path or a `file:` URI.
+    File(String),
+    /// A seed phrase (and optional passphrase) typed in at a prompt: `prompt://`.
+    Prompt,
+    /// A keypair read from standard input: `stdin`.
+    Stdin,
+    /// A signing service reached over HTTP(S).
+    Remote(String),
+}
+
+impl SignerSource {
+    pub fn parse(uri: &str) -> Result<Self> {
+        if uri == "stdin" {
+            return Ok(SignerSource::Stdin);
+        }
+        if uri.starts_with("prompt:") {
+            return Ok(SignerSource::Prompt);
+        }
+        if uri.starts_with("http://") || uri.starts_with("https://") {
+            return Ok(SignerSource::Remote(uri.trim_end_matches('/').to_string()));
+        }
+        if let Some(path) = uri.strip_prefix("file:") {
+            return Ok(SignerSource::File(path.to_string()));
+        }
+        if uri.starts_with("usb:") {
+            return Err(anyhow!("Hardware wallets are not supported: {}", uri));
+        }
+        Ok(SignerSource::File(uri.to_string()))
+    }
+}
+
+/// How the CLI talks to the remote signers given as signer URIs.
+#[derive(Parser, Debug, Clone)]
+pub struct RemoteSignerArgs {
+    /// Timeout (in secs) of a request to a remote signer, signing included. A signer that stops
+    /// answering fails the transaction instead of stalling the payouts.
+    #[arg(long, env, default_value = "30")]
+    pub remote_signer_timeout_secs: u64,
+    /// Timeout (in secs) of connecting to a remote signer.
+    #[arg(long, env, default_value = "5")]
+    pub remote_signer_connect_timeout_secs: u64,
+    /// Value of the `Authorization` header sent to remote signers, e.g. `Bearer <TOKEN>`.
+    /// Without it, requests are unauthenticated and the signer must only be reachable from a
+    /// trusted interface, such as localhost.
+    #[arg(long, env, hide_env_values = true)]
+    pub remote_signer_authorization: Option<String>,
+}
+
+/// Resolves the signer at `uri`. `name` describes the signer at prompts and in errors, e.g.
+/// "payer".
+pub async fn signer_from_uri(
+    uri: &str,
+    name: &str,
+    remote: &RemoteSignerArgs,
+) -> Result<BoxedSigner> {
+    match SignerSource::parse(uri)? {
+        SignerSource::File(path) => {
+            Ok(Box::new(read_keypair_file(&path).map_err(|e| {
+                anyhow!("Failed to read {} keypair from {}: {}", name, path, e)
+            })?))
+        }
+        SignerSource::Prompt => {
+            let seed_phrase = Password::new()
+                .with_prompt(format!("[{}] seed phrase", name))
+                .interact()?;
+            let passphrase = Password::new()
+                .with_prompt(format!(
+                    "[{}] passphrase (leave empty if there is none)",
+                    name
+                ))
+                .allow_empty_password(true)
+                .interact()?;
+            let keypair = keypair_from_seed_phrase_and_passphrase(&seed_phrase, &passphrase)
+                .map_err(|e| anyhow!("Invalid {} seed phrase: {}", name, e))?;
+            println!("[{}] recovered pubkey: {}", name, keypair.pubkey());
+            Ok(Box::new(keypair))
+        }
+        SignerSource::Stdin => Ok(Box::new(
+            read_keypair(&mut std::io::stdin())
+                .map_err(|e| anyhow!("Failed to read {} keypair from stdin: {}", name, e))?,
+        )),
+        SignerSource::Remote(url) => Ok(Box::new(RemoteSigner::connect(&url, remote).await?)),
+    }
+}
+
+/// Resolves the pubkey of the signer at `uri`, which can also be given as a plain pubkey.
+pub async fn pubkey_from_uri(uri: &str, name: &str, remote: &RemoteSignerArgs) -> Result<Pubkey> {
+    if let Ok(pubkey) = Pubkey::from_str(uri) {
+        return Ok(pubkey);
+    }
+    Ok(signer_from_uri(uri, name, remote).await?.pubkey())
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct RemotePubkeyResponse {
+    pub pubkey: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct RemoteSignRequest {
+    /// Base64-encoded message to sign.
+    pub message: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct RemoteSignResponse {
+    /// Base58-encoded signature.
+    pub signature: String,
+}
+
+/// Signs with a key held by a signing service. The service returns its pubkey on
+/// `GET <url>/pubkey` and signs the message posted to `POST <url>/sign`; every signature is
+/// verified before it is used. Requests carry `--remote-signer-authorization`, if set, and time
+/// out after `--remote-signer-timeout-secs`.
+pub struct RemoteSigner {
+    url: String,
+    pubkey: Pubkey,
+    client: reqwest::Client,
+}
+
+impl RemoteSigner {
+    pub async fn connect(url: &str, args: &RemoteSignerArgs) -> Result<Self> {
+        let mut headers = HeaderMap::new();
+        if let Some(authorization) = &args.remote_signer_authorization {
+            let mut value = HeaderValue::from_str(authorization)
+                .map_err(|e| anyhow!("Invalid remote signer authorization: {}", e))?;
+            value.set_sensitive(true);
+            headers.insert(AUTHORIZATION, value);
+        }
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(args.remote_signer_timeout_secs))
+            .connect_timeout(Duration::from_secs(args.remote_signer_connect_timeout_secs))
+            .default_headers(headers)
+            .build()
+            .map_err(|e| anyhow!("Failed to build the remote signer client: {}", e))?;
+        let response: RemotePubkeyResponse = client
+            .get(format!("{}/pubkey", url))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await
+            .map_err(|e| anyhow!("Invalid pubkey response from {}: {}", url, e))?;
+        let pubkey = Pubkey::from_str(&response.pubkey)
+            .map_err(|e| anyhow!("Invalid pubkey from {}: {}", url, e))?;
+        Ok(Self {
+            url: url.to_string(),
+            pubkey,
+            client,
+        })
+    }
+
+    async fn sign(&self, message: &[u8]) -> Result<Signature> {
+        let response: RemoteSignResponse = self
+            .client
+            .post(format!("{}/sign", self.url))
+            .json(&RemoteSignRequest {
+                message: STANDARD.encode(message),
+            })
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        let signature = Signature::from_str(&response.signature)?;
+        if !signature.verify(self.pubkey.as_ref(), message) {
+            return Err(anyhow!(
+                "{} returned a signature that does not match {}",
+                self.url,
+                self.pubkey
+            ));
+        }
+        Ok(signature)
+    }
+}
+
+impl Signer for RemoteSigner {
+    fn try_pubkey(&self) -> Result<Pubkey, SignerError> {
+        Ok(self.pubkey)
+    }
+
+    fn try_sign_message(&self, message: &[u8]) -> Result<Signature, SignerError> {
+        // Signing is synchronous, while the request is made on the multi-threaded runtime.
+        tokio::task::block_in_place(|| Handle::current().block_on(self.sign(message)))
+            .map_err(|e| SignerError::Custom(format!("Remote signer {}: {}", self.url, e)))
+    }
+
+    fn is_interactive(&self) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::remote_signer_args;
+    use solana_sdk::signature::Keypair;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    #[test]
+    fn test_parse_signer_source() {
+        assert_eq!(
+            SignerSource::parse("~/.config/solana/id.json").unwrap(),
+            SignerSource::File("~/.config/solana/id.json".to_string())
+        );
+        assert_eq!(
+            SignerSource::parse("file:payer.json").unwrap(),
+            SignerSource::File("payer.json".to_string())
+        );
+        assert_eq!(
+            SignerSource::parse("prompt://").unwrap(),
+            SignerSource::Prompt
+        );
+        assert_eq!(SignerSource::parse("stdin").unwrap(), SignerSource::Stdin);
+        assert_eq!(
+            SignerSource::parse("https://signer.internal/keys/payer/").unwrap(),
+            SignerSource::Remote("https://signer.internal/keys/payer".to_string())
+        );
+        assert!(SignerSource::parse("usb://ledger").is_err());
+    }
+
+    /// Serves the remote signer API for `keypair` on a local port, one request per connection.
+    /// With `corrupt`, signatures are made with another key. With `authorization`, requests
+    /// without that `Authorization` header are refused.
+    async fn serve_remote_signer(
+        keypair: Keypair,
+        corrupt: bool,
+        authorization: Option<&'static str>,
+    ) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut request = vec![];
+                let mut buf = [0u8; 4096];
+                let (head, body) = loop {
+                    let n = stream.read(&mut buf).await.unwrap();
+                    request.extend_from_slice(&buf[..n]);
+                    let text = String::from_utf8_lossy(&request).to_string();
+                    if let Some((head, body)) = text.split_once("\r\n\r\n") {
+                        let content_length = head
+                            .lines()
+                            .find_map(|line| {
+                                line.to_lowercase()
+                                    .strip_prefix("content-length:")
+                                    .map(|len| len.trim().parse::<usize>().unwrap())
+                            })
+                            .unwrap_or(0);
+                        if body.len() >= content_length {
+                            break (head.to_string(), body.to_string());
+                        }
+                    }
+                };
+                let authorized = authorization.is_none_or(|authorization| {
+                    head.lines().any(|line| {
+                        line.to_lowercase()
+                            .strip_prefix("authorization:")
+                            .is_some_and(|value| value.trim() == authorization.to_lowercase())
+                    })
+                });
+                if !authorized {
+                    stream
+                        .write_all(b"HTTP/1.1 401 Unauthorized\r\nContent-Length: 0\r\nConnection: close\r\n\r\n")
+                        .await
+                        .unwrap();
+                    continue;
+                }
+                let response = if head.starts_with("GET /pubkey") {
+                    serde_json::to_string(&RemotePubkeyResponse {
+                        pubkey: keypair.pubkey().to_string(),
+                    })
+                    .unwrap()
+                } else {
+                    let request: RemoteSignRequest = serde_json::from_str(&body).unwrap();
+                    let message = STANDARD.decode(request.message).unwrap();
+                    let signer = if corrupt {
+                        Keypair::new()
+                    } else {
+                        keypair.insecure_clone()
+                    };
+                    serde_json::to_string(&RemoteSignResponse {
+                        signature: signer.sign_message(&message).to_string(),
+                    })
+                    .unwrap()
+                };
+                let reply = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    response.len(),
+                    response
+                );
+                stream.write_all(reply.as_bytes()).await.unwrap();
+            }
+        });
+        url
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_remote_signer() {
+        let keypair = Keypair::new();
+        let remote = remote_signer_args();
+        let url = serve_remote_signer(keypair.insecure_clone(), false, None).await;
+        let signer = signer_from_uri(&url, "payer", &remote).await.unwrap();
+        assert_eq!(signer.pubkey(), keypair.pubkey());
+        assert_eq!(
+            signer.try_sign_message(b"payout").unwrap(),
+            keypair.sign_message(b"payout")
+        );
+        assert_eq!(
+            pubkey_from_uri(&url, "payer", &remote).await.unwrap(),
+            keypair.pubkey()
+        );
+
+        let url = serve_remote_signer(Keypair::new(), true, None).await;
+        let signer = signer_from_uri(&url, "payer", &remote).await.unwrap();
+        assert!(signer.try_sign_message(b"payout").is_err());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_remote_signer_authorization() {
+        let keypair = Keypair::new();
+        let url = serve_remote_signer(keypair.insecure_clone(), false, Some("Bearer token")).await;
+        assert!(signer_from_uri(&url, "payer", &remote_signer_args())
+            .await
+            .is_err());
+
+        let remote = RemoteSignerArgs {
+            remote_signer_authorization: Some("Bearer token".to_string()),
+            ..remote_signer_args()
+        };
+        let signer = signer_from_uri(&url, "payer", &remote).await.unwrap();
+        assert_eq!(
+            signer.try_sign_message(b"payout").unwrap(),
+            keypair.sign_message(b"payout")
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_remote_signer_timeout() {
+        // Accepts connections but never answers.
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            let mut streams = vec![];
+            loop {
+                streams.push(listener.accept().await.unwrap());
+            }
+        });
+        let remote = RemoteSignerArgs {
+            remote_signer_timeout_secs: 1,
+            ..remote_signer_args()
+        };
+        let started = std::time::Instant::now();
+        assert!(signer_from_uri(&url, "payer", &remote).await.is_err());
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+}
@@ -0,0 +1,124 @@
+//! Scaffolding shared by the tests of several modules.
+
+use std::path::PathBuf;
+
+use pye_core_cpi::pye_core::accounts::SoloValidatorBond;
+use solana_sdk::pubkey::Pubkey;
+
+use crate::multisig::{MultisigArgs, SQUADS_PROGRAM_ID};
+use crate::nonce::NonceArgs;
+use crate::priority_fees::PriorityFeeArgs;
+use crate::providers::fake::{solo_validator_bond, FakeChainData, FakeMevData};
+use crate::providers::tip_distribution::TIP_DISTRIBUTION_PROGRAM_ID;
+use crate::providers::MevSourceArgs;
+use crate::rewards::mev_rewards::ValidatorInfo;
+use crate::sender::SenderArgs;
+use crate::signer::RemoteSignerArgs;
+use crate::transactions::{PayoutMethod, TransactionArgs};
+
+pub const CURRENT_EPOCH: u64 = 801;
+pub const TARGET_EPOCH: u64 = 800;
+
+/// Returns a ledger path in the temp directory, unique to `name` and the test process, with no
+/// file left there by a previous run.
+pub fn temp_ledger_path(name: &str) -> PathBuf {
+    let path =
+        std::env::temp_dir().join(format!("pye-ledger-{}-{}.json", name, std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    path
+}
+
+pub struct Fixture {
+    pub bond_pubkey: Pubkey,
+    pub bond: SoloValidatorBond,
+    pub vote_pubkey: Pubkey,
+    pub chain: FakeChainData,
+    pub mev: FakeMevData,
+}
+
+/// A bond holding 100 SOL of a validator's 1,000 SOL, promised 5% inflation, 5% MEV and 50%
+/// block reward commissions, while the validator took 10%, 10% and 100% in `TARGET_EPOCH`.
+pub fn fixture() -> Fixture {
+    let bond_pubkey = Pubkey::new_unique();
+    let vote_pubkey = Pubkey::new_unique();
+    let node_pubkey = Pubkey::new_unique();
+    let stake_account = Pubkey::new_unique();
+    let bond = solo_validator_bond(vote_pubkey, stake_account, 500, 500, 5_000);
+
+    let chain = FakeChainData::new(CURRENT_EPOCH);
+    let first_slot = chain.epoch_schedule().get_first_slot_in_epoch(TARGET_EPOCH);
+    let last_slot = chain.epoch_schedule().get_last_slot_in_epoch(TARGET_EPOCH);
+    let chain = chain
+        .with_bond(bond_pubkey, &bond)
+        .with_block_time(last_slot, 1_700_000_000)
+        // The stake account has been credited the target epoch's inflation reward since.
+        .with_stake_account(stake_account, &vote_pubkey, 100_045_000_000)
+        .with_inflation_reward(stake_account, TARGET_EPOCH, 45_000_000, 100_045_000_000, 10)
+        .with_validator(&vote_pubkey, &node_pubkey)
+        .with_leader_slots(&node_pubkey, TARGET_EPOCH, vec![0, 1, 2])
+        .with_block(first_slot, &node_pubkey, 1_000_000_000)
+        .with_block(first_slot + 1, &node_pubkey, 1_000_000_000);
+    let mev = FakeMevData::default().with_validator(
+        TARGET_EPOCH,
+        ValidatorInfo {
+            vote_account: vote_pubkey.to_string(),
+            mev_commission_bps: 1_000,
+            mev_rewards: 10_000_000_000,
+            running_jito: true,
+            active_stake: 1_000_000_000_000,
+        },
+    );
+    Fixture {
+        bond_pubkey,
+        bond,
+        vote_pubkey,
+        chain,
+        mev,
+    }
+}
+
+pub fn mev_source_args() -> MevSourceArgs {
+    MevSourceArgs {
+        mev_source: String::new(),
+        mev_cross_check: None,
+        tip_distribution_program_id: TIP_DISTRIBUTION_PROGRAM_ID,
+        allow_missing_tip_distribution_account: false,
+    }
+}
+
+/// Sends system transfers from the payer, without priority fees, durable nonces or a multisig.
+pub fn transaction_args() -> TransactionArgs {
+    TransactionArgs {
+        payout_method: PayoutMethod::SystemTransfer,
+        priority_fees: PriorityFeeArgs {
+            compute_unit_limit: None,
+            compute_unit_price: 0,
+            priority_fee_percentile: None,
+            max_compute_unit_price: 1_000_000,
+        },
+        sender: SenderArgs {
+            rebroadcast_interval_ms: 0,
+            max_resigns: 0,
+            max_nonce_rebroadcasts: 100,
+        },
+        nonce: NonceArgs {
+            nonce_account: vec![],
+            nonce_authority: None,
+        },
+        multisig: MultisigArgs {
+            multisig: None,
+            vault_index: 0,
+            approve_proposals: false,
+            squads_program_id: SQUADS_PROGRAM_ID,
+        },
+        remote_signer: remote_signer_args(),
+    }
+}
+
+pub fn remote_signer_args() -> RemoteSignerArgs {
+    RemoteSignerArgs {
+        remote_signer_timeout_secs: 5,
+        remote_signer_connect_timeout_secs: 5,
+        remote_signer_authorization: None,
+    }
+}
@@ -1,63 +1,875 @@
+use crate::multisig::MultisigArgs;
+use crate::nonce::{Nonce, NonceArgs};
+use crate::priority_fees::{ComputeBudget, PriorityFeeArgs};
+use crate::sender::{send_with_rebroadcast, SendOutcome, SenderArgs};
+use crate::signer::{signer_from_uri, BoxedSigner, RemoteSignerArgs};
 use anchor_client::{Client, Cluster};
+use anchor_lang::{system_program, InstructionData, ToAccountMetas};
 use anyhow::{anyhow, Result};
+use clap::{Parser, ValueEnum};
+use log::info;
 use pye_core_cpi::pye_core::accounts::SoloValidatorBond;
+use pye_core_cpi::pye_core::client::{accounts as pye_accounts, args as pye_args};
+use pye_core_cpi::pye_core::types::NativeTransferArgs;
 use pye_core_cpi::pye_core::ID as PYE_BONDS_ID;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_client::rpc_config::RpcSimulateTransactionConfig;
+use solana_metrics::datapoint_error;
+use solana_sdk::instruction::Instruction;
 use solana_sdk::message::Message;
-use solana_sdk::signer::keypair::read_keypair_file;
+use solana_sdk::packet::PACKET_DATA_SIZE;
+use solana_sdk::pubkey;
+use solana_sdk::signature::SIGNATURE_BYTES;
 use solana_sdk::signer::Signer;
-use solana_sdk::system_instruction::transfer;
+use solana_sdk::system_instruction::{transfer, SystemInstruction};
 use solana_sdk::transaction::Transaction;
-use solana_sdk::{commitment_config::CommitmentConfig, pubkey::Pubkey};
+use solana_sdk::{commitment_config::CommitmentConfig, compute_budget, pubkey::Pubkey, sysvar};
+use std::collections::HashMap;
 use std::sync::Arc;
+use tokio::sync::Mutex;
 
-pub async fn transfer_excess_rewards(
-    payer_file_path: String,
-    cluster: Cluster,
-    bond_pubkey: &Pubkey,
-    _bond: &SoloValidatorBond,
-    excess_rewards: u64,
-) -> Result<()> {
-    if excess_rewards == 0 {
-        return Err(anyhow!("No excess rewards to transfer"));
+const STAKE_PROGRAM_ID: Pubkey = pubkey!("Stake11111111111111111111111111111111111111");
+const STAKE_CONFIG_ID: Pubkey = pubkey!("StakeConfig11111111111111111111111111111111");
+
+/// Upper bound on the compute units a single transaction can request.
+const MAX_COMPUTE_UNIT_LIMIT: u32 = 1_400_000;
+
+/// How excess rewards are paid to a bond.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum PayoutMethod {
+    /// System transfer to the bond account.
+    #[default]
+    SystemTransfer,
+    /// The Pye program's `native_transfer` instruction to the bond account.
+    NativeTransfer,
+    /// System transfer straight to the bond's stake account.
+    StakeAccount,
+    /// System transfer to the bond account followed by `solo_validator_delegate_tips`, which
+    /// stakes the lamports to the validator right away.
+    DelegateTips,
+}
+
+impl PayoutMethod {
+    /// Account the payer's lamports are transferred to: the bond's stake account for
+    /// `StakeAccount`, the bond account otherwise.
+    pub fn destination(&self, bond_pubkey: &Pubkey, stake_account: &Pubkey) -> Pubkey {
+        match self {
+            PayoutMethod::StakeAccount => *stake_account,
+            PayoutMethod::SystemTransfer
+            | PayoutMethod::NativeTransfer
+            | PayoutMethod::DelegateTips => *bond_pubkey,
+        }
     }
 
-    let payer = Arc::new(read_keypair_file(&payer_file_path).map_err(|e| {
-        anyhow!(
-            "Failed to read payer keypair from {}: {}",
-            payer_file_path,
-            e
-        )
-    })?);
-    let payer_pubkey = payer.pubkey();
-    println!("Payer: {:?}", payer_pubkey);
+    /// Estimated compute units used by the instructions paying a single bond.
+    fn compute_units_per_transfer(&self) -> u32 {
+        match self {
+            PayoutMethod::SystemTransfer | PayoutMethod::StakeAccount => 150,
+            PayoutMethod::NativeTransfer => 6_000,
+            PayoutMethod::DelegateTips => 60_000,
+        }
+    }
+
+    /// Estimated compute units used by a transaction paying `transfers` bonds, with some headroom.
+    pub fn estimated_compute_units(&self, transfers: usize) -> u32 {
+        (1_000 + self.compute_units_per_transfer() * transfers as u32).min(MAX_COMPUTE_UNIT_LIMIT)
+    }
+}
+
+/// Options shared by every transaction paying excess rewards.
+#[derive(Parser, Debug, Clone)]
+pub struct TransactionArgs {
+    /// How excess rewards are paid: `system-transfer` to the bond account, the Pye program's
+    /// `native-transfer`, a system transfer to the bond's `stake-account`, or `delegate-tips` to
+    /// transfer to the bond account and stake the lamports to the validator in the same
+    /// transaction.
+    #[arg(long, env, value_enum, default_value_t = PayoutMethod::SystemTransfer)]
+    pub payout_method: PayoutMethod,
+    #[command(flatten)]
+    pub priority_fees: PriorityFeeArgs,
+    #[command(flatten)]
+    pub sender: SenderArgs,
+    #[command(flatten)]
+    pub nonce: NonceArgs,
+    #[command(flatten)]
+    pub multisig: MultisigArgs,
+    #[command(flatten)]
+    pub remote_signer: RemoteSignerArgs,
+}
+
+/// Excess rewards owed to a single bond, paid as one transfer of a (possibly batched)
+/// transaction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BondTransfer {
+    pub bond_pubkey: Pubkey,
+    pub validator_vote_account: Pubkey,
+    pub stake_account: Pubkey,
+    pub lamports: u64,
+}
+
+impl BondTransfer {
+    pub fn new(bond_pubkey: &Pubkey, bond: &SoloValidatorBond, lamports: u64) -> Self {
+        Self {
+            bond_pubkey: *bond_pubkey,
+            validator_vote_account: bond.validator_vote_account,
+            stake_account: bond.stake_account,
+            lamports,
+        }
+    }
+}
+
+/// An unsigned payout transaction and the transfers it pays.
+#[derive(Debug, Clone)]
+pub struct PayoutTransaction {
+    pub transfers: Vec<BondTransfer>,
+    pub message: Message,
+    /// Durable nonce the transaction uses instead of a recent blockhash.
+    pub nonce: Option<Nonce>,
+}
+
+/// Payout transactions that were simulated successfully and that the payer can afford, ready to
+/// be sent.
+pub struct PreparedPayouts {
+    signers: PayoutSigners,
+    rpc: RpcClient,
+    transactions: Vec<PayoutTransaction>,
+    /// Held while a transaction using the nonce account is in flight: the nonce changes when a
+    /// transaction using it lands, so they are sent one at a time.
+    nonce_locks: HashMap<Pubkey, Mutex<()>>,
+}
+
+impl PreparedPayouts {
+    pub fn len(&self) -> usize {
+        self.transactions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.transactions.is_empty()
+    }
 
-    let client =
-        Client::new_with_options(cluster, Arc::clone(&payer), CommitmentConfig::processed());
+    /// Transfers paid by the transaction at `index`.
+    pub fn batch(&self, index: usize) -> &[BondTransfer] {
+        &self.transactions[index].transfers
+    }
+
+    /// Signs and sends the transaction at `index`.
+    pub async fn send(&self, index: usize, sender: &SenderArgs) -> SendOutcome {
+        let transaction = &self.transactions[index];
+        let signers = self.signers.signers();
+        let sign = |blockhash| {
+            let mut signed = Transaction::new_unsigned(transaction.message.clone());
+            signed.try_sign(&signers, blockhash)?;
+            Ok(signed)
+        };
+        let nonce_account = transaction.nonce.as_ref().map(|nonce| &nonce.account);
+        let _nonce_lock = match nonce_account {
+            Some(nonce_account) => Some(self.nonce_locks[nonce_account].lock().await),
+            None => None,
+        };
+        let outcome = send_with_rebroadcast(&self.rpc, sender, nonce_account, &sign).await;
+        match &outcome {
+            SendOutcome::Confirmed { signature } => println!("Transaction Sent: {}\n", signature),
+            outcome => println!("Transaction not confirmed: {:?}\n", outcome),
+        }
+        outcome
+    }
+}
 
-    // TODO: check balance and send notification if not enough balance
+/// Signers of the payout transactions, resolved once from their signer URIs.
+#[derive(Clone)]
+pub struct PayoutSigners {
+    pub payer: Arc<BoxedSigner>,
+    /// Signs the nonce advance, unless the payer is the nonce authority.
+    pub nonce_authority: Option<Arc<BoxedSigner>>,
+}
+
+impl PayoutSigners {
+    pub async fn resolve(payer: &str, tx_args: &TransactionArgs) -> Result<Self> {
+        let remote = &tx_args.remote_signer;
+        let payer = Arc::new(signer_from_uri(payer, "payer", remote).await?);
+        println!("Payer: {:?}", payer.pubkey());
+        let nonce_authority = match &tx_args.nonce.nonce_authority {
+            Some(uri) => Some(Arc::new(
+                signer_from_uri(uri, "nonce authority", remote).await?,
+            )),
+            None => None,
+        }
+        .filter(|nonce_authority| nonce_authority.pubkey() != payer.pubkey());
+        Ok(Self {
+            payer,
+            nonce_authority,
+        })
+    }
 
+    pub fn nonce_authority_pubkey(&self) -> Pubkey {
+        self.nonce_authority
+            .as_ref()
+            .unwrap_or(&self.payer)
+            .pubkey()
+    }
+
+    pub fn signers(&self) -> Vec<&(dyn Signer + Send + Sync)> {
+        std::iter::once(&self.payer)
+            .chain(&self.nonce_authority)
+            .map(|signer| signer.as_ref().as_ref())
+            .collect()
+    }
+}
+
+/// Builds one transaction per batch of transfers, checks that the payer can afford all of them
+/// and simulates each one. Nothing is sent, and any failure aborts the whole payout.
+pub async fn prepare_payouts(
+    signers: &PayoutSigners,
+    cluster: Cluster,
+    batches: Vec<Vec<BondTransfer>>,
+    tx_args: &TransactionArgs,
+) -> Result<PreparedPayouts> {
+    let payer_pubkey = signers.payer.pubkey();
+    let client = Client::new_with_options(
+        cluster,
+        Arc::clone(&signers.payer),
+        CommitmentConfig::confirmed(),
+    );
     let program = client.program(PYE_BONDS_ID)?;
-    let (recent_blockhash, _last_valid_block_height) = program
-        .rpc()
-        .get_latest_blockhash_with_commitment(CommitmentConfig::finalized())
+    let rpc = program.rpc();
+
+    let nonces = tx_args
+        .nonce
+        .fetch_nonces(&rpc, Some(&signers.nonce_authority_pubkey()))
+        .await?;
+
+    let transactions =
+        build_payout_transactions(&rpc, &payer_pubkey, batches, tx_args, &nonces).await?;
+    let nonce_locks = nonces
+        .iter()
+        .map(|nonce| (nonce.account, Mutex::new(())))
+        .collect();
+    Ok(PreparedPayouts {
+        signers: signers.clone(),
+        rpc,
+        transactions,
+        nonce_locks,
+    })
+}
+
+/// Builds the unsigned transactions paying each batch of transfers from `payer_pubkey`, checks
+/// that the payer can afford all of them and simulates each one. If `nonces` are given, the
+/// transactions use them in turn instead of a recent blockhash.
+pub async fn build_payout_transactions(
+    rpc: &RpcClient,
+    payer_pubkey: &Pubkey,
+    batches: Vec<Vec<BondTransfer>>,
+    tx_args: &TransactionArgs,
+    nonces: &[Nonce],
+) -> Result<Vec<PayoutTransaction>> {
+    if batches.iter().flatten().any(|t| t.lamports == 0) || batches.iter().any(Vec::is_empty) {
+        return Err(anyhow!("No excess rewards to transfer"));
+    }
+
+    // Unsigned messages are priced by the cluster with a recent blockhash in place of theirs.
+    let pricing_blockhash = rpc
+        .get_latest_blockhash()
         .await
         .map_err(|e| anyhow!("Failed to fetch latest blockhash: {}", e))?;
+    let mut transactions = vec![];
+    let mut fees = 0;
+    for (index, transfers) in batches.into_iter().enumerate() {
+        let payout_ixs = payout_instructions(payer_pubkey, &transfers, tx_args.payout_method);
+        let writable_accounts: Vec<Pubkey> = std::iter::once(*payer_pubkey)
+            .chain(transfers.iter().map(|t| {
+                tx_args
+                    .payout_method
+                    .destination(&t.bond_pubkey, &t.stake_account)
+            }))
+            .collect();
+        let compute_budget = tx_args
+            .priority_fees
+            .compute_budget(
+                rpc,
+                &writable_accounts,
+                tx_args
+                    .payout_method
+                    .estimated_compute_units(transfers.len()),
+            )
+            .await;
+        let instructions = [compute_budget.instructions(), payout_ixs].concat();
+        let nonce = (!nonces.is_empty()).then(|| nonces[index % nonces.len()]);
+        let message = match &nonce {
+            Some(nonce) => {
+                let mut message = Message::new_with_nonce(
+                    instructions,
+                    Some(payer_pubkey),
+                    &nonce.account,
+                    &nonce.authority,
+                );
+                message.recent_blockhash = nonce.blockhash;
+                message
+            }
+            None => Message::new(&instructions, Some(payer_pubkey)),
+        };
+        let mut priced_message = message.clone();
+        priced_message.recent_blockhash = pricing_blockhash;
+        let fee = rpc
+            .get_fee_for_message(&priced_message)
+            .await
+            .map_err(|e| anyhow!("Failed to fetch transaction fee: {}", e))?;
+        info!(
+            "Transaction fee: {} lamports ({} in priority fees)",
+            fee,
+            compute_budget.priority_fee()
+        );
+        fees += fee;
+        transactions.push(PayoutTransaction {
+            transfers,
+            message,
+            nonce,
+        });
+    }
 
-    let mut transfer_ixs = vec![];
+    let balance_check = PayerBalanceCheck {
+        balance: rpc
+            .get_balance(payer_pubkey)
+            .await
+            .map_err(|e| anyhow!("Failed to fetch payer balance: {}", e))?,
+        transfers: transactions
+            .iter()
+            .flat_map(|transaction| &transaction.transfers)
+            .map(|t| t.lamports)
+            .sum(),
+        fees,
+        rent_exempt_minimum: rpc
+            .get_minimum_balance_for_rent_exemption(0)
+            .await
+            .map_err(|e| anyhow!("Failed to fetch rent-exempt minimum: {}", e))?,
+    };
+    if balance_check.shortfall() > 0 {
+        datapoint_error!(
+            "payer_balance_shortfall",
+            ("payer", payer_pubkey.to_string(), String),
+            ("balance", balance_check.balance as i64, i64),
+            ("transfers", balance_check.transfers as i64, i64),
+            ("fees", balance_check.fees as i64, i64),
+            (
+                "rent_exempt_minimum",
+                balance_check.rent_exempt_minimum as i64,
+                i64
+            ),
+            ("shortfall", balance_check.shortfall() as i64, i64),
+        );
+        return Err(anyhow!("Payer {}: {}", payer_pubkey, balance_check));
+    }
 
-    // Transfer excess rewards from payer to stake account.
-    let transfer_ix = transfer(&payer_pubkey, bond_pubkey, excess_rewards);
-    transfer_ixs.push(transfer_ix);
+    for transaction in &transactions {
+        let simulation = rpc
+            .simulate_transaction_with_config(
+                &Transaction::new_unsigned(transaction.message.clone()),
+                RpcSimulateTransactionConfig {
+                    sig_verify: false,
+                    // A durable nonce stands in for the blockhash and must be kept.
+                    replace_recent_blockhash: transaction.nonce.is_none(),
+                    commitment: Some(CommitmentConfig::confirmed()),
+                    ..RpcSimulateTransactionConfig::default()
+                },
+            )
+            .await
+            .map_err(|e| anyhow!("Failed to simulate payout transaction: {}", e))?
+            .value;
+        if let Some(err) = simulation.err {
+            let bonds: Vec<String> = transaction
+                .transfers
+                .iter()
+                .map(|t| t.bond_pubkey.to_string())
+                .collect();
+            datapoint_error!(
+                "payout_simulation_failure",
+                ("payer", payer_pubkey.to_string(), String),
+                ("bonds", bonds.join(","), String),
+                ("error", err.to_string(), String),
+            );
+            return Err(anyhow!(
+                "Simulation of the payout to bonds {} failed: {}\n{}",
+                bonds.join(", "),
+                err,
+                simulation.logs.unwrap_or_default().join("\n")
+            ));
+        }
+    }
 
-    let message = Message::new(&[transfer_ixs].concat(), Some(&payer_pubkey));
+    Ok(transactions)
+}
 
-    let tx = Transaction::new(&[payer], message, recent_blockhash);
-    let sig = program
-        .rpc()
-        .send_and_confirm_transaction_with_spinner(&tx)
-        .await
-        .map_err(|e| anyhow!("Failed to send and confirm transaction: {}", e))?;
-    println!("Transaction Sent: {}\n", sig);
+/// Lamports the payer needs to make every transfer of a payout, pay its fees and stay
+/// rent-exempt.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PayerBalanceCheck {
+    pub balance: u64,
+    pub transfers: u64,
+    pub fees: u64,
+    pub rent_exempt_minimum: u64,
+}
 
-    Ok(())
+impl PayerBalanceCheck {
+    pub fn required(&self) -> u64 {
+        self.transfers + self.fees + self.rent_exempt_minimum
+    }
+
+    pub fn shortfall(&self) -> u64 {
+        self.required().saturating_sub(self.balance)
+    }
+}
+
+impl std::fmt::Display for PayerBalanceCheck {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "balance of {} lamports is short by {} lamports of the {} required ({} in transfers, \
+             {} in fees, {} rent-exempt minimum)",
+            self.balance,
+            self.shortfall(),
+            self.required(),
+            self.transfers,
+            self.fees,
+            self.rent_exempt_minimum
+        )
+    }
+}
+
+/// Instructions paying excess rewards from payer to each bond with `method`.
+pub fn payout_instructions(
+    payer_pubkey: &Pubkey,
+    transfers: &[BondTransfer],
+    method: PayoutMethod,
+) -> Vec<Instruction> {
+    let global_settings = global_settings_address();
+    transfers
+        .iter()
+        .flat_map(|t| match method {
+            PayoutMethod::SystemTransfer => {
+                vec![transfer(payer_pubkey, &t.bond_pubkey, t.lamports)]
+            }
+            PayoutMethod::NativeTransfer => vec![native_transfer_instruction(payer_pubkey, t)],
+            PayoutMethod::StakeAccount => {
+                vec![transfer(payer_pubkey, &t.stake_account, t.lamports)]
+            }
+            PayoutMethod::DelegateTips => vec![
+                transfer(payer_pubkey, &t.bond_pubkey, t.lamports),
+                delegate_tips_instruction(t, &global_settings),
+            ],
+        })
+        .collect()
+}
+
+/// What a payout transaction does, decoded from its message rather than taken on trust.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecodedPayout {
+    pub fee_payer: Pubkey,
+    /// Durable nonce account advanced by the transaction, if any.
+    pub nonce_account: Option<Pubkey>,
+    pub compute_budget: ComputeBudget,
+    /// Destination and lamports of every transfer from the fee payer, in order.
+    pub transfers: Vec<(Pubkey, u64)>,
+}
+
+/// Decodes a payout message built by `build_payout_transactions`, with any payout method.
+/// Fails if the message does anything else: an instruction of another program, a system
+/// instruction other than the nonce advance and transfers from the fee payer, or a
+/// `solo_validator_delegate_tips` for a bond that was not paid before it.
+pub fn decode_payout_message(message: &Message) -> Result<DecodedPayout> {
+    let fee_payer = *message
+        .account_keys
+        .first()
+        .ok_or_else(|| anyhow!("Transaction has no fee payer"))?;
+    let mut decoded = DecodedPayout {
+        fee_payer,
+        nonce_account: None,
+        compute_budget: ComputeBudget::default(),
+        transfers: vec![],
+    };
+    let delegate_tips_data = pye_args::SoloValidatorDelegateTips {}.data();
+    for (index, ix) in message.instructions.iter().enumerate() {
+        let key = |position: usize| -> Result<Pubkey> {
+            ix.accounts
+                .get(position)
+                .and_then(|account| message.account_keys.get(*account as usize))
+                .copied()
+                .ok_or_else(|| anyhow!("Instruction {} is missing account {}", index, position))
+        };
+        let program_id = message
+            .account_keys
+            .get(ix.program_id_index as usize)
+            .ok_or_else(|| anyhow!("Instruction {} has no program", index))?;
+        if *program_id == system_program::ID {
+            match bincode::deserialize::<SystemInstruction>(&ix.data) {
+                Ok(SystemInstruction::AdvanceNonceAccount) if index == 0 => {
+                    decoded.nonce_account = Some(key(0)?);
+                }
+                Ok(SystemInstruction::Transfer { lamports }) if key(0)? == fee_payer => {
+                    decoded.transfers.push((key(1)?, lamports));
+                }
+                _ => {
+                    return Err(anyhow!(
+                        "Instruction {} is not a transfer from the fee payer",
+                        index
+                    ))
+                }
+            }
+        } else if *program_id == compute_budget::id() {
+            match (ix.data.first(), ix.data.get(1..)) {
+                (Some(2), Some(limit)) if limit.len() == 4 => {
+                    decoded.compute_budget.unit_limit =
+                        u32::from_le_bytes(limit.try_into().unwrap());
+                }
+                (Some(3), Some(price)) if price.len() == 8 => {
+                    decoded.compute_budget.unit_price =
+                        u64::from_le_bytes(price.try_into().unwrap());
+                }
+                _ => {
+                    return Err(anyhow!(
+                        "Instruction {} is an unexpected compute budget instruction",
+                        index
+                    ))
+                }
+            }
+        } else if *program_id == PYE_BONDS_ID && ix.data == delegate_tips_data {
+            let bond = key(0)?;
+            if !decoded.transfers.iter().any(|(to, _)| *to == bond) {
+                return Err(anyhow!(
+                    "Instruction {} delegates the tips of bond {}, which is not paid",
+                    index,
+                    bond
+                ));
+            }
+        } else if let Some(lamports) = (*program_id == PYE_BONDS_ID)
+            .then(|| native_transfer_amount(&ix.data))
+            .flatten()
+        {
+            if key(0)? != fee_payer {
+                return Err(anyhow!(
+                    "Instruction {} is not a transfer from the fee payer",
+                    index
+                ));
+            }
+            decoded.transfers.push((key(1)?, lamports));
+        } else {
+            return Err(anyhow!(
+                "Instruction {} of program {} is not part of a payout",
+                index,
+                program_id
+            ));
+        }
+    }
+    Ok(decoded)
+}
+
+fn native_transfer_instruction(payer_pubkey: &Pubkey, t: &BondTransfer) -> Instruction {
+    Instruction {
+        program_id: PYE_BONDS_ID,
+        accounts: pye_accounts::NativeTransfer {
+            from: *payer_pubkey,
+            to: t.bond_pubkey,
+            system_program: system_program::ID,
+        }
+        .to_account_metas(None),
+        data: pye_args::NativeTransfer {
+            args: NativeTransferArgs { amount: t.lamports },
+        }
+        .data(),
+    }
+}
+
+/// Amount of a `native_transfer` instruction with `data`, if it is one.
+fn native_transfer_amount(data: &[u8]) -> Option<u64> {
+    let amount = u64::from_le_bytes(data.get(8..)?.try_into().ok()?);
+    let expected = pye_args::NativeTransfer {
+        args: NativeTransferArgs { amount },
+    }
+    .data();
+    (data == expected.as_slice()).then_some(amount)
+}
+
+fn delegate_tips_instruction(t: &BondTransfer, global_settings: &Pubkey) -> Instruction {
+    Instruction {
+        program_id: PYE_BONDS_ID,
+        accounts: pye_accounts::SoloValidatorDelegateTips {
+            bond: t.bond_pubkey,
+            validator_vote_account: t.validator_vote_account,
+            stake_account: t.stake_account,
+            clock: sysvar::clock::ID,
+            stake_program: STAKE_PROGRAM_ID,
+            stake_history: sysvar::stake_history::ID,
+            stake_config: STAKE_CONFIG_ID,
+            global_settings: *global_settings,
+            rent: sysvar::rent::ID,
+            system_program: system_program::ID,
+        }
+        .to_account_metas(None),
+        data: pye_args::SoloValidatorDelegateTips {}.data(),
+    }
+}
+
+fn global_settings_address() -> Pubkey {
+    Pubkey::find_program_address(&[b"global_settings"], &PYE_BONDS_ID).0
+}
+
+/// Splits `transfers` into batches of at most `max_per_tx` transfers whose transactions, compute
+/// budget instructions (and durable nonce advance, if used) included, fit in a single packet and
+/// the compute unit limit when paid with `method`. Order is preserved.
+pub fn batch_bond_transfers(
+    transfers: Vec<BondTransfer>,
+    max_per_tx: usize,
+    method: PayoutMethod,
+    durable_nonce: bool,
+) -> Vec<Vec<BondTransfer>> {
+    let max_per_tx = max_per_tx
+        .min(((MAX_COMPUTE_UNIT_LIMIT - 1_000) / method.compute_units_per_transfer()) as usize);
+    let mut batches: Vec<Vec<BondTransfer>> = vec![];
+    let mut batch: Vec<BondTransfer> = vec![];
+    for transfer in transfers {
+        batch.push(transfer);
+        if batch.len() > max_per_tx.max(1)
+            || (batch.len() > 1
+                && transaction_size(&batch, method, durable_nonce) > PACKET_DATA_SIZE)
+        {
+            let overflow = batch.pop().unwrap();
+            batches.push(std::mem::replace(&mut batch, vec![overflow]));
+        }
+    }
+    if !batch.is_empty() {
+        batches.push(batch);
+    }
+    batches
+}
+
+/// Serialized size of the signed transaction paying `transfers` with `method`. The size does not
+/// depend on which keys pay or hold the nonce; a nonce authority other than the payer is assumed.
+fn transaction_size(
+    transfers: &[BondTransfer],
+    method: PayoutMethod,
+    durable_nonce: bool,
+) -> usize {
+    let payer_pubkey = Pubkey::default();
+    let instructions = [
+        ComputeBudget::default().instructions(),
+        payout_instructions(&payer_pubkey, transfers, method),
+    ]
+    .concat();
+    let message = if durable_nonce {
+        Message::new_with_nonce(
+            instructions,
+            Some(&payer_pubkey),
+            &Pubkey::new_from_array([1; 32]),
+            &Pubkey::new_from_array([2; 32]),
+        )
+    } else {
+        Message::new(&instructions, Some(&payer_pubkey))
+    };
+    // Compact-u16 signature count (a single byte for a few signers) followed by the signatures.
+    1 + SIGNATURE_BYTES * message.header.num_required_signatures as usize
+        + message.serialize().len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transfers(count: usize) -> Vec<BondTransfer> {
+        (0..count)
+            .map(|i| BondTransfer {
+                bond_pubkey: Pubkey::new_unique(),
+                validator_vote_account: Pubkey::new_unique(),
+                stake_account: Pubkey::new_unique(),
+                lamports: 1_000 + i as u64,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_batch_bond_transfers_respects_max_per_tx() {
+        let transfers = transfers(7);
+        let batches =
+            batch_bond_transfers(transfers.clone(), 3, PayoutMethod::SystemTransfer, false);
+        assert_eq!(
+            batches.iter().map(Vec::len).collect::<Vec<_>>(),
+            vec![3, 3, 1]
+        );
+        assert_eq!(batches.concat(), transfers);
+    }
+
+    #[test]
+    fn test_batch_bond_transfers_fits_in_packet() {
+        for (method, durable_nonce) in PayoutMethod::value_variants()
+            .iter()
+            .flat_map(|method| [(*method, false), (*method, true)])
+        {
+            let transfers = transfers(100);
+            let batches =
+                batch_bond_transfers(transfers.clone(), usize::MAX, method, durable_nonce);
+            assert!(batches.len() > 1);
+            for batch in &batches {
+                assert!(transaction_size(batch, method, durable_nonce) <= PACKET_DATA_SIZE);
+            }
+            // Every batch but the last is as full as the packet allows.
+            for pair in batches.windows(2) {
+                let mut grown = pair[0].clone();
+                grown.push(pair[1][0]);
+                assert!(transaction_size(&grown, method, durable_nonce) > PACKET_DATA_SIZE);
+            }
+            assert_eq!(batches.concat(), transfers);
+        }
+    }
+
+    #[test]
+    fn test_payer_balance_check() {
+        let check = PayerBalanceCheck {
+            balance: 1_000_000_000,
+            transfers: 999_000_000,
+            fees: 10_000,
+            rent_exempt_minimum: 890_880,
+        };
+        assert_eq!(check.required(), 999_900_880);
+        assert_eq!(check.shortfall(), 0);
+
+        let check = PayerBalanceCheck {
+            balance: 999_500_000,
+            ..check
+        };
+        assert_eq!(check.shortfall(), 400_880);
+        assert_eq!(
+            check.to_string(),
+            "balance of 999500000 lamports is short by 400880 lamports of the 999900880 required \
+             (999000000 in transfers, 10000 in fees, 890880 rent-exempt minimum)"
+        );
+    }
+
+    #[test]
+    fn test_payout_instructions() {
+        let payer = Pubkey::new_unique();
+        let transfers = transfers(2);
+
+        let ixs = payout_instructions(&payer, &transfers, PayoutMethod::StakeAccount);
+        assert_eq!(
+            ixs,
+            vec![
+                transfer(&payer, &transfers[0].stake_account, 1_000),
+                transfer(&payer, &transfers[1].stake_account, 1_001),
+            ]
+        );
+
+        let ixs = payout_instructions(&payer, &transfers, PayoutMethod::NativeTransfer);
+        assert_eq!(ixs.len(), 2);
+        assert_eq!(ixs[0].program_id, PYE_BONDS_ID);
+        assert_eq!(ixs[0].accounts[1].pubkey, transfers[0].bond_pubkey);
+        assert_eq!(&ixs[0].data[8..], &1_000u64.to_le_bytes());
+
+        let ixs = payout_instructions(&payer, &transfers, PayoutMethod::DelegateTips);
+        assert_eq!(ixs.len(), 4);
+        assert_eq!(ixs[0], transfer(&payer, &transfers[0].bond_pubkey, 1_000));
+        assert_eq!(ixs[1].program_id, PYE_BONDS_ID);
+        assert_eq!(
+            ixs[1]
+                .accounts
+                .iter()
+                .take(3)
+                .map(|meta| meta.pubkey)
+                .collect::<Vec<_>>(),
+            vec![
+                transfers[0].bond_pubkey,
+                transfers[0].validator_vote_account,
+                transfers[0].stake_account
+            ]
+        );
+
+        // Every method pays the payer's lamports to its destination.
+        for method in PayoutMethod::value_variants() {
+            let destination =
+                method.destination(&transfers[0].bond_pubkey, &transfers[0].stake_account);
+            let ixs = payout_instructions(&payer, &transfers[..1], *method);
+            assert!(ixs[0]
+                .accounts
+                .iter()
+                .any(|meta| meta.pubkey == destination && meta.is_writable));
+        }
+    }
+
+    #[test]
+    fn test_decode_payout_message() {
+        let payer = Pubkey::new_unique();
+        let (nonce_account, nonce_authority) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let transfers = transfers(2);
+        let budget = ComputeBudget {
+            unit_limit: 10_000,
+            unit_price: 25_000,
+        };
+        for method in PayoutMethod::value_variants() {
+            let instructions = [
+                budget.instructions(),
+                payout_instructions(&payer, &transfers, *method),
+            ]
+            .concat();
+            let message = Message::new_with_nonce(
+                instructions,
+                Some(&payer),
+                &nonce_account,
+                &nonce_authority,
+            );
+            assert_eq!(
+                decode_payout_message(&message).unwrap(),
+                DecodedPayout {
+                    fee_payer: payer,
+                    nonce_account: Some(nonce_account),
+                    compute_budget: budget,
+                    transfers: transfers
+                        .iter()
+                        .map(|t| (
+                            method.destination(&t.bond_pubkey, &t.stake_account),
+                            t.lamports
+                        ))
+                        .collect(),
+                }
+            );
+        }
+
+        let payout_ixs = payout_instructions(&payer, &transfers, PayoutMethod::SystemTransfer);
+        // A transfer from an account other than the fee payer, e.g. the nonce authority.
+        let from_other = [
+            payout_ixs.clone(),
+            vec![transfer(&nonce_authority, &Pubkey::new_unique(), 1)],
+        ]
+        .concat();
+        // Any other system instruction.
+        let assign = [
+            payout_ixs.clone(),
+            vec![solana_sdk::system_instruction::assign(
+                &payer,
+                &PYE_BONDS_ID,
+            )],
+        ]
+        .concat();
+        // Tips delegated for a bond that is not paid.
+        let delegate_unpaid = [
+            payout_ixs.clone(),
+            vec![delegate_tips_instruction(
+                &BondTransfer {
+                    bond_pubkey: Pubkey::new_unique(),
+                    ..transfers[0]
+                },
+                &global_settings_address(),
+            )],
+        ]
+        .concat();
+        // An instruction of another program.
+        let other_program = [
+            payout_ixs,
+            vec![Instruction::new_with_bytes(
+                Pubkey::new_unique(),
+                &[],
+                vec![],
+            )],
+        ]
+        .concat();
+        for instructions in [from_other, assign, delegate_unpaid, other_program] {
+            let message = Message::new(&instructions, Some(&payer));
+            assert!(decode_payout_message(&message).is_err());
+        }
+    }
 }